@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use efield_sim::{simulation::Simulation, utils};
+
+use ultraviolet::Vec2;
+
+// 100k bodies in one plate, with their fields found by a first step
+fn large_plate() -> Simulation {
+    let (bodies, plates) = utils::large_plate(100_000, Vec2::new(-1000.0, -1000.0), Vec2::new(1000.0, 1000.0));
+    let mut simulation = Simulation::with_parameters(bodies, plates, 1.0, 0.56, 4.5e-2, 0.0);
+    simulation.step();
+    simulation
+}
+
+fn position_update(c: &mut Criterion) {
+    let mut simulation = large_plate();
+
+    let mut group = c.benchmark_group("100k bodies");
+    group.sample_size(20);
+    group.bench_function("iterate", |b| b.iter(|| simulation.iterate(0.1)));
+    group.bench_function("step", |b| b.iter(|| simulation.step()));
+    group.finish();
+}
+
+criterion_group!(benches, position_update);
+criterion_main!(benches);
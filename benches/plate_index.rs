@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use efield_sim::{
+    plate::Plate, plate_index::{PlateIndex, PLATE_INDEX_CELL_SIZE}, simulation, utils
+};
+
+use ultraviolet::Vec2;
+
+// 20 x 10 wire segments with gaps between them, and 100 bodies in each
+fn wire_grid() -> (Vec<Plate>, Vec<(Vec2, Vec2)>) {
+    let mut plates = Vec::new();
+    let mut moves = Vec::new();
+    let mut rng = fastrand::Rng::with_seed(0);
+
+    for x in 0..20 {
+        for y in 0..10 {
+            let min = Vec2::new(x as f32 * 50.0, y as f32 * 30.0);
+            let plate = Plate::new(min, min + Vec2::new(40.0, 20.0));
+
+            for body in utils::uniform_rect(100, plate.min, plate.max, -1.0) {
+                let step = Vec2::new(rng.f32() - 0.5, rng.f32() - 0.5) * 4.0;
+                moves.push((body.pos, body.pos + step));
+            }
+            plates.push(plate);
+        }
+    }
+
+    (plates, moves)
+}
+
+fn clip_positions(c: &mut Criterion) {
+    let (plates, moves) = wire_grid();
+
+    // A single cell holds every plate, which is the same as scanning them all
+    let indexes = [
+        ("linear scan", PlateIndex::new(&plates, f32::MAX)),
+        ("grid index", PlateIndex::new(&plates, PLATE_INDEX_CELL_SIZE)),
+    ];
+
+    let mut group = c.benchmark_group("clip_pos 200 plates 20k bodies");
+    for (name, index) in &indexes {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for &(old_pos, new_pos) in &moves {
+                    black_box(simulation::clip_pos(old_pos, new_pos, &plates, index));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, clip_positions);
+criterion_main!(benches);
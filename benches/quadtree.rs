@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use efield_sim::{
+    body::Body, plate::Plate, quadtree::{Quad, Quadtree}, utils
+};
+
+use ultraviolet::Vec2;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const THETAS: [f32; 4] = [0.5, 0.75, 1.0, 1.5];
+
+// uniform_disc seeds its own generator, so every run builds the same scene
+fn build(quadtree: &mut Quadtree, bodies: &[Body]) {
+    quadtree.clear(Quad::new_containing(bodies));
+    for (i, body) in bodies.iter().enumerate() {
+        quadtree.insert(body.pos, body.charge, i);
+    }
+}
+
+fn tree_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quadtree build");
+    for n in SIZES {
+        let bodies = utils::uniform_disc(n);
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &bodies, |b, bodies| {
+            b.iter(|| build(&mut quadtree, bodies))
+        });
+    }
+    group.finish();
+}
+
+fn tree_propagate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quadtree propagate");
+    for n in SIZES {
+        let bodies = utils::uniform_disc(n);
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+        build(&mut quadtree, &bodies);
+
+        group.bench_function(BenchmarkId::from_parameter(n), |b| b.iter(|| quadtree.propagate()));
+    }
+    group.finish();
+}
+
+// The field at every body of a 100k-body tree, which is the force walk of one step
+fn tree_efield(c: &mut Criterion) {
+    let bodies = utils::uniform_disc(100_000);
+    let mut quadtree = Quadtree::new(0.75, 1.0);
+    build(&mut quadtree, &bodies);
+    quadtree.propagate();
+
+    let mut group = c.benchmark_group("quadtree efield 100k");
+    group.sample_size(10);
+    for theta in THETAS {
+        quadtree.set_theta(theta);
+        group.bench_function(BenchmarkId::new("theta", theta), |b| {
+            b.iter(|| {
+                for body in &bodies {
+                    black_box(quadtree.efield(body.pos));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// One plate's field over a 100 x 100 grid around it
+fn plate_efield(c: &mut Criterion) {
+    let plate = Plate::new(Vec2::new(-100.0, -50.0), Vec2::new(100.0, 50.0));
+    let points: Vec<Vec2> = (0..100 * 100)
+        .map(|i| Vec2::new((i % 100) as f32 * 4.0 - 200.0, (i / 100) as f32 * 4.0 - 200.0))
+        .collect();
+
+    let mut group = c.benchmark_group("plate efield 100x100");
+    group.bench_function("f32", |b| {
+        b.iter(|| {
+            for &pos in &points {
+                black_box(plate.efield_at(pos, 0.0));
+            }
+        })
+    });
+    group.bench_function("f64", |b| {
+        b.iter(|| {
+            for &pos in &points {
+                black_box(plate.efield_at_f64(pos, 0.0));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tree_build, tree_propagate, tree_efield, plate_efield);
+criterion_main!(benches);
@@ -1,26 +1,107 @@
+use serde::{Deserialize, Serialize};
 use ultraviolet::Vec2;
 
-use crate::{plate::Plate, simulation};
+use crate::simulation::IntegrationMode;
 
-#[derive(Clone, Copy)]
+pub const ELECTRON_CHARGE: f32 = -1.0;
+pub const ION_MASS: f32 = 1836.0;
+pub const ION_RADIUS: f32 = 2.0;
+pub const FIXED_RADIUS: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Species {
+    Electron,
+    Ion,
+    // Positive carrier with the electron's mass, drifting against the electrons
+    Hole,
+}
+
+impl Species {
+    pub const COUNT: usize = 3;
+
+    pub fn index(self) -> usize {
+        match self {
+            Species::Electron => 0,
+            Species::Ion => 1,
+            Species::Hole => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Body {
+    // Given by the simulation when the body is added, and kept however the bodies are reordered; 0 until then
+    #[serde(skip)]
+    pub id: u64,
     pub pos: Vec2,
+    pub vel: Vec2,
+    pub acc: Vec2,
+    pub prev_acc: Vec2,
     pub efield: Vec2,
+    pub mass: f32,
+    pub charge: f32,
     pub radius: f32,
     pub resist: f32,
+    pub species: Species,
+    // Pinned in place: still a source of field, but never moved
+    pub fixed: bool,
 }
 
 impl Body {
     pub fn new(pos: Vec2, radius: f32) -> Self {
         Self {
+            id: 0,
             pos,
+            vel: Vec2::zero(),
+            acc: Vec2::zero(),
+            prev_acc: Vec2::zero(),
             efield: Vec2::zero(),
+            mass: 1.0,
+            charge: ELECTRON_CHARGE,
             radius,
             resist: 1.0,
+            species: Species::Electron,
+            fixed: false,
         }
     }
 
-    pub fn get_new_pos(&self, dt: f32) -> Vec2 {
-        return self.pos + self.efield * dt * self.resist;
+    pub fn new_fixed(pos: Vec2, charge: f32) -> Self {
+        let mut body = Self::new(pos, FIXED_RADIUS);
+        body.charge = charge;
+        body.fixed = true;
+        return body;
+    }
+
+    pub fn make_ion(&mut self, mass: f32) {
+        self.species = Species::Ion;
+        self.charge = -ELECTRON_CHARGE;
+        self.mass = mass;
+        self.radius = ION_RADIUS;
     }
-}
\ No newline at end of file
+
+    pub fn make_hole(&mut self) {
+        self.species = Species::Hole;
+        self.charge = -ELECTRON_CHARGE;
+    }
+
+    // Pin in place as a fixed charge, keeping its charge
+    pub fn pin(&mut self) {
+        self.fixed = true;
+        self.vel = Vec2::zero();
+        self.acc = Vec2::zero();
+        self.prev_acc = Vec2::zero();
+        self.radius = FIXED_RADIUS;
+    }
+
+    pub fn get_new_vel(&self, dt: f32) -> Vec2 {
+        return (self.vel + self.efield / self.mass * dt) * self.resist;
+    }
+
+    pub fn get_new_pos(&self, dt: f32, mode: IntegrationMode) -> Vec2 {
+        match mode {
+            IntegrationMode::Drift | IntegrationMode::Rk4 => self.pos + self.efield / self.mass * dt * self.resist,
+            IntegrationMode::Inertial => self.pos + self.get_new_vel(dt) * dt,
+            IntegrationMode::Verlet => self.pos + self.vel * dt + self.efield / self.mass * (0.5 * dt * dt),
+        }
+    }
+}
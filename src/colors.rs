@@ -0,0 +1,122 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// Settings file, looked for next to the executable
+const SETTINGS_FILE: &str = "settings.toml";
+
+// Colors the scene is drawn with; resistors shade from a dark version of theirs up to it as resist rises
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub background: [u8; 4],
+    pub normal: [u8; 4],
+    pub battery: [u8; 4],
+    pub resistor: [u8; 4],
+    pub capacitor: [u8; 4],
+    pub diode: [u8; 4],
+    pub sink: [u8; 4],
+    pub emitter: [u8; 4],
+    pub insulator: [u8; 4],
+    pub dielectric: [u8; 4],
+    pub electron: [u8; 4],
+    // Electrons given a positive charge
+    pub positive: [u8; 4],
+    pub ion: [u8; 4],
+    pub hole: [u8; 4],
+    // Selection outlines and the hovered cell
+    pub selection: [u8; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: [0, 0, 0, 255],
+            normal: [50, 50, 50, 255],
+            battery: [30, 100, 30, 255],
+            resistor: [225, 165, 95, 255],
+            capacitor: [40, 60, 140, 255],
+            diode: [110, 40, 110, 255],
+            sink: [15, 15, 15, 255],
+            emitter: [20, 110, 120, 255],
+            insulator: [150, 140, 110, 255],
+            dielectric: [90, 160, 90, 110],
+            electron: [50, 180, 240, 255],
+            positive: [240, 70, 50, 255],
+            ion: [240, 200, 60, 255],
+            hole: [200, 90, 230, 255],
+            selection: [255, 255, 255, 255],
+        }
+    }
+}
+
+// What's kept in the settings file, with the colors under [colors]
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    #[serde(default)]
+    colors: Palette,
+}
+
+impl Palette {
+    // Bright, saturated colors that stay apart on a washed-out projector
+    pub fn high_contrast() -> Self {
+        Self {
+            background: [0, 0, 0, 255],
+            normal: [170, 170, 170, 255],
+            battery: [0, 230, 0, 255],
+            resistor: [255, 150, 0, 255],
+            capacitor: [0, 120, 255, 255],
+            diode: [255, 0, 255, 255],
+            sink: [110, 0, 0, 255],
+            emitter: [0, 255, 255, 255],
+            insulator: [255, 255, 170, 255],
+            dielectric: [120, 255, 120, 150],
+            electron: [255, 255, 255, 255],
+            positive: [255, 40, 40, 255],
+            ion: [255, 220, 0, 255],
+            hole: [190, 120, 255, 255],
+            selection: [255, 255, 0, 255],
+        }
+    }
+
+    // settings.toml next to the executable, or in the working directory if that can't be found
+    pub fn path() -> PathBuf {
+        return match std::env::current_exe() {
+            Ok(exe) => exe.with_file_name(SETTINGS_FILE),
+            Err(_) => PathBuf::from(SETTINGS_FILE),
+        };
+    }
+
+    // Colors missing from the file keep their defaults
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let settings: Settings = toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        return Ok(settings.colors);
+    }
+
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        let text = toml::to_string(&Settings { colors: *self }).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        return fs::write(path, text);
+    }
+
+    // Each labelled color, for the settings window
+    pub fn entries(&mut self) -> [(&'static str, &mut [u8; 4]); 15] {
+        return [
+            ("Background", &mut self.background),
+            ("Wire", &mut self.normal),
+            ("Battery", &mut self.battery),
+            ("Resistor", &mut self.resistor),
+            ("Capacitor", &mut self.capacitor),
+            ("Diode", &mut self.diode),
+            ("Sink", &mut self.sink),
+            ("Emitter", &mut self.emitter),
+            ("Insulator", &mut self.insulator),
+            ("Dielectric", &mut self.dielectric),
+            ("Electrons", &mut self.electron),
+            ("Positive Electrons", &mut self.positive),
+            ("Ions", &mut self.ion),
+            ("Holes", &mut self.hole),
+            ("Selection", &mut self.selection),
+        ];
+    }
+}
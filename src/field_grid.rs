@@ -0,0 +1,263 @@
+use crate::plate::{Plate, PlateType};
+
+use rayon::prelude::*;
+
+use ultraviolet::Vec2;
+
+// Distance the grid extends past the plates on each side
+pub const FIELD_GRID_MARGIN: f32 = 100.0;
+
+// Most grid points along a side; the spacing grows past this
+pub const FIELD_GRID_MAX_POINTS: usize = 1024;
+
+// Every this many cells, the interpolation is checked against the exact field
+const ERROR_SAMPLE_STRIDE: usize = 61;
+
+// Field of all the plates together at qp = 1, sampled on a regular grid and read back with bilinear interpolation
+// A capacitor's stored charge changes every step, so each capacitor also keeps the field of its plate at unit density
+pub struct PlateFieldGrid {
+    pub min: Vec2,
+    pub spacing: f32,
+    pub width: usize,
+    pub height: usize,
+    // Max and RMS relative error against the exact field, measured at a spread of cell centers
+    pub error: (f32, f32),
+    // Grid point (x, y) holds layers fields from (y * width + x) * layers: the summed field, then one per capacitor
+    fields: Vec<Vec2>,
+    layers: usize,
+    // Indices of the capacitor plates, in layer order
+    capacitors: Vec<usize>,
+    // What the grid was built from, to tell when it has gone stale: each plate's bounds, charge density at qp = 1
+    // and whether it is a capacitor
+    sources: Vec<(Vec2, Vec2, f32, bool)>,
+    requested_spacing: f32,
+    precise: bool,
+    epsilon: f32,
+}
+
+// Grid points around a position and their bilinear weights
+pub struct GridSample {
+    index: [usize; 4],
+    weights: [f32; 4],
+}
+
+impl PlateFieldGrid {
+    pub fn new(plates: &[Plate], spacing: f32, precise: bool, epsilon: f32) -> Self {
+        let mut min = Vec2::zero();
+        let mut max = Vec2::zero();
+        if let Some(first) = plates.first() {
+            min = first.min;
+            max = first.max;
+        }
+        for plate in plates {
+            min = min.min_by_component(plate.min);
+            max = max.max_by_component(plate.max);
+        }
+        min -= Vec2::broadcast(FIELD_GRID_MARGIN);
+        max += Vec2::broadcast(FIELD_GRID_MARGIN);
+
+        let size = max - min;
+        let requested_spacing = spacing;
+        let spacing = spacing.max(size.x.max(size.y) / (FIELD_GRID_MAX_POINTS - 1) as f32);
+        let width = (size.x / spacing).ceil() as usize + 1;
+        let height = (size.y / spacing).ceil() as usize + 1;
+
+        let capacitors: Vec<usize> = (0..plates.len()).filter(|&p| plates[p].plate_type == PlateType::Capacitor).collect();
+        let layers = 1 + capacitors.len();
+        let mut fields = vec![Vec2::zero(); width * height * layers];
+        fields.par_chunks_mut(layers).enumerate().for_each(|(i, point)| {
+            let pos = min + Vec2::new((i % width) as f32, (i / width) as f32) * spacing;
+            for plate in plates {
+                point[0] += plate.efield_at_auto(pos, precise, epsilon) * plate.charge_density(1.0, 0.0);
+            }
+            for (field, &p) in point[1..].iter_mut().zip(&capacitors) {
+                *field = plates[p].efield_at_auto(pos, precise, epsilon);
+            }
+        });
+
+        let mut grid = Self {
+            min,
+            spacing,
+            width,
+            height,
+            error: (0.0, 0.0),
+            fields,
+            layers,
+            capacitors,
+            sources: plates.iter().map(source).collect(),
+            requested_spacing,
+            precise,
+            epsilon,
+        };
+        grid.error = grid.measure_error(plates);
+
+        return grid;
+    }
+
+    // Whether the grid was built for these plates and settings
+    pub fn matches(&self, plates: &[Plate], spacing: f32, precise: bool, epsilon: f32) -> bool {
+        return self.requested_spacing == spacing
+            && self.precise == precise
+            && self.epsilon == epsilon
+            && self.sources.len() == plates.len()
+            && self.sources.iter().zip(plates).all(|(&built, plate)| built == source(plate));
+    }
+
+    // Grid points around pos, or None outside the grid
+    pub fn locate(&self, pos: Vec2) -> Option<GridSample> {
+        let t = (pos - self.min) / self.spacing;
+
+        // Also rejects NaN
+        if !(t.x >= 0.0 && t.y >= 0.0) {
+            return None;
+        }
+
+        let (x, y) = (t.x as usize, t.y as usize);
+        if x + 1 >= self.width || y + 1 >= self.height {
+            return None;
+        }
+
+        let (fx, fy) = (t.x - x as f32, t.y - y as f32);
+        let i = y * self.width + x;
+
+        Some(GridSample {
+            index: [i, i + 1, i + self.width, i + self.width + 1],
+            weights: [(1.0 - fx) * (1.0 - fy), fx * (1.0 - fy), (1.0 - fx) * fy, fx * fy],
+        })
+    }
+
+    // Interpolated field of layer l at the sampled position
+    fn layer(&self, sample: &GridSample, l: usize) -> Vec2 {
+        let mut field = Vec2::zero();
+        for k in 0..4 {
+            field += self.fields[sample.index[k] * self.layers + l] * sample.weights[k];
+        }
+
+        field
+    }
+
+    // Interpolated field of all the plates at the sampled position, for the plates the grid was built from
+    pub fn field(&self, sample: &GridSample, plates: &[Plate], qp: f32, qe: f32) -> Vec2 {
+        let mut field = self.layer(sample, 0) * qp;
+        for (k, &p) in self.capacitors.iter().enumerate() {
+            field += self.layer(sample, k + 1) * plates[p].charge_density(0.0, qe);
+        }
+
+        field
+    }
+
+    // Compare the interpolated and exact total field at cell centers, where interpolation is worst
+    fn measure_error(&self, plates: &[Plate]) -> (f32, f32) {
+        let mut max_error = 0.0f32;
+        let mut sum_sq_error = 0.0f32;
+        let mut count = 0;
+
+        let cells = (self.width - 1) * (self.height - 1);
+        for cell in (0..cells).step_by(ERROR_SAMPLE_STRIDE) {
+            let (x, y) = (cell % (self.width - 1), cell / (self.width - 1));
+            let pos = self.min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * self.spacing;
+            let Some(sample) = self.locate(pos) else { continue };
+
+            // Each layer at unit weight
+            let mut exact = Vec2::zero();
+            for plate in plates {
+                exact += plate.efield_at_auto(pos, self.precise, self.epsilon) * plate.charge_density(1.0, 0.0);
+            }
+            for &p in &self.capacitors {
+                exact += plates[p].efield_at_auto(pos, self.precise, self.epsilon);
+            }
+            let interpolated = (0..self.layers).fold(Vec2::zero(), |sum, l| sum + self.layer(&sample, l));
+
+            if exact.mag_sq() > 0.0 {
+                let error = (interpolated - exact).mag() / exact.mag();
+                max_error = max_error.max(error);
+                sum_sq_error += error * error;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return (0.0, 0.0);
+        }
+
+        (max_error, (sum_sq_error / count as f32).sqrt())
+    }
+}
+
+// What a plate contributes to the grid
+fn source(plate: &Plate) -> (Vec2, Vec2, f32, bool) {
+    return (plate.min, plate.max, plate.charge_density(1.0, 0.0), plate.plate_type == PlateType::Capacitor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A battery, a resistor and a capacitor holding some charge
+    fn test_plates() -> Vec<Plate> {
+        let mut battery = Plate::new(Vec2::new(-100.0, -50.0), Vec2::new(100.0, -30.0));
+        battery.make_battery(1.0, 0.0);
+        let mut resistor = Plate::new(Vec2::new(-100.0, 30.0), Vec2::new(100.0, 50.0));
+        resistor.make_resistor(0.5, 0.0);
+        let mut capacitor = Plate::new(Vec2::new(-20.0, -10.0), Vec2::new(20.0, 10.0));
+        capacitor.make_capacitor();
+        capacitor.stored_charge = -30.0;
+        return vec![battery, resistor, capacitor];
+    }
+
+    fn exact_field(plates: &[Plate], pos: Vec2, qp: f32, qe: f32) -> Vec2 {
+        return plates.iter().fold(Vec2::zero(), |sum, plate| sum + plate.efield_at(pos, 0.0) * plate.charge_density(qp, qe));
+    }
+
+    #[test]
+    fn interpolation_matches_exact_field() {
+        let mut plates = test_plates();
+        let grid = PlateFieldGrid::new(&plates, 2.0, false, 0.0);
+        let (qp, qe) = (4.5e-2, 0.56);
+
+        // The capacitor's charge can change after the grid is built
+        plates[2].stored_charge = 50.0;
+
+        let mut rng = fastrand::Rng::with_seed(0);
+        let (mut sum_sq_error, mut count) = (0.0, 0);
+        for _ in 0..2000 {
+            let pos = Vec2::new(rng.f32() * 300.0 - 150.0, rng.f32() * 200.0 - 100.0);
+            let sample = grid.locate(pos).expect("inside the grid");
+            let exact = exact_field(&plates, pos, qp, qe);
+            let error = (grid.field(&sample, &plates, qp, qe) - exact).mag() / exact.mag();
+            sum_sq_error += error * error;
+            count += 1;
+        }
+
+        let rms_error = (sum_sq_error / count as f32).sqrt();
+        assert!(rms_error < 2e-3, "RMS relative error {}", rms_error);
+        assert!(grid.error.1 < 2e-3, "measured RMS relative error {}", grid.error.1);
+    }
+
+    #[test]
+    fn points_outside_the_grid_fall_back() {
+        let grid = PlateFieldGrid::new(&test_plates(), 2.0, false, 0.0);
+        let max = grid.min + Vec2::new((grid.width - 1) as f32, (grid.height - 1) as f32) * grid.spacing;
+        for pos in [grid.min - Vec2::one(), max + Vec2::one(), Vec2::new(f32::NAN, 0.0)] {
+            assert!(grid.locate(pos).is_none(), "{:?} is outside", pos);
+        }
+    }
+
+    #[test]
+    fn grid_goes_stale_when_plates_change() {
+        let mut plates = test_plates();
+        let grid = PlateFieldGrid::new(&plates, 2.0, false, 0.0);
+        assert!(grid.matches(&plates, 2.0, false, 0.0));
+        assert!(!grid.matches(&plates, 4.0, false, 0.0));
+        assert!(!grid.matches(&plates, 2.0, false, 1.0));
+
+        // Stored charge is read at sampling time, but a new density or type needs a new grid
+        plates[2].stored_charge = 10.0;
+        assert!(grid.matches(&plates, 2.0, false, 0.0));
+        plates[0].charge_scale = 2.0;
+        assert!(!grid.matches(&plates, 2.0, false, 0.0));
+        plates[0].charge_scale = 1.0;
+        plates[1].make_capacitor();
+        assert!(!grid.matches(&plates, 2.0, false, 0.0));
+    }
+}
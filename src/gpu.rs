@@ -0,0 +1,176 @@
+use crate::quadtree::Quadtree;
+
+use bytemuck::{Pod, Zeroable};
+
+use ultraviolet::Vec2;
+
+use wgpu::util::DeviceExt;
+
+// Invocations per workgroup, matching the shader
+const WORKGROUP_SIZE: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuParams {
+    period: [f32; 2],
+    t_sq: f32,
+    dipole: u32,
+    body_count: u32,
+    periodic: u32,
+    pad: [u32; 2],
+}
+
+// One quadtree node as the shader reads it
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuNode {
+    pos: [f32; 2],
+    dipole: [f32; 2],
+    charge: f32,
+    size_sq: f32,
+    children: u32,
+    next: u32,
+    bucket: u32,
+    count: u32,
+}
+
+// Compute-shader backend for the body-body field, walking the flattened quadtree on the GPU
+pub struct GpuField {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    pub adapter_name: String,
+}
+
+impl GpuField {
+    // None when there's no adapter that can run compute shaders
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))?;
+
+        if !adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+            return None;
+        }
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("efield"),
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        }, None)).ok()?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("efield"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu_field.wgsl").into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("efield"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        Some(Self {
+            device,
+            queue,
+            pipeline,
+            adapter_name: adapter.get_info().name,
+        })
+    }
+
+    // Field of the tree at each (position, squared softening), or None if the GPU failed
+    pub fn efield(&self, quadtree: &Quadtree, queries: &[(Vec2, f32)]) -> Option<Vec<Vec2>> {
+        let walk = &quadtree.walk;
+        if walk.pos.is_empty() || queries.is_empty() {
+            return Some(vec![Vec2::zero(); queries.len()]);
+        }
+
+        let params = GpuParams {
+            period: quadtree.period.map_or([0.0; 2], |period| [period.x, period.y]),
+            t_sq: quadtree.t_sq,
+            dipole: quadtree.dipole as u32,
+            body_count: queries.len() as u32,
+            periodic: quadtree.period.is_some() as u32,
+            pad: [0; 2],
+        };
+
+        let nodes: Vec<GpuNode> = (0..walk.pos.len()).map(|i| GpuNode {
+            pos: [walk.pos[i].x, walk.pos[i].y],
+            dipole: [walk.dipole[i].x, walk.dipole[i].y],
+            charge: walk.charge[i],
+            size_sq: walk.size_sq[i],
+            children: walk.children[i],
+            next: walk.next[i],
+            bucket: walk.bucket[i],
+            count: walk.count[i],
+        }).collect();
+
+        let entries: Vec<[f32; 4]> = quadtree.entries.iter().map(|entry| [entry.pos.x, entry.pos.y, entry.charge, 0.0]).collect();
+        let bodies: Vec<[f32; 4]> = queries.iter().map(|&(pos, e_sq)| [pos.x, pos.y, e_sq, 0.0]).collect();
+
+        let buffer = |label, contents: &[u8], usage| {
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+        };
+        let params_buffer = buffer("params", bytemuck::bytes_of(&params), wgpu::BufferUsages::UNIFORM);
+        let nodes_buffer = buffer("nodes", bytemuck::cast_slice(&nodes), wgpu::BufferUsages::STORAGE);
+        let entries_buffer = buffer("entries", bytemuck::cast_slice(&entries), wgpu::BufferUsages::STORAGE);
+        let bodies_buffer = buffer("bodies", bytemuck::cast_slice(&bodies), wgpu::BufferUsages::STORAGE);
+
+        let size = (queries.len() * std::mem::size_of::<[f32; 2]>()) as u64;
+        let efield_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("efield"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("efield"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: nodes_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: entries_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: bodies_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: efield_buffer.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("efield") });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("efield") });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(queries.len().div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&efield_buffer, 0, &staging_buffer, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        // Wait for the results to come back
+        let slice = staging_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+
+        let efield = {
+            let data = slice.get_mapped_range();
+            let fields: &[[f32; 2]] = bytemuck::cast_slice(&data);
+            fields.iter().map(|&[x, y]| Vec2::new(x, y)).collect()
+        };
+        staging_buffer.unmap();
+
+        Some(efield)
+    }
+}
@@ -0,0 +1,88 @@
+// Barnes-Hut field walk, one body per invocation, following the same
+// stackless order as Quadtree::efield_with
+
+struct Params {
+    period: vec2<f32>,
+    t_sq: f32,
+    dipole: u32,
+    body_count: u32,
+    periodic: u32,
+    pad: vec2<u32>,
+}
+
+struct Node {
+    pos: vec2<f32>,
+    dipole: vec2<f32>,
+    charge: f32,
+    size_sq: f32,
+    children: u32,
+    next: u32,
+    bucket: u32,
+    count: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+// xy = position, z = charge
+@group(0) @binding(2) var<storage, read> entries: array<vec4<f32>>;
+// xy = position, z = squared softening
+@group(0) @binding(3) var<storage, read> bodies: array<vec4<f32>>;
+@group(0) @binding(4) var<storage, read_write> efield: array<vec2<f32>>;
+
+// Minimum-image offset in a periodic domain
+fn wrap(d: vec2<f32>) -> vec2<f32> {
+    if (params.periodic == 0u) {
+        return d;
+    }
+    return d - params.period * round(d / params.period);
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= params.body_count) {
+        return;
+    }
+
+    let pos = bodies[i].xy;
+    let e_sq = bodies[i].z;
+    var field = vec2<f32>(0.0, 0.0);
+
+    var node = 0u;
+    loop {
+        let n = nodes[node];
+        let d = wrap(pos - n.pos);
+        let d_sq = dot(d, d);
+
+        let far = n.size_sq < d_sq * params.t_sq;
+        if (far || n.children == 0u) {
+            if (far) {
+                let denom = d_sq + e_sq;
+                field += d * (n.charge / denom);
+
+                if (params.dipole != 0u) {
+                    field += (d * (2.0 * dot(n.dipole, d) / denom) - n.dipole) / denom;
+                }
+            } else {
+                // Near leaves are summed body by body
+                for (var k = n.bucket; k < n.bucket + n.count; k = k + 1u) {
+                    let entry = entries[k];
+                    let offset = wrap(pos - entry.xy);
+                    let denom = dot(offset, offset) + e_sq;
+                    if (denom > 0.0) {
+                        field += offset * (entry.z / denom);
+                    }
+                }
+            }
+
+            node = n.next;
+            if (node == 0u) {
+                break;
+            }
+        } else {
+            node = n.children;
+        }
+    }
+
+    efield[i] = field;
+}
@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use quarkstrom::{winit::event::VirtualKeyCode, winit_input_helper::WinitInputHelper};
+
+// Keymap file, looked for next to the executable
+const KEYMAP_FILE: &str = "keymap.toml";
+
+// Everything the keyboard does; Ctrl+C/V/Z/D/A and the mouse buttons keep their modifiers and buttons
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    ToggleSettings,
+    TogglePause,
+    Rewind,
+    HalveGrid,
+    DoubleGrid,
+    ToggleCharges,
+    NegativeCharge,
+    PositiveCharge,
+    ToggleProbe,
+    ToggleRuler,
+    ToggleAnnotations,
+    ToggleStreamlines,
+    Screenshot,
+    Undo,
+    TurnSelection,
+    MirrorSelection,
+    DuplicateSelection,
+    DeleteSelection,
+    SelectAll,
+    EraseBodies,
+    PaintBodies,
+    FluxLoop,
+    FlipBattery,
+    SetNormal,
+    SetBattery,
+    SetResistor,
+    SetDiode,
+    SetSink,
+    SetEmitter,
+    SetInsulator,
+    SetDielectric,
+    SetCapacitor,
+}
+
+impl Action {
+    pub const ALL: [Action; 32] = [
+        Action::ToggleSettings,
+        Action::TogglePause,
+        Action::Rewind,
+        Action::HalveGrid,
+        Action::DoubleGrid,
+        Action::ToggleCharges,
+        Action::NegativeCharge,
+        Action::PositiveCharge,
+        Action::ToggleProbe,
+        Action::ToggleRuler,
+        Action::ToggleAnnotations,
+        Action::ToggleStreamlines,
+        Action::Screenshot,
+        Action::Undo,
+        Action::TurnSelection,
+        Action::MirrorSelection,
+        Action::DuplicateSelection,
+        Action::DeleteSelection,
+        Action::SelectAll,
+        Action::EraseBodies,
+        Action::PaintBodies,
+        Action::FluxLoop,
+        Action::FlipBattery,
+        Action::SetNormal,
+        Action::SetBattery,
+        Action::SetResistor,
+        Action::SetDiode,
+        Action::SetSink,
+        Action::SetEmitter,
+        Action::SetInsulator,
+        Action::SetDielectric,
+        Action::SetCapacitor,
+    ];
+
+    fn default_key(self) -> VirtualKeyCode {
+        return match self {
+            Action::ToggleSettings => VirtualKeyCode::E,
+            Action::TogglePause => VirtualKeyCode::Space,
+            Action::Rewind => VirtualKeyCode::R,
+            Action::HalveGrid => VirtualKeyCode::LBracket,
+            Action::DoubleGrid => VirtualKeyCode::RBracket,
+            Action::ToggleCharges => VirtualKeyCode::Q,
+            Action::NegativeCharge => VirtualKeyCode::Minus,
+            Action::PositiveCharge => VirtualKeyCode::Equals,
+            Action::ToggleProbe => VirtualKeyCode::V,
+            Action::ToggleRuler => VirtualKeyCode::M,
+            Action::ToggleAnnotations => VirtualKeyCode::N,
+            Action::ToggleStreamlines => VirtualKeyCode::S,
+            Action::Screenshot => VirtualKeyCode::F12,
+            Action::Undo => VirtualKeyCode::Z,
+            Action::TurnSelection => VirtualKeyCode::T,
+            Action::MirrorSelection => VirtualKeyCode::H,
+            Action::DuplicateSelection => VirtualKeyCode::D,
+            Action::DeleteSelection => VirtualKeyCode::Back,
+            Action::SelectAll => VirtualKeyCode::A,
+            Action::EraseBodies => VirtualKeyCode::X,
+            Action::PaintBodies => VirtualKeyCode::B,
+            Action::FluxLoop => VirtualKeyCode::L,
+            Action::FlipBattery => VirtualKeyCode::F,
+            Action::SetNormal => VirtualKeyCode::Key1,
+            Action::SetBattery => VirtualKeyCode::Key2,
+            Action::SetResistor => VirtualKeyCode::Key3,
+            Action::SetDiode => VirtualKeyCode::Key4,
+            Action::SetSink => VirtualKeyCode::Key5,
+            Action::SetEmitter => VirtualKeyCode::Key6,
+            Action::SetInsulator => VirtualKeyCode::I,
+            Action::SetDielectric => VirtualKeyCode::Key7,
+            Action::SetCapacitor => VirtualKeyCode::C,
+        };
+    }
+
+    pub fn label(self) -> &'static str {
+        return match self {
+            Action::ToggleSettings => "Open Controls",
+            Action::TogglePause => "Pause",
+            Action::Rewind => "Rewind (hold)",
+            Action::HalveGrid => "Halve Grid",
+            Action::DoubleGrid => "Double Grid",
+            Action::ToggleCharges => "Place Fixed Charges",
+            Action::NegativeCharge => "Negative Charge",
+            Action::PositiveCharge => "Positive Charge",
+            Action::ToggleProbe => "Voltage Probe",
+            Action::ToggleRuler => "Ruler",
+            Action::ToggleAnnotations => "Annotate",
+            Action::ToggleStreamlines => "Seed Streamlines",
+            Action::Screenshot => "Screenshot",
+            Action::Undo => "Undo (Ctrl, Shift to redo)",
+            Action::TurnSelection => "Turn Selection (Shift: right)",
+            Action::MirrorSelection => "Mirror Selection (Shift: vertically)",
+            Action::DuplicateSelection => "Duplicate Selection (Ctrl)",
+            Action::DeleteSelection => "Delete Selection",
+            Action::SelectAll => "Select All (Ctrl)",
+            Action::EraseBodies => "Erase Bodies (hold)",
+            Action::PaintBodies => "Paint Bodies (hold)",
+            Action::FluxLoop => "Flux Loop",
+            Action::FlipBattery => "Flip Battery",
+            Action::SetNormal => "Wire",
+            Action::SetBattery => "Battery",
+            Action::SetResistor => "Resistor",
+            Action::SetDiode => "Diode",
+            Action::SetSink => "Sink",
+            Action::SetEmitter => "Emitter",
+            Action::SetInsulator => "Insulator",
+            Action::SetDielectric => "Dielectric",
+            Action::SetCapacitor => "Capacitor",
+        };
+    }
+
+    // Name used in the keymap file
+    fn name(self) -> String {
+        return format!("{:?}", self);
+    }
+}
+
+// Keys that can be bound, named as in the keymap file by their winit names
+pub const BINDABLE_KEYS: [VirtualKeyCode; 72] = [
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E, VirtualKeyCode::F,
+    VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J, VirtualKeyCode::K, VirtualKeyCode::L,
+    VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O, VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R,
+    VirtualKeyCode::S, VirtualKeyCode::T, VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X,
+    VirtualKeyCode::Y, VirtualKeyCode::Z,
+    VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4, VirtualKeyCode::F5, VirtualKeyCode::F6,
+    VirtualKeyCode::F7, VirtualKeyCode::F8, VirtualKeyCode::F9, VirtualKeyCode::F10, VirtualKeyCode::F11, VirtualKeyCode::F12,
+    VirtualKeyCode::Space, VirtualKeyCode::Back, VirtualKeyCode::Delete, VirtualKeyCode::Tab, VirtualKeyCode::Return,
+    VirtualKeyCode::Insert, VirtualKeyCode::Home, VirtualKeyCode::End, VirtualKeyCode::PageUp, VirtualKeyCode::PageDown,
+    VirtualKeyCode::Up, VirtualKeyCode::Down, VirtualKeyCode::Left, VirtualKeyCode::Right,
+    VirtualKeyCode::Minus, VirtualKeyCode::Equals, VirtualKeyCode::LBracket, VirtualKeyCode::RBracket,
+    VirtualKeyCode::Semicolon, VirtualKeyCode::Apostrophe, VirtualKeyCode::Comma, VirtualKeyCode::Period,
+    VirtualKeyCode::Slash, VirtualKeyCode::Backslash,
+];
+
+pub fn key_name(key: VirtualKeyCode) -> String {
+    return format!("{:?}", key);
+}
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    return BINDABLE_KEYS.iter().copied().find(|&key| key_name(key).eq_ignore_ascii_case(name));
+}
+
+// The key bound to each action
+#[derive(Clone, PartialEq)]
+pub struct Keymap {
+    keys: [VirtualKeyCode; Action::ALL.len()],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self { keys: Action::ALL.map(Action::default_key) }
+    }
+}
+
+impl Keymap {
+    pub fn key(&self, action: Action) -> VirtualKeyCode {
+        return self.keys[action as usize];
+    }
+
+    pub fn bind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.keys[action as usize] = key;
+    }
+
+    pub fn pressed(&self, input: &WinitInputHelper, action: Action) -> bool {
+        return input.key_pressed(self.key(action));
+    }
+
+    pub fn held(&self, input: &WinitInputHelper, action: Action) -> bool {
+        return input.key_held(self.key(action));
+    }
+
+    // Whether another action shares this action's key
+    pub fn conflicts(&self, action: Action) -> bool {
+        return Action::ALL.iter().any(|&other| other != action && self.key(other) == self.key(action));
+    }
+
+    // keymap.toml next to the executable, or in the working directory if that can't be found
+    pub fn path() -> PathBuf {
+        return match std::env::current_exe() {
+            Ok(exe) => exe.with_file_name(KEYMAP_FILE),
+            Err(_) => PathBuf::from(KEYMAP_FILE),
+        };
+    }
+
+    // Actions missing from the file keep their default keys
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let table: BTreeMap<String, String> = toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let mut keymap = Keymap::default();
+        for (name, key_name) in &table {
+            let Some(action) = Action::ALL.into_iter().find(|action| action.name() == *name) else {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown action {}", name)));
+            };
+            let Some(key) = parse_key(key_name) else {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown key {} for {}", key_name, name)));
+            };
+            keymap.bind(action, key);
+        }
+
+        return Ok(keymap);
+    }
+
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        let table: BTreeMap<String, String> = Action::ALL.iter().map(|&action| (action.name(), key_name(self.key(action)))).collect();
+        let text = toml::to_string(&table).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        return fs::write(path, text);
+    }
+}
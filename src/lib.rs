@@ -0,0 +1,11 @@
+pub mod utils;
+pub mod body;
+pub mod simulation;
+pub mod quadtree;
+pub mod field_grid;
+pub mod plate_index;
+pub mod plate;
+pub mod scene;
+pub mod undo;
+#[cfg(feature = "gpu")]
+pub mod gpu;
@@ -1,75 +1,295 @@
-use std::sync::atomic::Ordering;
+use std::{panic::PanicHookInfo, sync::atomic::Ordering, time::{Duration, Instant}};
 
-mod utils;
-mod body;
+mod colors;
+mod keymap;
 mod renderer;
-mod simulation;
-mod quadtree;
-mod plate;
+mod screenshot;
 
+use efield_sim::{scene::Scene, simulation::{self, Simulation}, utils};
 use renderer::Renderer;
-use simulation::Simulation;
+
+// Name of the thread running the simulation, so the panic hook can tell it apart
+const SIM_THREAD_NAME: &str = "simulation";
+
+// How long to wait for the simulation thread to stop once the window closes
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 
 fn main() {
+    // Headless Ohm's law sweep, printing the current through the resistor
+    if std::env::args().any(|arg| arg == "--ohms-law") {
+        ohms_law_sweep();
+        return;
+    }
+
+    // Compare the GPU field against the CPU walk on a random scene
+    #[cfg(feature = "gpu")]
+    if std::env::args().any(|arg| arg == "--gpu-check") {
+        gpu_check();
+        return;
+    }
+
+    // Run a scene without a window: --headless --steps N --scene file [--out file] [--seed N] [--gpu]
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        run_headless(&args);
+        return;
+    }
+
     let config = quarkstrom::Config {
         window_mode: quarkstrom::WindowMode::Windowed(900, 900),
     };
 
-    let mut simulation = Simulation::new();
+    // Same seed and same edits give the same run; the renderer takes the seed from the first snapshot
+    let seed = arg_value(&args, "--seed").and_then(|seed| seed.parse().ok()).unwrap_or(0);
 
-    std::thread::spawn(move || {
-        loop {
+    let mut simulation = Simulation::with_edits(renderer::EDITS.1.clone());
+    simulation.reseed(seed);
+    select_backend(&args, &mut simulation);
+
+    // Show a panic on the simulation thread in the window, on top of the usual message
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some(SIM_THREAD_NAME) {
+            *renderer::CRASH.lock() = Some(panic_message(info));
+        }
+        default_hook(info);
+    }));
+
+    let spawned = std::thread::Builder::new().name(SIM_THREAD_NAME.to_string()).spawn(move || {
+        let mut rate_timer = Instant::now();
+        let mut rate_steps = 0;
+        let mut rate_ticks = 0;
+        let mut next_tick = Instant::now();
+
+        while !renderer::SHUTDOWN.load(Ordering::Relaxed) {
             if renderer::PAUSED.load(Ordering::Relaxed) {
+                // Edits still go through while paused, and get published below
+                simulation.apply_edits();
                 std::thread::yield_now();
             } else {
-                simulation.step();
+                // Several steps per publication, so rendering cost doesn't scale with speed
+                let steps = simulation.steps_per_frame;
+                for _ in 0..steps {
+                    simulation.step();
+                }
+                rate_steps += steps;
+            }
+            rate_ticks += 1;
+
+            // Measure the achieved step and tick rates about once a second
+            let elapsed = rate_timer.elapsed().as_secs_f32();
+            if elapsed >= 1.0 {
+                simulation.average_performance.steps_per_second = rate_steps as f32 / elapsed;
+                simulation.average_performance.ticks_per_second = rate_ticks as f32 / elapsed;
+                rate_timer = Instant::now();
+                rate_steps = 0;
+                rate_ticks = 0;
             }
+
+            let publish_start = Instant::now();
             send_sim_data_to_renderer(&mut simulation);
+            simulation::smooth(&mut simulation.average_performance.publish_time, publish_start.elapsed().as_secs_f32());
 
-            std::thread::sleep(std::time::Duration::from_millis(16));
+            // Fixed tick rate: sleep off whatever is left of this tick, or carry on at once when behind
+            let target = simulation.target_ticks_per_second.max(1.0);
+            next_tick += Duration::from_secs_f32(1.0 / target);
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+            } else {
+                // Don't try to make up ticks that were already lost
+                next_tick = now;
+            }
         }
     });
+    let simulation_thread = match spawned {
+        Ok(handle) => handle,
+        Err(err) => {
+            eprintln!("Couldn't start the simulation thread: {}", err);
+            return;
+        }
+    };
 
     quarkstrom::run::<Renderer>(config);
+
+    // Let the simulation finish its tick, but don't hang on exit if it's stuck in a long step
+    renderer::SHUTDOWN.store(true, Ordering::Relaxed);
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while !simulation_thread.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if simulation_thread.is_finished() {
+        let _ = simulation_thread.join();
+    } else {
+        eprintln!("Simulation thread didn't stop within {:?}, exiting anyway", SHUTDOWN_TIMEOUT);
+    }
+}
+
+// Panic payload and where it happened, as one line
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    };
+
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", message, location.file(), location.line()),
+        None => message,
+    }
+}
+
+// Current should scale with battery strength * resist, so k should stay roughly constant
+fn ohms_law_sweep() {
+    let steps = 2000;
+
+    println!("battery  resist  current  k");
+    for battery_strength in [0.5, 1.0, 2.0] {
+        for resist in [0.25, 0.5, 1.0] {
+            let (bodies, plates) = utils::ohms_law_circuit(4, battery_strength, resist);
+            let mut simulation = Simulation::headless(bodies, plates);
+
+            for _ in 0..steps {
+                simulation.step();
+            }
+
+            let current = simulation.current_through(2);
+            println!("{:7.2}  {:6.2}  {:7.4}  {:.4}", battery_strength, resist, current, current / (battery_strength * resist));
+        }
+    }
+}
+
+// Move the body field onto the GPU when --gpu is passed and an adapter is available
+#[cfg(feature = "gpu")]
+fn select_backend(args: &[String], simulation: &mut Simulation) {
+    if !args.iter().any(|arg| arg == "--gpu") {
+        return;
+    }
+
+    simulation.gpu = efield_sim::gpu::GpuField::new();
+    if simulation.gpu.is_none() {
+        eprintln!("No GPU adapter with compute shaders, using the CPU");
+    }
+}
+
+#[cfg(not(feature = "gpu"))]
+fn select_backend(args: &[String], _simulation: &mut Simulation) {
+    if args.iter().any(|arg| arg == "--gpu") {
+        eprintln!("Built without the gpu feature, using the CPU");
+    }
+}
+
+// Relative error of the GPU field against the CPU walk, failing past a tolerance
+#[cfg(feature = "gpu")]
+fn gpu_check() {
+    let tolerance = 1e-3;
+
+    let Some(gpu) = efield_sim::gpu::GpuField::new() else {
+        eprintln!("No GPU adapter with compute shaders");
+        std::process::exit(1);
+    };
+
+    let mut bodies = utils::uniform_disc(20000);
+    let mut rng = fastrand::Rng::with_seed(0);
+    for body in &mut bodies {
+        body.charge = if rng.bool() { 1.0 } else { -1.0 };
+    }
+
+    let mut quadtree = efield_sim::quadtree::Quadtree::new(0.75, 1.0);
+    quadtree.dipole = true;
+    quadtree.clear(efield_sim::quadtree::Quad::new_containing(&bodies));
+    for (i, body) in bodies.iter().enumerate() {
+        quadtree.insert(body.pos, body.charge, i);
+    }
+    quadtree.propagate();
+
+    let queries: Vec<_> = bodies.iter().map(|body| (body.pos, quadtree.e_sq)).collect();
+    let Some(efields) = gpu.efield(&quadtree, &queries) else {
+        eprintln!("GPU field evaluation failed on {}", gpu.adapter_name);
+        std::process::exit(1);
+    };
+
+    let mut max_error = 0.0f32;
+    let mut sum_sq_error = 0.0f32;
+    for (&(pos, e_sq), gpu_efield) in queries.iter().zip(&efields) {
+        let cpu_efield = quadtree.efield_with(pos, e_sq);
+        let error = (*gpu_efield - cpu_efield).mag() / cpu_efield.mag().max(1e-6);
+        max_error = max_error.max(error);
+        sum_sq_error += error * error;
+    }
+    let rms_error = (sum_sq_error / queries.len() as f32).sqrt();
+
+    println!("{}: {} bodies, max error {:.2e}, rms error {:.2e}", gpu.adapter_name, queries.len(), max_error, rms_error);
+    if rms_error > tolerance {
+        std::process::exit(1);
+    }
+}
+
+// Value following a command line flag
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    return args.get(i + 1).map(|arg| arg.as_str());
+}
+
+// Step a scene file on its own and print the final state as JSON
+fn run_headless(args: &[String]) {
+    let steps: usize = arg_value(args, "--steps").and_then(|steps| steps.parse().ok()).unwrap_or(1000);
+    let Some(path) = arg_value(args, "--scene") else {
+        eprintln!("--headless needs --scene <file>");
+        return;
+    };
+
+    let scene = match Scene::load(path) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("Couldn't load scene {}: {}", path, err);
+            return;
+        }
+    };
+
+    let mut simulation = scene.into_simulation();
+    select_backend(args, &mut simulation);
+    eprintln!("Field backend: {}", simulation.field_backend());
+    if let Some(seed) = arg_value(args, "--seed").and_then(|seed| seed.parse().ok()) {
+        simulation.reseed(seed);
+    }
+    for _ in 0..steps {
+        simulation.step();
+    }
+
+    let currents: Vec<_> = simulation.plates.iter().enumerate().map(|(i, plate)| serde_json::json!({
+        "plate": i,
+        "type": plate.plate_type,
+        "current": simulation.current_through(i),
+    })).collect();
+    let bodies: Vec<_> = simulation.bodies.iter().map(|body| serde_json::json!({
+        "pos": [body.pos.x, body.pos.y],
+        "charge": body.charge,
+    })).collect();
+    let summary = serde_json::json!({
+        "steps": steps,
+        "currents": currents,
+        "bodies": bodies,
+    });
+
+    let text = serde_json::to_string_pretty(&summary).unwrap_or_default();
+    match arg_value(args, "--out") {
+        Some(out) => {
+            if let Err(err) = std::fs::write(out, text) {
+                eprintln!("Couldn't write {}: {}", out, err);
+            }
+        },
+        None => println!("{}", text),
+    }
 }
 
 // Send the simulation data to the renderer
 fn send_sim_data_to_renderer(simulation: &mut Simulation) {
-    let mut lock = renderer::SIM_TO_RENDERER_UPDATE_LOCK.lock();
-    {
-        // Update the bodies
-        let mut lock = renderer::BODIES.lock();
-        lock.clear();
-        lock.extend_from_slice(&simulation.bodies);
-    }
-    {
-        // Update the plates
-        let mut lock = renderer::PLATES.lock();
-        lock.clear();
-        lock.extend_from_slice(&simulation.plates);
-    }
-    {
-        // Update the quadtree
-        let mut lock = renderer::QUADTREE.lock();
-        lock.clear();
-        lock.extend_from_slice(&simulation.quadtree.nodes);
-    }
-    {
-        // Update the time step
-        let lock = renderer::DT.lock();
-        simulation.dt = *lock;
-    }
-    {
-        // Update electron charge
-        let lock = renderer::QE.lock();
-        simulation.qe = *lock;
-    }
-    {
-        // Update plate charge
-        let lock = renderer::QP.lock();
-        simulation.qp = *lock;
-    }
-
-    // Trigger update
-    *lock |= true;
+    // Publish a snapshot once the renderer has taken the last one, and only when it would show something new,
+    // so a paused simulation stops copying the scene until an edit comes in
+    if !renderer::SNAPSHOTS.0.is_full() && simulation.snapshot_stale() {
+        let snapshot = simulation.snapshot();
+        let _ = renderer::SNAPSHOTS.0.try_send(snapshot);
+    }
 }
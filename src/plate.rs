@@ -1,14 +1,59 @@
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU32, Ordering};
 
+use serde::{Deserialize, Serialize};
 use ultraviolet::Vec2;
 
-#[derive(Clone, Copy)]
+// Resist applied to bodies pushed against a diode's direction
+pub const DIODE_REVERSE_RESIST: f32 = 0.02;
+
+// Plates with a side longer than this evaluate their field in double precision
+pub const F64_PLATE_SIZE: f32 = 200.0;
+
+// Upper limit for a regulated battery's EMF
+pub const MAX_REGULATED_EMF: f32 = 10.0;
+
+// Source of plate ids, which stay stable while plates are added and removed
+static NEXT_PLATE_ID: AtomicU32 = AtomicU32::new(0);
+
+fn next_plate_id() -> u32 {
+    NEXT_PLATE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Boundary segments per side carrying induced surface charge
+pub const INDUCED_SEGMENTS_PER_SIDE: usize = 8;
+pub const INDUCED_SEGMENTS: usize = INDUCED_SEGMENTS_PER_SIDE * 4;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Plate {
     pub min: Vec2,
     pub max: Vec2,
     pub efield: Vec2,
+    pub direction: f32,
+    pub frequency: f32,
+    pub phase: f32,
     pub resist: f32,
+    // Temperature coefficient of a resistor's resist
+    pub alpha: f32,
+    // Relative permittivity of a dielectric
+    pub epsilon_r: f32,
+    pub charge_scale: f32,
+    pub stored_charge: f32,
+    pub emit_rate: f32,
+    pub emit_accum: f32,
+    pub induced: [f32; INDUCED_SEGMENTS],
     pub plate_type: PlateType,
+    pub profile: DriveProfile,
+    // Batteries with a gain regulate their EMF towards the target current
+    pub gain: f32,
+    pub target_current: f32,
+    pub regulated_emf: f32,
+    // Label shown on the plate, empty when unnamed
+    #[serde(default)]
+    pub name: String,
+    // Not saved: loaded plates get fresh ids
+    #[serde(skip, default = "next_plate_id")]
+    pub id: u32,
 }
 
 impl Plate {
@@ -17,30 +62,44 @@ impl Plate {
             min,
             max,
             efield: Vec2::zero(),
+            direction: 0.0,
+            frequency: 0.0,
+            phase: 0.0,
             resist: 1.0,
+            alpha: 0.0,
+            epsilon_r: 1.0,
+            charge_scale: 1.0,
+            stored_charge: 0.0,
+            emit_rate: 0.0,
+            emit_accum: 0.0,
+            induced: [0.0; INDUCED_SEGMENTS],
             plate_type: PlateType::Normal,
+            profile: DriveProfile::Uniform,
+            gain: 0.0,
+            target_current: 0.0,
+            regulated_emf: 0.0,
+            name: String::new(),
+            id: next_plate_id(),
         }
     }
 
-    pub fn is_in_plate(&self, pos: Vec2) -> bool {
-        return pos.x > self.min.x && pos.x < self.max.x && pos.y > self.min.y && pos.y < self.max.y;
-    }
-
-    pub fn efield_at(&self, pos: Vec2) -> Vec2 {
+    // Field of the plate, softened by epsilon so it stays bounded at the corners
+    pub fn efield_at(&self, pos: Vec2, epsilon: f32) -> Vec2 {
+        let e_sq = epsilon * epsilon;
         let a = self.max.y - pos.y;
         let b = self.min.y - pos.y;
         let c = self.min.x - pos.x;
         let d = self.max.x - pos.x;
 
-        let xac = 0.5 * a * (a * a + c * c).ln() + c * (a / c).atan();
-        let xad = 0.5 * a * (a * a + d * d).ln() + d * (a / d).atan();
-        let xbc = 0.5 * b * (b * b + c * c).ln() + c * (b / c).atan();
-        let xbd = 0.5 * b * (b * b + d * d).ln() + d * (b / d).atan();
+        let xac = edge_term(a, c, e_sq);
+        let xad = edge_term(a, d, e_sq);
+        let xbc = edge_term(b, c, e_sq);
+        let xbd = edge_term(b, d, e_sq);
 
-        let yca = 0.5 * c * (c * c + a * a).ln() + a * (c / a).atan();
-        let ycb = 0.5 * c * (c * c + b * b).ln() + b * (c / b).atan();
-        let yda = 0.5 * d * (d * d + a * a).ln() + a * (d / a).atan();
-        let ydb = 0.5 * d * (d * d + b * b).ln() + b * (d / b).atan();
+        let yca = edge_term(c, a, e_sq);
+        let ycb = edge_term(c, b, e_sq);
+        let yda = edge_term(d, a, e_sq);
+        let ydb = edge_term(d, b, e_sq);
 
         let xa = xad - xac;
         let xb = xbd - xbc;
@@ -49,55 +108,456 @@ impl Plate {
 
         let e_field = Vec2::new(xb - xa, yd - yc) / 2.0;
 
-        if e_field.x.is_nan() || e_field.y.is_nan() {
-            return Vec2::new(0.0, 0.0);
+        return -e_field;
+    }
+
+    // Potential of the uniform plate, softened by the same epsilon so that efield_at == -grad(potential_at)
+    pub fn potential_at(&self, pos: Vec2, epsilon: f32) -> f32 {
+        // The softened potential is only integrated numerically, which is done in f64
+        if epsilon != 0.0 {
+            return self.potential_at_f64(pos, epsilon);
+        }
+
+        let a = self.max.y - pos.y;
+        let b = self.min.y - pos.y;
+        let c = self.min.x - pos.x;
+        let d = self.max.x - pos.x;
+
+        return corner_term(d, a) - corner_term(c, a) - corner_term(d, b) + corner_term(c, b);
+    }
+
+    // Same as efield_at but with the intermediate math in f64
+    pub fn efield_at_f64(&self, pos: Vec2, epsilon: f32) -> Vec2 {
+        let e_sq = (epsilon as f64) * (epsilon as f64);
+        let (px, py) = (pos.x as f64, pos.y as f64);
+        let a = self.max.y as f64 - py;
+        let b = self.min.y as f64 - py;
+        let c = self.min.x as f64 - px;
+        let d = self.max.x as f64 - px;
+
+        let xa = edge_term_f64(a, d, e_sq) - edge_term_f64(a, c, e_sq);
+        let xb = edge_term_f64(b, d, e_sq) - edge_term_f64(b, c, e_sq);
+        let yc = edge_term_f64(c, b, e_sq) - edge_term_f64(c, a, e_sq);
+        let yd = edge_term_f64(d, b, e_sq) - edge_term_f64(d, a, e_sq);
+
+        let e_field = Vec2::new(((xb - xa) / 2.0) as f32, ((yd - yc) / 2.0) as f32);
+
+        return -e_field;
+    }
+
+    // Field of the plate, switching to double precision for large plates (or always)
+    pub fn efield_at_auto(&self, pos: Vec2, always_f64: bool, epsilon: f32) -> Vec2 {
+        let size = self.max - self.min;
+        if always_f64 || size.x.max(size.y) > F64_PLATE_SIZE {
+            return self.efield_at_f64(pos, epsilon);
+        }
+
+        return self.efield_at(pos, epsilon);
+    }
+
+    pub fn potential_at_f64(&self, pos: Vec2, epsilon: f32) -> f32 {
+        let (px, py) = (pos.x as f64, pos.y as f64);
+        let a = self.max.y as f64 - py;
+        let b = self.min.y as f64 - py;
+        let c = self.min.x as f64 - px;
+        let d = self.max.x as f64 - px;
+
+        let potential = if epsilon != 0.0 {
+            softened_potential_f64(a, b, c, d, epsilon as f64)
         } else {
-            return -e_field;
+            corner_term_f64(d, a) - corner_term_f64(c, a) - corner_term_f64(d, b) + corner_term_f64(c, b)
+        };
+
+        return potential as f32;
+    }
+
+    // Potential of the plate, with the same precision switch as efield_at_auto
+    pub fn potential_at_auto(&self, pos: Vec2, always_f64: bool, epsilon: f32) -> f32 {
+        let size = self.max - self.min;
+        if always_f64 || size.x.max(size.y) > F64_PLATE_SIZE {
+            return self.potential_at_f64(pos, epsilon);
+        }
+
+        return self.potential_at(pos, epsilon);
+    }
+
+    // Per-axis scale of the battery drive at pos, which is inside the plate
+    pub fn drive_profile(&self, pos: Vec2) -> Vec2 {
+        let w = self.max.x - self.min.x;
+        let h = self.max.y - self.min.y;
+
+        // Distance from the center line, 0 at the center and 1 at the ends
+        let tx = ((pos.x - (self.min.x + self.max.x) / 2.0).abs() / (w / 2.0)).min(1.0);
+        let ty = ((pos.y - (self.min.y + self.max.y) / 2.0).abs() / (h / 2.0)).min(1.0);
+
+        return match self.profile {
+            DriveProfile::Uniform => Vec2::one(),
+            DriveProfile::Triangular => Vec2::new(1.0 - tx, 1.0 - ty),
+            DriveProfile::Smoothstep => {
+                let smooth = |t: f32| 1.0 - t * t * (3.0 - 2.0 * t);
+                Vec2::new(smooth(tx), smooth(ty))
+            }
+        };
+    }
+
+    // Battery drive at the given time (AC when the frequency is non-zero)
+    pub fn drive(&self, time: f32) -> Vec2 {
+        let efield = if self.gain > 0.0 { self.direction_vec() * self.regulated_emf } else { self.efield };
+
+        if self.frequency == 0.0 {
+            return efield;
+        }
+
+        return efield * (std::f32::consts::TAU * self.frequency * time + self.phase).sin();
+    }
+
+    pub fn area(&self) -> f32 {
+        return (self.max.x - self.min.x) * (self.max.y - self.min.y);
+    }
+
+    // Surface charge density seen by bodies (capacitors add their stored charge)
+    pub fn charge_density(&self, qp: f32, qe: f32) -> f32 {
+        match self.plate_type {
+            PlateType::Capacitor => qp * self.charge_scale + qe * self.stored_charge / self.area(),
+            PlateType::Insulator => 0.0,
+            // Polarization screens the plate's own charge
+            PlateType::Dielectric => qp * self.charge_scale / self.epsilon_r,
+            _ => qp * self.charge_scale,
+        }
+    }
+
+    // Centers of the boundary segments, counterclockwise from the bottom left corner
+    pub fn segment_points(&self) -> [Vec2; INDUCED_SEGMENTS] {
+        let corners = [
+            self.min,
+            Vec2::new(self.max.x, self.min.y),
+            self.max,
+            Vec2::new(self.min.x, self.max.y),
+        ];
+
+        let mut points = [Vec2::zero(); INDUCED_SEGMENTS];
+        for side in 0..4 {
+            let a = corners[side];
+            let b = corners[(side + 1) % 4];
+            for i in 0..INDUCED_SEGMENTS_PER_SIDE {
+                let t = (i as f32 + 0.5) / INDUCED_SEGMENTS_PER_SIDE as f32;
+                points[side * INDUCED_SEGMENTS_PER_SIDE + i] = a + (b - a) * t;
+            }
         }
+
+        points
+    }
+
+    pub fn is_conductor(&self) -> bool {
+        return self.plate_type != PlateType::Insulator && self.plate_type != PlateType::Dielectric;
     }
 
+    // Copy of the plate shifted by offset, with an id of its own
+    pub fn duplicate(&self, offset: Vec2) -> Plate {
+        let mut plate = self.clone();
+        plate.min += offset;
+        plate.max += offset;
+        plate.id = next_plate_id();
+        return plate;
+    }
+
+    // The plate turned or flipped about center, with its drive direction following
+    pub fn transformed(&self, transform: PlateTransform, center: Vec2) -> Plate {
+        let a = transform.apply_point(self.min, center);
+        let b = transform.apply_point(self.max, center);
+
+        let mut plate = self.clone();
+        plate.min = a.min_by_component(b);
+        plate.max = a.max_by_component(b);
+
+        let direction = transform.apply_vector(self.direction_vec());
+        plate.direction = direction.y.atan2(direction.x);
+        plate.efield = transform.apply_vector(self.efield);
+
+        // The sides have moved around, so the induced charge starts over
+        plate.induced = [0.0; INDUCED_SEGMENTS];
+        return plate;
+    }
+
+    // True if the interiors intersect; plates that only share an edge don't overlap
+    pub fn overlaps(&self, other: &Plate) -> bool {
+        return self.min.x < other.max.x && self.max.x > other.min.x && self.min.y < other.max.y && self.max.y > other.min.y;
+    }
+
+    // Half-open [min, max) on both axes, so abutting plates tile without gaps or double coverage
     pub fn contains_point(&self, pos: Vec2) -> bool {
-        return pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y;
+        return pos.x >= self.min.x && pos.x < self.max.x && pos.y >= self.min.y && pos.y < self.max.y;
     }
 
     pub fn make_normal(&mut self) {
         self.plate_type = PlateType::Normal;
+        self.gain = 0.0;
+        self.target_current = 0.0;
         self.resist = 1.0;
+        self.alpha = 0.0;
+        self.epsilon_r = 1.0;
         self.efield = Vec2::new(0.0, 0.0);
+        self.frequency = 0.0;
+        self.phase = 0.0;
+        self.stored_charge = 0.0;
+        self.emit_rate = 0.0;
+        self.emit_accum = 0.0;
     }
 
-    pub fn make_battery(&mut self, efield: f32) {
+    // Direction is the angle of the drive in radians
+    pub fn make_battery(&mut self, efield: f32, direction: f32) {
+        // The regulated EMF carries over while the plate stays a battery
+        let regulated_emf = if self.plate_type == PlateType::Battery { self.regulated_emf } else { efield.abs() };
+        self.make_normal();
         self.plate_type = PlateType::Battery;
-        self.resist = 1.0;
-        
-        // Find direction by taking the longest side
-        let x = self.max.x - self.min.x;
-        let y = self.max.y - self.min.y;
+        self.regulated_emf = regulated_emf;
+        self.direction = direction;
+        self.efield = Vec2::new(direction.cos(), direction.sin()) * efield;
+    }
 
-        if x > y {
-            self.efield = Vec2::new(efield, 0.0);
-        } else {
-            self.efield = Vec2::new(0.0, efield);
-        }
+    pub fn battery_strength(&self) -> f32 {
+        return self.efield.dot(self.direction_vec());
+    }
+
+    pub fn direction_vec(&self) -> Vec2 {
+        return Vec2::new(self.direction.cos(), self.direction.sin());
+    }
+
+    pub fn make_ac_battery(&mut self, amplitude: f32, direction: f32, frequency: f32, phase: f32) {
+        self.make_battery(amplitude, direction);
+        self.frequency = frequency;
+        self.phase = phase;
     }
 
-    pub fn make_resistor(&mut self, resist: f32) {
+    pub fn make_resistor(&mut self, resist: f32, alpha: f32) {
+        self.make_normal();
         self.plate_type = PlateType::Resistor;
         self.resist = resist;
+        self.alpha = alpha;
+    }
+
+    // Resist at the given temperature, dropping as the plate heats up
+    pub fn effective_resist(&self, temperature: f32) -> f32 {
+        return self.resist / (1.0 + self.alpha * temperature);
+    }
+
+    // Direction is the angle bodies are allowed to move along in radians
+    pub fn make_diode(&mut self, direction: f32) {
+        self.make_normal();
+        self.plate_type = PlateType::Diode;
+        self.direction = direction;
+    }
+
+    pub fn make_sink(&mut self) {
+        self.make_normal();
+        self.plate_type = PlateType::Sink;
+    }
+
+    // Rate is the number of bodies spawned per unit of time
+    pub fn make_emitter(&mut self, rate: f32) {
+        let emit_accum = self.emit_accum;
+        self.make_normal();
+        self.plate_type = PlateType::Emitter;
+        self.emit_rate = rate;
+        self.emit_accum = emit_accum;
+    }
+
+    pub fn make_insulator(&mut self) {
+        self.make_normal();
+        self.plate_type = PlateType::Insulator;
+    }
+
+    pub fn make_dielectric(&mut self, epsilon_r: f32) {
+        self.make_normal();
+        self.plate_type = PlateType::Dielectric;
+        self.epsilon_r = epsilon_r;
+    }
+
+    pub fn make_capacitor(&mut self) {
+        let stored_charge = if self.plate_type == PlateType::Capacitor { self.stored_charge } else { 0.0 };
+        self.make_normal();
+        self.plate_type = PlateType::Capacitor;
+        self.stored_charge = stored_charge;
+    }
+
+    // The setting that sets this plate apart from the others of its type, with its name
+    pub fn key_parameter(&self) -> Option<(&'static str, f32)> {
+        match self.plate_type {
+            PlateType::Battery => Some(("strength", self.battery_strength())),
+            PlateType::Resistor => Some(("resist", self.resist)),
+            PlateType::Capacitor => Some(("stored", self.stored_charge)),
+            PlateType::Emitter => Some(("rate", self.emit_rate)),
+            PlateType::Dielectric => Some(("εr", self.epsilon_r)),
+            _ => None,
+        }
+    }
+
+    // Change the setting key_parameter reports
+    pub fn set_key_parameter(&mut self, value: f32) {
+        match self.plate_type {
+            PlateType::Battery => self.efield = self.direction_vec() * value,
+            PlateType::Resistor => self.resist = value,
+            PlateType::Capacitor => self.stored_charge = value,
+            PlateType::Emitter => self.emit_rate = value,
+            PlateType::Dielectric => self.epsilon_r = value,
+            _ => {}
+        }
     }
 }
 
+// Antiderivative term 0.5 * p * ln(p² + q²) + q * atan(p / q) of the uniform plate field,
+// written with its limits so it stays finite and continuous when p or q is zero
+// e_sq softens the corners; with e_sq = 0 this is the exact uniform plate
+fn edge_term(p: f32, q: f32, e_sq: f32) -> f32 {
+    let r_sq = p * p + q * q + e_sq;
+    let log_term = if r_sq > 0.0 { 0.5 * p * r_sq.ln() } else { 0.0 };
+
+    // q * atan(p / q) == |q| * atan2(p, |q|), which goes to zero as q does
+    let q = (q * q + e_sq).sqrt();
+    let atan_term = q * p.atan2(q);
+
+    log_term + atan_term
+}
+
+// Antiderivative of ln(x² + y²) / 4 over x and y
+fn corner_term(x: f32, y: f32) -> f32 {
+    let r_sq = x * x + y * y;
+    let log_term = if r_sq > 0.0 { x * y * r_sq.ln() } else { 0.0 };
+    let atan_x = if x != 0.0 { x * x * (y / x).atan() } else { 0.0 };
+    let atan_y = if y != 0.0 { y * y * (x / y).atan() } else { 0.0 };
+
+    (log_term - 3.0 * x * y + atan_x + atan_y) / 4.0
+}
+
+fn corner_term_f64(x: f64, y: f64) -> f64 {
+    let r_sq = x * x + y * y;
+    let log_term = if r_sq > 0.0 { x * y * r_sq.ln() } else { 0.0 };
+    let atan_x = if x != 0.0 { x * x * (y / x).atan() } else { 0.0 };
+    let atan_y = if y != 0.0 { y * y * (x / y).atan() } else { 0.0 };
+
+    (log_term - 3.0 * x * y + atan_x + atan_y) / 4.0
+}
+
+fn edge_term_f64(p: f64, q: f64, e_sq: f64) -> f64 {
+    let r_sq = p * p + q * q + e_sq;
+    let log_term = if r_sq > 0.0 { 0.5 * p * r_sq.ln() } else { 0.0 };
+    let q = (q * q + e_sq).sqrt();
+    let atan_term = q * p.atan2(q);
+
+    log_term + atan_term
+}
+
+// 16 point Gauss-Legendre rule on [-1, 1], as the positive nodes and their weights
+const GAUSS_NODES: [f64; 8] = [
+    0.0950125098376374, 0.2816035507792589, 0.4580167776572274, 0.6178762444026438,
+    0.755404408355003, 0.8656312023878318, 0.9445750230732326, 0.9894009349916499,
+];
+const GAUSS_WEIGHTS: [f64; 8] = [
+    0.1894506104550685, 0.1826034150449236, 0.1691565193950025, 0.1495959888165767,
+    0.1246289712555339, 0.0951585116824928, 0.0622535239386479, 0.0271524594117541,
+];
+
+// Antiderivative of ln(x² + y² + e²) over y
+fn strip_term_f64(x: f64, y: f64, e_sq: f64) -> f64 {
+    let k = (x * x + e_sq).sqrt();
+    return y * (x * x + y * y + e_sq).ln() - 2.0 * y + 2.0 * k * y.atan2(k);
+}
+
+// Integral of ln(x² + y² + e²) / 4 over x in [c, d] and y in [b, a], for epsilon > 0. The integral over y is
+// closed form; the one over x has none, so it's done with x = epsilon * sinh(t), which packs the nodes in
+// where the integrand bends, near x = 0
+fn softened_potential_f64(a: f64, b: f64, c: f64, d: f64, epsilon: f64) -> f64 {
+    let e_sq = epsilon * epsilon;
+    let integrate = |lo: f64, hi: f64| {
+        let (t0, t1) = ((lo / epsilon).asinh(), (hi / epsilon).asinh());
+        let (mid, half) = ((t0 + t1) / 2.0, (t1 - t0) / 2.0);
+
+        let mut sum = 0.0;
+        for (&node, &weight) in GAUSS_NODES.iter().zip(&GAUSS_WEIGHTS) {
+            for t in [mid - half * node, mid + half * node] {
+                let x = epsilon * t.sinh();
+                sum += weight * (strip_term_f64(x, a, e_sq) - strip_term_f64(x, b, e_sq)) * epsilon * t.cosh();
+            }
+        }
+        sum * half
+    };
+
+    // Split at x = 0 so each half is smooth
+    let integral = if c < 0.0 && d > 0.0 { integrate(c, 0.0) + integrate(0.0, d) } else { integrate(c, d) };
+
+    return integral / 4.0;
+}
+
 impl PartialEq for Plate {
     fn eq(&self, other: &Self) -> bool {
-        self.min == other.min && self.max == other.max
+        self.min == other.min && self.max == other.max && self.charge_scale == other.charge_scale
+    }
+}
+
+// Quarter turns and mirror flips, which keep grid-aligned plates grid-aligned
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlateTransform {
+    RotateLeft,
+    RotateRight,
+    MirrorHorizontal,
+    MirrorVertical,
+}
+
+impl PlateTransform {
+    pub fn inverse(self) -> Self {
+        return match self {
+            PlateTransform::RotateLeft => PlateTransform::RotateRight,
+            PlateTransform::RotateRight => PlateTransform::RotateLeft,
+            mirror => mirror,
+        };
+    }
+
+    pub fn apply_vector(self, v: Vec2) -> Vec2 {
+        return match self {
+            PlateTransform::RotateLeft => Vec2::new(-v.y, v.x),
+            PlateTransform::RotateRight => Vec2::new(v.y, -v.x),
+            PlateTransform::MirrorHorizontal => Vec2::new(-v.x, v.y),
+            PlateTransform::MirrorVertical => Vec2::new(v.x, -v.y),
+        };
+    }
+
+    pub fn apply_point(self, pos: Vec2, center: Vec2) -> Vec2 {
+        return center + self.apply_vector(pos - center);
+    }
+
+    // Center nearest to pos about which the transform maps grid lines onto grid lines
+    pub fn snap_center(self, pos: Vec2, grid_size: f32) -> Vec2 {
+        if matches!(self, PlateTransform::MirrorHorizontal | PlateTransform::MirrorVertical) {
+            let half = grid_size * 0.5;
+            return (pos / half).map(|t| t.round()) * half;
+        }
+
+        // Turning needs both coordinates on grid lines, or both halfway between them
+        let corner = (pos / grid_size).map(|t| t.round()) * grid_size;
+        let center = (pos / grid_size).map(|t| t.floor() + 0.5) * grid_size;
+        return if (corner - pos).mag_sq() <= (center - pos).mag_sq() { corner } else { center };
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+// How a battery's drive varies from its center to its ends
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DriveProfile {
+    Uniform,
+    Triangular,
+    Smoothstep,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum PlateType {
     Normal,
     Battery,
-    Resistor
+    Resistor,
+    Capacitor,
+    Diode,
+    Sink,
+    Emitter,
+    Insulator,
+    Dielectric,
 }
 
 impl Debug for PlateType {
@@ -106,6 +566,319 @@ impl Debug for PlateType {
             PlateType::Normal => write!(f, "Normal"),
             PlateType::Battery => write!(f, "Battery"),
             PlateType::Resistor => write!(f, "Resistor"),
+            PlateType::Capacitor => write!(f, "Capacitor"),
+            PlateType::Diode => write!(f, "Diode"),
+            PlateType::Sink => write!(f, "Sink"),
+            PlateType::Emitter => write!(f, "Emitter"),
+            PlateType::Insulator => write!(f, "Insulator"),
+            PlateType::Dielectric => write!(f, "Dielectric"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // -grad(potential_at) by central differences, in f64 so the differences don't lose the field
+    fn potential_gradient(plate: &Plate, pos: Vec2, epsilon: f32) -> Vec2 {
+        let h = 1e-2;
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        let gx = plate.potential_at_f64(pos + dx, epsilon) - plate.potential_at_f64(pos - dx, epsilon);
+        let gy = plate.potential_at_f64(pos + dy, epsilon) - plate.potential_at_f64(pos - dy, epsilon);
+
+        return -Vec2::new(gx, gy) / (2.0 * h);
+    }
+
+    // Plates from small to past F64_PLATE_SIZE
+    fn test_plates() -> Vec<Plate> {
+        return vec![
+            Plate::new(Vec2::new(-1.0, -1.0), Vec2::new(1.0, 1.0)),
+            Plate::new(Vec2::new(-20.0, -5.0), Vec2::new(20.0, 5.0)),
+            Plate::new(Vec2::new(10.0, -150.0), Vec2::new(310.0, 150.0)),
+        ];
+    }
+
+    // The corners and edge midpoints of a plate, and points just off them on every side
+    fn boundary_points(plate: &Plate) -> Vec<Vec2> {
+        let mid = (plate.min + plate.max) / 2.0;
+        let anchors = [
+            plate.min, plate.max, Vec2::new(plate.min.x, plate.max.y), Vec2::new(plate.max.x, plate.min.y),
+            Vec2::new(plate.min.x, mid.y), Vec2::new(plate.max.x, mid.y), Vec2::new(mid.x, plate.min.y), Vec2::new(mid.x, plate.max.y),
+        ];
+
+        let mut points = Vec::new();
+        for anchor in anchors {
+            for dx in [-1e-3, 0.0, 1e-3] {
+                for dy in [-1e-3, 0.0, 1e-3] {
+                    points.push(anchor + Vec2::new(dx, dy));
+                }
+            }
+        }
+        return points;
+    }
+
+    // -1/2 the integral of (pos - s) / (|pos - s|² + epsilon²) over the plate, by the midpoint rule on an n by n grid
+    fn quadrature_efield(plate: &Plate, pos: Vec2, epsilon: f32, n: usize) -> Vec2 {
+        let (min, max) = ((plate.min.x as f64, plate.min.y as f64), (plate.max.x as f64, plate.max.y as f64));
+        let (dx, dy) = ((max.0 - min.0) / n as f64, (max.1 - min.1) / n as f64);
+        let e_sq = (epsilon as f64).powi(2);
+
+        let (mut ex, mut ey) = (0.0, 0.0);
+        for i in 0..n {
+            for j in 0..n {
+                let x = pos.x as f64 - (min.0 + (i as f64 + 0.5) * dx);
+                let y = pos.y as f64 - (min.1 + (j as f64 + 0.5) * dy);
+                let denom = x * x + y * y + e_sq;
+                ex += x / denom;
+                ey += y / denom;
+            }
+        }
+
+        let scale = -0.5 * dx * dy;
+        return Vec2::new((ex * scale) as f32, (ey * scale) as f32);
+    }
+
+    #[test]
+    fn efield_matches_quadrature_on_edge_lines() {
+        let plate = Plate::new(Vec2::new(-20.0, -5.0), Vec2::new(20.0, 5.0));
+
+        // On the lines through the edges, outside the plate, where the old atan form flipped sign
+        let points = [
+            Vec2::new(-20.0, 12.0), Vec2::new(-20.0, -9.0), Vec2::new(20.0, 7.5), Vec2::new(20.0, -30.0),
+            Vec2::new(-26.0, 5.0), Vec2::new(31.0, 5.0), Vec2::new(-45.0, -5.0), Vec2::new(23.0, -5.0),
+        ];
+        for pos in points {
+            let expected = quadrature_efield(&plate, pos, 0.0, 400);
+            for efield in [plate.efield_at(pos, 0.0), plate.efield_at_f64(pos, 0.0)] {
+                assert!((efield - expected).mag() <= 1e-3 * expected.mag().max(1.0), "at {:?}: {:?} vs {:?}", pos, efield, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn efield_matches_quadrature_at_corners_and_center() {
+        let plate = Plate::new(Vec2::new(-20.0, -5.0), Vec2::new(20.0, 5.0));
+
+        // The unsoftened field diverges at the corners, so these are softened
+        let points = [plate.min, plate.max, Vec2::new(-20.0, 5.0), Vec2::new(20.0, -5.0), Vec2::new(0.0, 5.0), Vec2::zero()];
+        for pos in points {
+            let expected = quadrature_efield(&plate, pos, 1.0, 400);
+            for efield in [plate.efield_at(pos, 1.0), plate.efield_at_f64(pos, 1.0)] {
+                assert!((efield - expected).mag() <= 1e-3 * expected.mag().max(1.0), "at {:?}: {:?} vs {:?}", pos, efield, expected);
+            }
+        }
+
+        // By symmetry there's no field at the center, softened or not
+        assert!(plate.efield_at(Vec2::zero(), 0.0).mag() < 1e-5);
+    }
+
+    // No point sees a larger field than at the center of a disc with the plate's area, sqrt(pi * area)
+    fn efield_bound(plate: &Plate) -> f32 {
+        return (std::f32::consts::PI * plate.area()).sqrt();
+    }
+
+    #[test]
+    fn softened_efield_is_bounded_at_corners() {
+        for plate in test_plates() {
+            for epsilon in [0.1, 1.0] {
+                for pos in boundary_points(&plate) {
+                    for efield in [plate.efield_at(pos, epsilon), plate.efield_at_f64(pos, epsilon)] {
+                        assert!(efield.mag() <= efield_bound(&plate), "epsilon {} at {:?}: {:?}", epsilon, pos, efield);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn potential_is_bounded_and_continuous_at_edges_and_corners() {
+        for plate in test_plates() {
+            for epsilon in [0.0, 0.1, 1.0] {
+                let points = boundary_points(&plate);
+                for chunk in points.chunks(9) {
+                    // The anchor is in the middle of its 3 by 3 block of points
+                    let center = plate.potential_at_f64(chunk[4], epsilon);
+                    assert!(center.is_finite(), "epsilon {} at {:?}: {}", epsilon, chunk[4], center);
+
+                    // Points at most 1.5e-3 away can only differ by the field times that, plus rounding
+                    let tolerance = efield_bound(&plate) * 1.5e-3 + center.abs() * 1e-6;
+                    for &pos in chunk {
+                        let potential = plate.potential_at_f64(pos, epsilon);
+                        assert!((potential - center).abs() <= tolerance, "epsilon {} at {:?}: {} vs {}", epsilon, pos, potential, center);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn f32_and_f64_agree() {
+        for plate in test_plates() {
+            let size = plate.max - plate.min;
+            for i in 0..=10 {
+                for j in 0..=10 {
+                    let pos = plate.min - size * 0.5 + Vec2::new(i as f32, j as f32) * size * 0.2;
+                    for epsilon in [0.0, 1.0] {
+                        let potential = plate.potential_at(pos, epsilon);
+                        let potential_f64 = plate.potential_at_f64(pos, epsilon);
+                        let scale = potential_f64.abs().max(1.0);
+                        assert!((potential - potential_f64).abs() <= 1e-4 * scale, "epsilon {} at {:?}: {} vs {}", epsilon, pos, potential, potential_f64);
+
+                        let efield = plate.efield_at(pos, epsilon);
+                        let efield_f64 = plate.efield_at_f64(pos, epsilon);
+                        let scale = efield_f64.mag().max(1.0);
+                        assert!((efield - efield_f64).mag() <= 1e-4 * scale, "epsilon {} at {:?}: {:?} vs {:?}", epsilon, pos, efield, efield_f64);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn efield_is_negative_potential_gradient() {
+        let plate = Plate::new(Vec2::new(-20.0, -5.0), Vec2::new(20.0, 5.0));
+
+        for epsilon in [0.0, 0.5, 2.0] {
+            for i in 0..=12 {
+                for j in 0..=8 {
+                    // Offset off the grid so no sample lands exactly on an edge, where the unsoftened field jumps
+                    let pos = Vec2::new(-30.0 + i as f32 * 5.0 + 0.3, -20.0 + j as f32 * 5.0 + 0.3);
+                    let efield = plate.efield_at_f64(pos, epsilon);
+                    let gradient = potential_gradient(&plate, pos, epsilon);
+
+                    let error = (efield - gradient).mag();
+                    assert!(error <= 1e-2 * efield.mag().max(1.0), "epsilon {} at {:?}: {:?} vs {:?}", epsilon, pos, efield, gradient);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn f64_is_more_precise_near_the_center_of_a_large_plate() {
+        let plate = Plate::new(Vec2::new(-500.0, -500.0), Vec2::new(500.0, 500.0));
+
+        // Near the center of a square the field is -pi/2 times the offset, up to offset³ / size², which is tiny here
+        let offsets = [Vec2::new(0.25, 0.0), Vec2::new(-0.0625, 0.125), Vec2::new(0.5, -0.375), Vec2::new(0.125, 0.4375)];
+        let mut error = 0.0f32;
+        let mut error_f64 = 0.0f32;
+        for offset in offsets {
+            let expected = -offset * std::f32::consts::FRAC_PI_2;
+            error = error.max((plate.efield_at(offset, 0.0) - expected).mag());
+            error_f64 = error_f64.max((plate.efield_at_f64(offset, 0.0) - expected).mag());
+        }
+
+        assert!(error_f64 < 1e-6, "f64 error {}", error_f64);
+        assert!(error_f64 * 100.0 < error, "f64 error {} vs f32 error {}", error_f64, error);
+    }
+
+    #[test]
+    fn overlapping_plates_of_different_types_overlap() {
+        let mut battery = Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0));
+        battery.make_battery(1.0, 0.0);
+        let mut resistor = Plate::new(Vec2::new(30.0, 5.0), Vec2::new(60.0, 25.0));
+        resistor.make_resistor(0.5, 0.0);
+        assert!(battery.overlaps(&resistor) && resistor.overlaps(&battery));
+
+        // One inside the other
+        let mut diode = Plate::new(Vec2::new(10.0, 2.0), Vec2::new(20.0, 8.0));
+        diode.make_diode(0.0);
+        assert!(battery.overlaps(&diode) && diode.overlaps(&battery));
+    }
+
+    #[test]
+    fn plates_sharing_an_edge_or_corner_dont_overlap() {
+        let mut battery = Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0));
+        battery.make_battery(1.0, 0.0);
+
+        let mut resistor = Plate::new(Vec2::new(40.0, 0.0), Vec2::new(60.0, 10.0));
+        resistor.make_resistor(0.5, 0.0);
+        assert!(!battery.overlaps(&resistor) && !resistor.overlaps(&battery));
+
+        let mut insulator = Plate::new(Vec2::new(40.0, 10.0), Vec2::new(50.0, 20.0));
+        insulator.make_insulator();
+        assert!(!battery.overlaps(&insulator) && !insulator.overlaps(&battery));
+    }
+
+    const TRANSFORMS: [PlateTransform; 4] = [
+        PlateTransform::RotateLeft, PlateTransform::RotateRight, PlateTransform::MirrorHorizontal, PlateTransform::MirrorVertical,
+    ];
+
+    fn on_grid(pos: Vec2, grid_size: f32) -> bool {
+        let off = |t: f32| (t / grid_size - (t / grid_size).round()).abs();
+        return off(pos.x) < 1e-4 && off(pos.y) < 1e-4;
+    }
+
+    #[test]
+    fn rotating_swaps_width_and_height() {
+        let plate = Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0));
+        let turned = plate.transformed(PlateTransform::RotateLeft, Vec2::new(20.0, 5.0));
+        assert_eq!((turned.min, turned.max), (Vec2::new(15.0, -15.0), Vec2::new(25.0, 25.0)));
+
+        let turned = plate.transformed(PlateTransform::RotateRight, Vec2::zero());
+        assert_eq!((turned.min, turned.max), (Vec2::new(0.0, -40.0), Vec2::new(10.0, 0.0)));
+    }
+
+    #[test]
+    fn mirroring_keeps_the_size() {
+        let plate = Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0));
+        let mirrored = plate.transformed(PlateTransform::MirrorHorizontal, Vec2::new(50.0, 0.0));
+        assert_eq!((mirrored.min, mirrored.max), (Vec2::new(60.0, 0.0), Vec2::new(100.0, 10.0)));
+
+        let mirrored = plate.transformed(PlateTransform::MirrorVertical, Vec2::new(0.0, 20.0));
+        assert_eq!((mirrored.min, mirrored.max), (Vec2::new(0.0, 30.0), Vec2::new(40.0, 40.0)));
+    }
+
+    #[test]
+    fn transforms_undo_with_their_inverse() {
+        let plate = Plate::new(Vec2::new(-30.0, 10.0), Vec2::new(20.0, 25.0));
+        let center = Vec2::new(5.0, -5.0);
+        for transform in TRANSFORMS {
+            let back = plate.transformed(transform, center).transformed(transform.inverse(), center);
+            assert_eq!((back.min, back.max), (plate.min, plate.max), "{:?}", transform);
+        }
+
+        // Four quarter turns come back around
+        let mut turned = plate.clone();
+        for _ in 0..4 {
+            turned = turned.transformed(PlateTransform::RotateLeft, center);
+        }
+        assert_eq!((turned.min, turned.max), (plate.min, plate.max));
+    }
+
+    #[test]
+    fn battery_direction_follows_the_transform() {
+        let mut battery = Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0));
+        battery.make_battery(1.0, 0.0);
+
+        let expected = [Vec2::new(0.0, 1.0), Vec2::new(0.0, -1.0), Vec2::new(-1.0, 0.0), Vec2::new(1.0, 0.0)];
+        for (transform, expected) in TRANSFORMS.into_iter().zip(expected) {
+            let direction = battery.transformed(transform, Vec2::zero()).direction_vec();
+            assert!((direction - expected).mag() < 1e-6, "{:?}: {:?}", transform, direction);
+        }
+    }
+
+    #[test]
+    fn snapped_centers_keep_plates_on_the_grid() {
+        let grid_size = 10.0;
+        let plates = [
+            Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0)),
+            Plate::new(Vec2::new(-30.0, 20.0), Vec2::new(0.0, 70.0)),
+        ];
+
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..100 {
+            let pos = Vec2::new(rng.f32() * 200.0 - 100.0, rng.f32() * 200.0 - 100.0);
+            for transform in TRANSFORMS {
+                let center = transform.snap_center(pos, grid_size);
+                assert!((center - pos).mag() <= grid_size, "{:?}: {:?} snapped to {:?}", transform, pos, center);
+
+                for plate in &plates {
+                    let moved = plate.transformed(transform, center);
+                    assert!(on_grid(moved.min, grid_size) && on_grid(moved.max, grid_size), "{:?} about {:?}", transform, center);
+                }
+            }
         }
     }
 }
@@ -0,0 +1,186 @@
+use crate::plate::Plate;
+
+use ultraviolet::Vec2;
+
+// Same as the renderer's grid, which plates snap to
+pub const PLATE_INDEX_CELL_SIZE: f32 = 10.0;
+
+// Most cells along a side; the cell size grows past this
+pub const PLATE_INDEX_MAX_CELLS: usize = 1024;
+
+// Uniform grid over the plates, listing the plates that overlap each cell
+pub struct PlateIndex {
+    min: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    // Plates overlapping cell i are indices[starts[i]..starts[i + 1]]
+    starts: Vec<u32>,
+    indices: Vec<u32>,
+    // Plate bounds the index was built from, to tell when it has gone stale
+    bounds: Vec<(Vec2, Vec2)>,
+}
+
+impl PlateIndex {
+    pub fn new(plates: &[Plate], cell_size: f32) -> Self {
+        let mut min = Vec2::zero();
+        let mut max = Vec2::zero();
+        if let Some(first) = plates.first() {
+            min = first.min;
+            max = first.max;
+        }
+        for plate in plates {
+            min = min.min_by_component(plate.min);
+            max = max.max_by_component(plate.max);
+        }
+
+        let size = max - min;
+        let cell_size = cell_size.max(size.x.max(size.y) / PLATE_INDEX_MAX_CELLS as f32);
+        let width = (size.x / cell_size) as usize + 1;
+        let height = (size.y / cell_size) as usize + 1;
+
+        let mut index = Self {
+            min,
+            cell_size,
+            width,
+            height,
+            starts: vec![0; width * height + 1],
+            indices: Vec::new(),
+            bounds: plates.iter().map(|plate| (plate.min, plate.max)).collect(),
+        };
+
+        // Count the plates in each cell, turn the counts into offsets, then fill
+        for plate in plates {
+            for cell in index.cells(plate) {
+                index.starts[cell + 1] += 1;
+            }
+        }
+        for i in 0..width * height {
+            index.starts[i + 1] += index.starts[i];
+        }
+
+        let mut fill = index.starts.clone();
+        index.indices = vec![0; index.starts[width * height] as usize];
+        for (p, plate) in plates.iter().enumerate() {
+            for cell in index.cells(plate) {
+                index.indices[fill[cell] as usize] = p as u32;
+                fill[cell] += 1;
+            }
+        }
+
+        index
+    }
+
+    // Whether the index was built for these plates
+    pub fn matches(&self, plates: &[Plate]) -> bool {
+        return self.bounds.len() == plates.len()
+            && self.bounds.iter().zip(plates).all(|(&(min, max), plate)| min == plate.min && max == plate.max);
+    }
+
+    // Cells a plate overlaps
+    fn cells(&self, plate: &Plate) -> Vec<usize> {
+        let (x0, y0) = self.cell_coords(plate.min);
+        let (x1, y1) = self.cell_coords(plate.max);
+
+        let mut cells = Vec::with_capacity((x1 - x0 + 1) * (y1 - y0 + 1));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                cells.push(y * self.width + x);
+            }
+        }
+
+        cells
+    }
+
+    fn cell_coords(&self, pos: Vec2) -> (usize, usize) {
+        let t = (pos - self.min) / self.cell_size;
+        let x = (t.x.max(0.0) as usize).min(self.width - 1);
+        let y = (t.y.max(0.0) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    // Indices of the plates whose cell holds pos
+    fn cell_plates(&self, pos: Vec2) -> &[u32] {
+        let t = (pos - self.min) / self.cell_size;
+
+        // Also rejects NaN
+        let inside = t.x >= 0.0 && t.y >= 0.0 && (t.x as usize) < self.width && (t.y as usize) < self.height;
+        let range = if inside {
+            let cell = t.y as usize * self.width + t.x as usize;
+            self.starts[cell] as usize..self.starts[cell + 1] as usize
+        } else {
+            0..0
+        };
+
+        &self.indices[range]
+    }
+
+    // Plates whose cell holds pos; callers still check contains_point
+    pub fn candidates<'a>(&'a self, plates: &'a [Plate], pos: Vec2) -> impl Iterator<Item = &'a Plate> + 'a {
+        self.cell_plates(pos).iter().map(move |&p| &plates[p as usize])
+    }
+
+    // Index of the plate containing pos, if any
+    pub fn index_at(&self, plates: &[Plate], pos: Vec2) -> Option<usize> {
+        self.cell_plates(pos).iter().map(|&p| p as usize).find(|&p| plates[p].contains_point(pos))
+    }
+
+    // The plate containing pos, if any (plates never overlap)
+    pub fn plate_at<'a>(&'a self, plates: &'a [Plate], pos: Vec2) -> Option<&'a Plate> {
+        self.candidates(plates, pos).find(|plate| plate.contains_point(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200 wire segments, 20 by 10 with gaps between them, and two plates sharing an edge
+    fn wire_plates() -> Vec<Plate> {
+        let mut plates = Vec::new();
+        for i in 0..200 {
+            let min = Vec2::new((i % 20) as f32 * 30.0, (i / 20) as f32 * 20.0);
+            plates.push(Plate::new(min, min + Vec2::new(20.0, 10.0)));
+        }
+        plates.push(Plate::new(Vec2::new(-40.0, -40.0), Vec2::new(-20.0, -20.0)));
+        plates.push(Plate::new(Vec2::new(-20.0, -40.0), Vec2::new(0.0, -20.0)));
+        return plates;
+    }
+
+    #[test]
+    fn index_matches_a_linear_scan() {
+        let plates = wire_plates();
+        let index = PlateIndex::new(&plates, PLATE_INDEX_CELL_SIZE);
+
+        let mut rng = fastrand::Rng::with_seed(0);
+        let mut points: Vec<Vec2> = (0..5000).map(|_| Vec2::new(rng.f32() * 700.0 - 60.0, rng.f32() * 260.0 - 60.0)).collect();
+        // Corners and edges, where half-open bounds decide
+        for plate in &plates {
+            points.extend([plate.min, plate.max, Vec2::new(plate.min.x, plate.max.y), Vec2::new(plate.max.x, plate.min.y)]);
+        }
+
+        for pos in points {
+            let expected = plates.iter().position(|plate| plate.contains_point(pos));
+            assert_eq!(index.index_at(&plates, pos), expected, "at {:?}", pos);
+            assert_eq!(index.plate_at(&plates, pos).map(|plate| plate.id), expected.map(|p| plates[p].id));
+        }
+    }
+
+    #[test]
+    fn index_goes_stale_when_a_plate_moves() {
+        let mut plates = wire_plates();
+        let index = PlateIndex::new(&plates, PLATE_INDEX_CELL_SIZE);
+        assert!(index.matches(&plates));
+
+        assert!(!index.matches(&plates[..10]));
+        plates[3] = plates[3].duplicate(Vec2::new(5.0, 0.0));
+        assert!(!index.matches(&plates));
+    }
+
+    #[test]
+    fn empty_index_finds_nothing() {
+        let index = PlateIndex::new(&[], PLATE_INDEX_CELL_SIZE);
+        assert_eq!(index.index_at(&[], Vec2::zero()), None);
+        assert_eq!(index.index_at(&[], Vec2::new(f32::NAN, 1.0)), None);
+    }
+}
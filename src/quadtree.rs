@@ -1,6 +1,15 @@
+use std::{cmp::Reverse, collections::BinaryHeap, sync::atomic::{AtomicUsize, Ordering}};
+
 use crate::body::Body;
 use ultraviolet::Vec2;
 
+// Default number of bodies a leaf holds before it subdivides
+pub const LEAF_CAPACITY: usize = 8;
+// Deepest a leaf can be split; below this, nearly coincident bodies share a leaf past its capacity
+pub const MAX_DEPTH: usize = 32;
+// Smallest softening length; with none at all, coincident bodies divide zero by zero
+pub const MIN_EPSILON: f32 = 0.05;
+
 #[derive(Clone, Copy)]
 pub struct Quad {
     pub center: Vec2,
@@ -9,6 +18,11 @@ pub struct Quad {
 
 impl Quad {
     pub fn new_containing(bodies: &[Body]) -> Self {
+        // Unit quad at the origin when there is nothing to contain
+        if bodies.is_empty() {
+            return Self { center: Vec2::zero(), size: 1.0 };
+        }
+
         let mut min_x = f32::MAX;
         let mut min_y = f32::MAX;
         let mut max_x = f32::MIN;
@@ -22,11 +36,38 @@ impl Quad {
         }
 
         let center = Vec2::new(min_x + max_x, min_y + max_y) * 0.5;
-        let size = (max_x - min_x).max(max_y - min_y);
+        let mut size = (max_x - min_x).max(max_y - min_y);
+
+        // A single body (or bodies all at one position) still needs a non-degenerate quad
+        if size <= 0.0 || size.is_nan() {
+            size = 1.0;
+        }
 
         Self { center, size }
     }
 
+    pub fn new_bounds(min: Vec2, max: Vec2) -> Self {
+        let center = (min + max) * 0.5;
+        let size = (max.x - min.x).max(max.y - min.y);
+
+        Self { center, size }
+    }
+
+    // Grow the quad so it also covers the rectangle from min to max
+    pub fn include(&mut self, min: Vec2, max: Vec2) {
+        let half = self.size * 0.5;
+        let min = Vec2::new(min.x.min(self.center.x - half), min.y.min(self.center.y - half));
+        let max = Vec2::new(max.x.max(self.center.x + half), max.y.max(self.center.y + half));
+
+        self.center = (min + max) * 0.5;
+        self.size = (max.x - min.x).max(max.y - min.y);
+    }
+
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let half = self.size * 0.5;
+        (pos.x - self.center.x).abs() <= half && (pos.y - self.center.y).abs() <= half
+    }
+
     pub fn find_quadrant(&self, pos: Vec2) -> usize {
         ((pos.y > self.center.y) as usize) << 1 | (pos.x > self.center.x) as usize
     }
@@ -49,7 +90,13 @@ pub struct Node {
     pub next: usize,
     pub pos: Vec2,
     pub charge: f32,
+    // Dipole moment about pos
+    pub dipole: Vec2,
     pub quad: Quad,
+    // Start of this leaf's slot in the entries array, how many of it are used, and how many it has room for
+    pub bucket: usize,
+    pub count: usize,
+    pub slots: usize,
 }
 
 impl Node {
@@ -59,7 +106,11 @@ impl Node {
             next,
             pos: Vec2::zero(),
             charge: 0.0,
+            dipole: Vec2::zero(),
             quad,
+            bucket: 0,
+            count: 0,
+            slots: 0,
         }
     }
 
@@ -72,16 +123,62 @@ impl Node {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.charge == 0.0
+        self.count == 0 && self.is_leaf()
+    }
+}
+
+// The fields the force walk reads, packed into parallel arrays indexed like nodes
+#[derive(Default)]
+pub struct Walk {
+    pub pos: Vec<Vec2>,
+    pub charge: Vec<f32>,
+    pub dipole: Vec<Vec2>,
+    pub size_sq: Vec<f32>,
+    pub children: Vec<u32>,
+    pub next: Vec<u32>,
+    pub bucket: Vec<u32>,
+    pub count: Vec<u32>,
+}
+
+impl Walk {
+    fn clear(&mut self) {
+        self.pos.clear();
+        self.charge.clear();
+        self.dipole.clear();
+        self.size_sq.clear();
+        self.children.clear();
+        self.next.clear();
+        self.bucket.clear();
+        self.count.clear();
     }
 }
 
+// A point charge stored in a leaf, with the index it was inserted under
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub pos: Vec2,
+    pub charge: f32,
+    pub index: usize,
+}
+
 pub struct Quadtree {
     pub t_sq: f32,
     pub e_sq: f32,
     pub nodes: Vec<Node>,
     pub parents: Vec<usize>,
-    pub calcs: usize,
+    pub leaf_capacity: usize,
+    // Add the dipole term of accepted nodes, for charges that nearly cancel
+    pub dipole: bool,
+    // Bodies held by the leaves, in slots of leaf_capacity entries or more
+    pub entries: Vec<Entry>,
+    // Slots left behind by leaves that were subdivided
+    free_buckets: Vec<usize>,
+    // Rebuilt from nodes by propagate
+    pub walk: Walk,
+    pub max_depth: usize,
+    pub calcs: AtomicUsize,
+    // Size of the periodic domain for minimum-image distances
+    pub period: Option<Vec2>,
 }
 
 impl Quadtree {
@@ -90,20 +187,48 @@ impl Quadtree {
     pub fn new(theta: f32, epsilon: f32) -> Self {
         Self {
             t_sq: theta * theta,
-            e_sq: epsilon * epsilon,
+            e_sq: epsilon.max(MIN_EPSILON).powi(2),
             nodes: Vec::new(),
             parents: Vec::new(),
-            calcs: 0,
+            leaf_capacity: LEAF_CAPACITY,
+            dipole: false,
+            entries: Vec::new(),
+            free_buckets: Vec::new(),
+            walk: Walk::default(),
+            max_depth: 0,
+            calcs: AtomicUsize::new(0),
+            period: None,
         }
     }
 
+    pub fn set_theta(&mut self, theta: f32) {
+        self.t_sq = theta * theta;
+    }
+
+    pub fn set_epsilon(&mut self, epsilon: f32) {
+        self.e_sq = epsilon.max(MIN_EPSILON).powi(2);
+    }
+
     pub fn clear(&mut self, quad: Quad) {
-        // println!("Calculations: {0}", self.calcs);
+        // println!("Calculations: {0}", self.calcs.load(Ordering::Relaxed));
         
-        self.nodes.clear();
+        // Reserve for last frame's tree so reinsertion doesn't reallocate as it grows
+        let (nodes, parents) = (self.nodes.len(), self.parents.len());
+        self.nodes.truncate(1);
         self.parents.clear();
-        self.nodes.push(Node::new(0, quad));
-        self.calcs = 0;
+        self.nodes.reserve(nodes.saturating_sub(1));
+        self.parents.reserve(parents);
+        self.entries.clear();
+        self.free_buckets.clear();
+        self.walk.clear();
+        self.max_depth = 0;
+
+        // Reset the root in place instead of pushing a fresh one
+        match self.nodes.first_mut() {
+            Some(root) => *root = Node::new(0, quad),
+            None => self.nodes.push(Node::new(0, quad)),
+        }
+        self.calcs.store(0, Ordering::Relaxed);
     }
 
     fn subdivide(&mut self, node: usize) -> usize {
@@ -125,91 +250,662 @@ impl Quadtree {
         return children;
     }
 
-    pub fn insert(&mut self, pos: Vec2, charge: f32) {
+    fn alloc_bucket(&mut self) -> usize {
+        if let Some(bucket) = self.free_buckets.pop() {
+            return bucket;
+        }
+
+        let bucket = self.entries.len();
+        self.entries.resize(bucket + self.leaf_capacity, Entry { pos: Vec2::zero(), charge: 0.0, index: 0 });
+        return bucket;
+    }
+
+    // Move a full leaf's entries to a slot twice the size at the end of the entries array
+    fn grow_bucket(&mut self, node: usize) {
+        let (bucket, count) = (self.nodes[node].bucket, self.nodes[node].count);
+        let grown = self.entries.len();
+        self.entries.resize(grown + count * 2, Entry { pos: Vec2::zero(), charge: 0.0, index: 0 });
+        self.entries.copy_within(bucket..bucket + count, grown);
+
+        // The old slot still holds at least leaf_capacity entries, so another leaf can have it
+        self.free_buckets.push(bucket);
+        self.nodes[node].bucket = grown;
+        self.nodes[node].slots = count * 2;
+    }
+
+    fn push_entry(&mut self, node: usize, entry: Entry) {
+        if self.nodes[node].count == 0 {
+            self.nodes[node].bucket = self.alloc_bucket();
+            self.nodes[node].slots = self.leaf_capacity;
+        } else if self.nodes[node].count == self.nodes[node].slots {
+            self.grow_bucket(node);
+        }
+
+        let n = &mut self.nodes[node];
+        self.entries[n.bucket + n.count] = entry;
+        n.count += 1;
+    }
+
+    // Index identifies the charge in range queries, usually its position in the body list
+    pub fn insert(&mut self, pos: Vec2, charge: f32, index: usize) {
+        let capacity = self.leaf_capacity.max(1);
         let mut node = Self::ROOT;
+        let mut depth = 0;
 
         while self.nodes[node].is_branch() {
             let quadrant = self.nodes[node].quad.find_quadrant(pos);
             node = self.nodes[node].children + quadrant;
-        }
-
-        if self.nodes[node].is_empty() {
-            self.nodes[node].pos = pos;
-            self.nodes[node].charge = charge;
-            return;
-        }
-
-        let (p, m) = (self.nodes[node].pos, self.nodes[node].charge);
-        if pos == p {
-            self.nodes[node].charge += charge;
-            return;
+            depth += 1;
         }
 
         loop {
-            let children = self.subdivide(node);
+            self.max_depth = self.max_depth.max(depth);
 
-            let q1 = self.nodes[node].quad.find_quadrant(p);
-            let q2 = self.nodes[node].quad.find_quadrant(pos);
-
-            if q1 == q2 {
-                node = children + q1;
-            } else {
-                let n1 = children + q1;
-                let n2 = children + q2;
+            let (bucket, count) = (self.nodes[node].bucket, self.nodes[node].count);
+            if count < capacity {
+                self.push_entry(node, Entry { pos, charge, index });
+                return;
+            }
 
-                self.nodes[n1].pos = p;
-                self.nodes[n1].charge = m;
-                self.nodes[n2].pos = pos;
-                self.nodes[n2].charge = charge;
+            // No split would separate a body from one at the same position, or tell bodies apart past
+            // MAX_DEPTH, so the leaf keeps it past its capacity
+            let coincident = self.entries[bucket..bucket + count].iter().any(|entry| entry.pos == pos);
+            if coincident || depth >= MAX_DEPTH {
+                self.push_entry(node, Entry { pos, charge, index });
                 return;
             }
+
+            // Full leaf: split it and hand its bodies down to the children
+            let children = self.subdivide(node);
+            self.nodes[node].count = 0;
+
+            for k in bucket..bucket + count {
+                let entry = self.entries[k];
+                let child = children + self.nodes[node].quad.find_quadrant(entry.pos);
+                self.push_entry(child, entry);
+            }
+            self.free_buckets.push(bucket);
+
+            node = children + self.nodes[node].quad.find_quadrant(pos);
+            depth += 1;
         }
     }
 
     pub fn propagate(&mut self) {
+        // Leaves summarize their bucket the same way parents summarize their children
+        for node in &mut self.nodes {
+            if node.is_branch() {
+                continue;
+            }
+
+            let mut pos = Vec2::zero();
+            let mut weight = 0.0;
+            let mut charge = 0.0;
+            for entry in &self.entries[node.bucket..node.bucket + node.count] {
+                pos += entry.pos * entry.charge.abs();
+                weight += entry.charge.abs();
+                charge += entry.charge;
+            }
+
+            node.charge = charge;
+            node.pos = if weight > 0.0 { pos / weight } else { node.quad.center };
+
+            let center = node.pos;
+            node.dipole = self.entries[node.bucket..node.bucket + node.count]
+                .iter()
+                .fold(Vec2::zero(), |dipole, entry| dipole + (entry.pos - center) * entry.charge);
+        }
+
         for &node in self.parents.iter().rev() {
             let i = self.nodes[node].children;
 
-            self.nodes[node].pos = self.nodes[i].pos * self.nodes[i].charge
-                + self.nodes[i + 1].pos * self.nodes[i + 1].charge
-                + self.nodes[i + 2].pos * self.nodes[i + 2].charge
-                + self.nodes[i + 3].pos * self.nodes[i + 3].charge;
+            // Weight by the charge magnitude so opposite charges don't cancel the center
+            self.nodes[node].pos = self.nodes[i].pos * self.nodes[i].charge.abs()
+                + self.nodes[i + 1].pos * self.nodes[i + 1].charge.abs()
+                + self.nodes[i + 2].pos * self.nodes[i + 2].charge.abs()
+                + self.nodes[i + 3].pos * self.nodes[i + 3].charge.abs();
+
+            let weight = self.nodes[i].charge.abs()
+                + self.nodes[i + 1].charge.abs()
+                + self.nodes[i + 2].charge.abs()
+                + self.nodes[i + 3].charge.abs();
 
             self.nodes[node].charge = self.nodes[i].charge
                 + self.nodes[i + 1].charge
                 + self.nodes[i + 2].charge
                 + self.nodes[i + 3].charge;
 
-            let mass = self.nodes[node].charge;
-            self.nodes[node].pos /= mass;
+            // Only zero-charge children: the position doesn't matter, but it must stay finite
+            if weight > 0.0 {
+                self.nodes[node].pos /= weight;
+            } else {
+                self.nodes[node].pos = self.nodes[node].quad.center;
+            }
+
+            // Shift each child's dipole to the new center
+            let center = self.nodes[node].pos;
+            let mut dipole = Vec2::zero();
+            for child in &self.nodes[i..i + 4] {
+                dipole += child.dipole + (child.pos - center) * child.charge;
+            }
+            self.nodes[node].dipole = dipole;
+        }
+
+        self.flatten();
+    }
+
+    fn flatten(&mut self) {
+        let walk = &mut self.walk;
+        walk.clear();
+
+        for node in &self.nodes {
+            walk.pos.push(node.pos);
+            walk.charge.push(node.charge);
+            walk.dipole.push(node.dipole);
+            walk.size_sq.push(node.quad.size * node.quad.size);
+            walk.children.push(node.children as u32);
+            walk.next.push(node.next as u32);
+            walk.bucket.push(node.bucket as u32);
+            walk.count.push(node.count as u32);
+        }
+    }
+
+    // Minimum-image offset in a periodic domain
+    fn wrap(&self, mut d: Vec2) -> Vec2 {
+        if let Some(period) = self.period {
+            d.x -= period.x * (d.x / period.x).round();
+            d.y -= period.y * (d.y / period.y).round();
         }
+        d
     }
 
-    pub fn efield(&mut self, pos: Vec2) -> Vec2 {
+    pub fn efield(&self, pos: Vec2) -> Vec2 {
+        self.efield_with(pos, self.e_sq)
+    }
+
+    // Field at pos with a softening chosen by the caller
+    pub fn efield_with(&self, pos: Vec2, e_sq: f32) -> Vec2 {
         let mut efield = Vec2::zero();
+        let mut calcs = 0;
+
+        let walk = &self.walk;
+        if walk.pos.is_empty() {
+            return efield;
+        }
 
         let mut node = Self::ROOT;
         loop {
-            let n = &self.nodes[node];
-
-            let d = pos - n.pos;
+            let d = self.wrap(pos - walk.pos[node]);
             let d_sq = d.mag_sq();
 
-            if n.is_leaf() || n.quad.size * n.quad.size < d_sq * self.t_sq {
-                // Electic force (2D)
-                let denom = d_sq + self.e_sq;
-                efield += d * (n.charge / denom).min(f32::MAX);
-                self.calcs += 1;
+            let far = walk.size_sq[node] < d_sq * self.t_sq;
+            let children = walk.children[node] as usize;
+            if far || children == 0 {
+                if far {
+                    // Electic force (2D)
+                    let denom = d_sq + e_sq;
+                    efield += d * (walk.charge[node] / denom).min(f32::MAX);
+                    calcs += 1;
+
+                    // -(p.grad)(d / denom)
+                    if self.dipole {
+                        let p = walk.dipole[node];
+                        efield += (d * (2.0 * p.dot(d) / denom) - p) / denom;
+                    }
+                } else {
+                    // Near leaves are summed body by body
+                    let bucket = walk.bucket[node] as usize;
+                    for entry in &self.entries[bucket..bucket + walk.count[node] as usize] {
+                        let d = self.wrap(pos - entry.pos);
+                        let denom = d.mag_sq() + e_sq;
+                        if denom > 0.0 {
+                            efield += d * (entry.charge / denom);
+                        }
+                        calcs += 1;
+                    }
+                }
 
-                if n.next == 0 {
+                node = walk.next[node] as usize;
+                if node == 0 {
                     break;
                 }
-                node = n.next;
             } else {
-                node = n.children;
+                node = children;
             }
         }
 
+        // Counted locally so parallel queries only touch the shared counter once
+        self.calcs.fetch_add(calcs, Ordering::Relaxed);
         efield
     }
+
+    // Potential of the softened 2D kernel, with efield == -grad(potential)
+    pub fn potential(&self, pos: Vec2) -> f32 {
+        let mut potential = 0.0;
+
+        let walk = &self.walk;
+        if walk.pos.is_empty() {
+            return potential;
+        }
+
+        let mut node = Self::ROOT;
+        loop {
+            let d = self.wrap(pos - walk.pos[node]);
+            let d_sq = d.mag_sq();
+
+            let far = walk.size_sq[node] < d_sq * self.t_sq;
+            let children = walk.children[node] as usize;
+            if far || children == 0 {
+                if far {
+                    let denom = d_sq + self.e_sq;
+                    if denom > 0.0 {
+                        potential -= 0.5 * walk.charge[node] * denom.ln();
+                        if self.dipole {
+                            potential += walk.dipole[node].dot(d) / denom;
+                        }
+                    }
+                } else {
+                    let bucket = walk.bucket[node] as usize;
+                    for entry in &self.entries[bucket..bucket + walk.count[node] as usize] {
+                        let denom = self.wrap(pos - entry.pos).mag_sq() + self.e_sq;
+                        if denom > 0.0 {
+                            potential -= 0.5 * entry.charge * denom.ln();
+                        }
+                    }
+                }
+
+                node = walk.next[node] as usize;
+                if node == 0 {
+                    break;
+                }
+            } else {
+                node = children;
+            }
+        }
+
+        potential
+    }
+
+    // Indices of the entries inside [min, max), visiting only the quads that overlap it
+    pub fn query_rect(&self, min: Vec2, max: Vec2, out: &mut Vec<usize>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![Self::ROOT];
+        while let Some(node) = stack.pop() {
+            let n = &self.nodes[node];
+            let half = n.quad.size * 0.5;
+            if n.quad.center.x + half < min.x || n.quad.center.x - half > max.x ||
+                n.quad.center.y + half < min.y || n.quad.center.y - half > max.y {
+                continue;
+            }
+
+            if n.is_branch() {
+                stack.extend(n.children..n.children + 4);
+                continue;
+            }
+
+            for entry in &self.entries[n.bucket..n.bucket + n.count] {
+                let p = entry.pos;
+                if p.x >= min.x && p.x < max.x && p.y >= min.y && p.y < max.y {
+                    out.push(entry.index);
+                }
+            }
+        }
+    }
+
+    // Index of the entry closest to pos, searching quads in order of their distance
+    pub fn nearest(&self, pos: Vec2) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(f32, usize)> = None;
+
+        // Non-negative floats order the same as their bits, so the heap can key on them
+        let mut heap = BinaryHeap::new();
+        heap.push(Reverse((0u32, Self::ROOT)));
+
+        while let Some(Reverse((bits, node))) = heap.pop() {
+            // Every quad left is farther than the best body found so far
+            if let Some((best_sq, _)) = best {
+                if f32::from_bits(bits) > best_sq {
+                    break;
+                }
+            }
+
+            let n = &self.nodes[node];
+            if n.is_branch() {
+                for child in n.children..n.children + 4 {
+                    let d_sq = self.quad_dist_sq(&self.nodes[child].quad, pos);
+                    heap.push(Reverse((d_sq.to_bits(), child)));
+                }
+                continue;
+            }
+
+            for entry in &self.entries[n.bucket..n.bucket + n.count] {
+                let d_sq = (entry.pos - pos).mag_sq();
+                if best.map_or(true, |(best_sq, _)| d_sq < best_sq) {
+                    best = Some((d_sq, entry.index));
+                }
+            }
+        }
+
+        best.map(|(_, index)| index)
+    }
+
+    // Squared distance from pos to the closest point of quad
+    fn quad_dist_sq(&self, quad: &Quad, pos: Vec2) -> f32 {
+        let half = quad.size * 0.5;
+        let dx = ((pos.x - quad.center.x).abs() - half).max(0.0);
+        let dy = ((pos.y - quad.center.y).abs() - half).max(0.0);
+        dx * dx + dy * dy
+    }
+}
+
+// Exact field from every point charge, with the same softening as the tree
+pub fn direct_efield(sources: &[(Vec2, f32)], pos: Vec2, e_sq: f32, period: Option<Vec2>) -> Vec2 {
+    let mut efield = Vec2::zero();
+
+    for &(source_pos, charge) in sources {
+        let mut d = pos - source_pos;
+        if let Some(period) = period {
+            d.x -= period.x * (d.x / period.x).round();
+            d.y -= period.y * (d.y / period.y).round();
+        }
+
+        let denom = d.mag_sq() + e_sq;
+        if denom > 0.0 {
+            efield += d * (charge / denom);
+        }
+    }
+
+    efield
+}
+
+// Z-order index of pos within quad, so sorting by it keeps nearby bodies together
+pub fn morton_code(quad: &Quad, pos: Vec2) -> u32 {
+    let half = quad.size * 0.5;
+    let scale = 65535.0 / quad.size.max(f32::MIN_POSITIVE);
+    let x = ((pos.x - quad.center.x + half) * scale).clamp(0.0, 65535.0) as u32;
+    let y = ((pos.y - quad.center.y + half) * scale).clamp(0.0, 65535.0) as u32;
+
+    return spread_bits(x) | spread_bits(y) << 1;
+}
+
+// Put a zero bit between each of the low 16 bits
+fn spread_bits(mut v: u32) -> u32 {
+    v &= 0xFFFF;
+    v = (v | v << 8) & 0x00FF00FF;
+    v = (v | v << 4) & 0x0F0F0F0F;
+    v = (v | v << 2) & 0x33333333;
+    v = (v | v << 1) & 0x55555555;
+    return v;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tree over the bodies, built the way the simulation builds it
+    fn build(bodies: &[Body]) -> Quadtree {
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+        quadtree.clear(Quad::new_containing(bodies));
+        for (i, body) in bodies.iter().enumerate() {
+            quadtree.insert(body.pos, body.charge, i);
+        }
+        quadtree.propagate();
+        return quadtree;
+    }
+
+    fn sources(bodies: &[Body]) -> Vec<(Vec2, f32)> {
+        return bodies.iter().map(|body| (body.pos, body.charge)).collect();
+    }
+
+    #[test]
+    fn empty_input_gives_a_unit_quad() {
+        let quad = Quad::new_containing(&[]);
+        assert_eq!(quad.center, Vec2::zero());
+        assert_eq!(quad.size, 1.0);
+
+        let quadtree = build(&[]);
+        assert_eq!(quadtree.efield(Vec2::new(3.0, 4.0)), Vec2::zero());
+        assert_eq!(quadtree.potential(Vec2::new(3.0, 4.0)), 0.0);
+    }
+
+    #[test]
+    fn single_body_gives_a_unit_quad_around_it() {
+        let bodies = [Body::new(Vec2::new(2.0, -3.0), 1.0)];
+        let quad = Quad::new_containing(&bodies);
+        assert_eq!(quad.center, bodies[0].pos);
+        assert_eq!(quad.size, 1.0);
+
+        let quadtree = build(&bodies);
+        let pos = Vec2::new(5.0, 1.0);
+        assert!((quadtree.efield(pos) - direct_efield(&sources(&bodies), pos, quadtree.e_sq, None)).mag() < 1e-6);
+    }
+
+    #[test]
+    fn identical_positions_stay_finite() {
+        let bodies = vec![Body::new(Vec2::new(1.0, 1.0), 1.0); 100];
+        let quad = Quad::new_containing(&bodies);
+        assert_eq!(quad.size, 1.0);
+
+        // Bodies past the leaf's capacity stay in the one leaf instead of splitting forever
+        let quadtree = build(&bodies);
+        assert_eq!(quadtree.nodes.len(), 1);
+        assert_eq!(quadtree.nodes[Quadtree::ROOT].count, bodies.len());
+        for pos in [Vec2::new(1.0, 1.0), Vec2::new(4.0, -2.0)] {
+            let efield = quadtree.efield(pos);
+            let expected = direct_efield(&sources(&bodies), pos, quadtree.e_sq, None);
+            assert!(efield.x.is_finite() && efield.y.is_finite());
+            assert!((efield - expected).mag() <= 1e-4 * expected.mag().max(1.0), "{:?} vs {:?}", efield, expected);
+            assert!(quadtree.potential(pos).is_finite());
+        }
+    }
+
+    #[test]
+    fn zero_softening_skips_coincident_bodies() {
+        let bodies = [Body::new(Vec2::new(1.0, 1.0), -1.0), Body::new(Vec2::new(3.0, 1.0), -1.0)];
+        let mut quadtree = build(&bodies);
+        quadtree.set_epsilon(0.0);
+        assert_eq!(quadtree.e_sq, MIN_EPSILON * MIN_EPSILON);
+
+        // A body's own term is 0 / 0; it is skipped rather than turning the whole sum into NaN
+        let efield = quadtree.efield_with(bodies[0].pos, 0.0);
+        let expected = direct_efield(&sources(&bodies), bodies[0].pos, 0.0, None);
+        assert!(efield.x.is_finite() && efield.y.is_finite(), "{:?}", efield);
+        assert!((efield - Vec2::new(0.5, 0.0)).mag() < 1e-6, "{:?}", efield);
+        assert!((expected - Vec2::new(0.5, 0.0)).mag() < 1e-6, "{:?}", expected);
+    }
+
+    #[test]
+    fn nearly_coincident_bodies_stop_at_max_depth() {
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+        quadtree.leaf_capacity = 1;
+        quadtree.clear(Quad::new_bounds(Vec2::new(-1000.0, -1000.0), Vec2::new(1000.0, 1000.0)));
+
+        // Telling these apart would take quads 2000 / 2^34 across, past MAX_DEPTH, so they share a leaf
+        let a = Vec2::new(0.3, 0.7);
+        let b = a + Vec2::new(f32::EPSILON, 0.0);
+        assert_ne!(a, b);
+        quadtree.insert(a, -1.0, 0);
+        quadtree.insert(b, -1.0, 1);
+        quadtree.propagate();
+
+        assert_eq!(quadtree.max_depth, MAX_DEPTH);
+        assert_eq!(quadtree.nodes[Quadtree::ROOT].charge, -2.0);
+        assert!(quadtree.nodes.iter().any(|node| node.count == 2));
+        let efield = quadtree.efield(Vec2::new(5.0, 1.0));
+        assert!(efield.x.is_finite() && efield.y.is_finite());
+
+        let mut found = Vec::new();
+        quadtree.query_rect(a - Vec2::one(), a + Vec2::one(), &mut found);
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1]);
+    }
+
+    // Bodies scattered over a 200 unit square, with a mix of charges
+    fn random_bodies(rng: &mut fastrand::Rng, n: usize) -> Vec<Body> {
+        return (0..n).map(|_| {
+            let mut body = Body::new(Vec2::new(rng.f32() * 200.0 - 100.0, rng.f32() * 200.0 - 100.0), 1.0);
+            body.charge = if rng.bool() { 1.0 } else { -1.0 };
+            body
+        }).collect();
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        for n in [1, 10, 500] {
+            let bodies = random_bodies(&mut rng, n);
+            let quadtree = build(&bodies);
+
+            for _ in 0..100 {
+                let pos = Vec2::new(rng.f32() * 300.0 - 150.0, rng.f32() * 300.0 - 150.0);
+                let distance = |i: usize| (bodies[i].pos - pos).mag_sq();
+                let expected = (0..n).min_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+
+                // Ties can pick either body, so compare distances
+                let found = quadtree.nearest(pos);
+                assert_eq!(found.map(distance), expected.map(distance), "{} bodies, at {:?}", n, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn query_rect_matches_brute_force() {
+        let mut rng = fastrand::Rng::with_seed(2);
+        let bodies = random_bodies(&mut rng, 500);
+        let quadtree = build(&bodies);
+
+        for _ in 0..100 {
+            let a = Vec2::new(rng.f32() * 240.0 - 120.0, rng.f32() * 240.0 - 120.0);
+            let b = Vec2::new(rng.f32() * 240.0 - 120.0, rng.f32() * 240.0 - 120.0);
+            let (min, max) = (a.min_by_component(b), a.max_by_component(b));
+
+            let mut found = Vec::new();
+            quadtree.query_rect(min, max, &mut found);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..bodies.len()).filter(|&i| {
+                let p = bodies[i].pos;
+                p.x >= min.x && p.x < max.x && p.y >= min.y && p.y < max.y
+            }).collect();
+            assert_eq!(found, expected, "rect {:?} to {:?}", min, max);
+        }
+    }
+
+    #[test]
+    fn query_rect_finds_coincident_bodies() {
+        // Stacks of bodies at the same position, and pairs too close to split apart by MAX_DEPTH, among scattered ones
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut bodies = random_bodies(&mut rng, 200);
+        for i in 0..20 {
+            for _ in 0..rng.usize(1..30) {
+                bodies.push(bodies[i]);
+            }
+            let mut near = bodies[i];
+            near.pos.y = 1e-3 * i as f32;
+            bodies.push(near);
+            near.pos.y = f32::from_bits(near.pos.y.to_bits() + 1);
+            bodies.push(near);
+        }
+
+        // One body per leaf, so every stack and pair is past capacity
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+        quadtree.leaf_capacity = 1;
+        quadtree.clear(Quad::new_containing(&bodies));
+        for (i, body) in bodies.iter().enumerate() {
+            quadtree.insert(body.pos, body.charge, i);
+        }
+        quadtree.propagate();
+        assert_eq!(quadtree.max_depth, MAX_DEPTH);
+
+        for _ in 0..100 {
+            let a = Vec2::new(rng.f32() * 240.0 - 120.0, rng.f32() * 240.0 - 120.0);
+            let b = Vec2::new(rng.f32() * 240.0 - 120.0, rng.f32() * 240.0 - 120.0);
+            let (min, max) = (a.min_by_component(b), a.max_by_component(b));
+
+            let mut found = Vec::new();
+            quadtree.query_rect(min, max, &mut found);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..bodies.len()).filter(|&i| {
+                let p = bodies[i].pos;
+                p.x >= min.x && p.x < max.x && p.y >= min.y && p.y < max.y
+            }).collect();
+            assert_eq!(found, expected, "rect {:?} to {:?}", min, max);
+
+            let charge = |indices: &[usize]| indices.iter().map(|&i| bodies[i].charge).sum::<f32>();
+            assert_eq!(charge(&found), charge(&expected));
+        }
+    }
+
+    #[test]
+    fn nearest_in_an_empty_tree_is_none() {
+        assert_eq!(build(&[]).nearest(Vec2::zero()), None);
+    }
+
+    // Mean error of the tree's field at every body against the direct sum, relative to the mean field
+    fn field_error(bodies: &[Body], dipole: bool) -> f32 {
+        let mut quadtree = Quadtree::new(0.75, 1.0);
+        quadtree.dipole = dipole;
+        quadtree.clear(Quad::new_containing(bodies));
+        for (i, body) in bodies.iter().enumerate() {
+            quadtree.insert(body.pos, body.charge, i);
+        }
+        quadtree.propagate();
+
+        let sources = sources(bodies);
+        let (mut error, mut magnitude) = (0.0, 0.0);
+        for body in bodies {
+            let expected = direct_efield(&sources, body.pos, quadtree.e_sq, None);
+            error += (quadtree.efield(body.pos) - expected).mag();
+            magnitude += expected.mag();
+        }
+        return error / magnitude;
+    }
+
+    #[test]
+    fn dipole_terms_reduce_the_error_of_mixed_charges() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let bodies = random_bodies(&mut rng, 2000);
+
+        let monopole = field_error(&bodies, false);
+        let dipole = field_error(&bodies, true);
+        assert!(dipole * 2.0 < monopole, "relative error {} with dipoles vs {} without", dipole, monopole);
+    }
+
+    // Tree over the bodies in a periodic domain centered on the origin
+    fn build_periodic(bodies: &[Body], theta: f32, period: Vec2) -> Quadtree {
+        let mut quadtree = Quadtree::new(theta, 1.0);
+        quadtree.period = Some(period);
+        quadtree.clear(Quad::new_bounds(-period * 0.5, period * 0.5));
+        for (i, body) in bodies.iter().enumerate() {
+            quadtree.insert(body.pos, body.charge, i);
+        }
+        quadtree.propagate();
+        return quadtree;
+    }
+
+    #[test]
+    fn periodic_field_uses_the_nearest_image() {
+        let period = Vec2::new(200.0, 200.0);
+
+        // With theta = 0 every body is summed on its own, so this is exact up to rounding
+        let bodies = random_bodies(&mut fastrand::Rng::with_seed(4), 300);
+        let quadtree = build_periodic(&bodies, 0.0, period);
+        let sources = sources(&bodies);
+        for body in &bodies {
+            let expected = direct_efield(&sources, body.pos, quadtree.e_sq, Some(period));
+            assert!((quadtree.efield(body.pos) - expected).mag() <= 1e-4 * expected.mag().max(1.0), "at {:?}", body.pos);
+        }
+
+        // A pair 2 apart through the wrap, rather than 198 apart across the domain: d = 2 and d² + e² = 5
+        let pair = [Body::new(Vec2::new(-99.0, 0.0), 1.0), Body::new(Vec2::new(99.0, 0.0), 1.0)];
+        let quadtree = build_periodic(&pair, 0.75, period);
+        let efield = quadtree.efield(pair[0].pos);
+        assert!((efield - Vec2::new(2.0 * pair[1].charge / 5.0, 0.0)).mag() < 1e-5, "{:?}", efield);
+    }
+
 }
@@ -1,44 +1,199 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{collections::{HashMap, VecDeque}, sync::atomic::{AtomicBool, Ordering}, time::Instant};
 
-use crate::{
-    body::{self, Body}, plate::{Plate, PlateType}, quadtree::{Node, Quadtree}, simulation, utils
+use efield_sim::{
+    body::{self, Body, Species, ELECTRON_CHARGE, ION_MASS}, plate::{DriveProfile, Plate, PlateTransform, PlateType}, plate_index::PlateIndex, quadtree::{self, Node, Quadtree}, scene::{Annotation, PlateClipboard, Scene}, undo::UndoStack, simulation::{self, Boundary, CursorReading, EditCommand, FieldRequest, FieldSamples, ForceMethod, Instruments, IntegrationMode, Measurements, Parameters, Performance, Probe, ProbeKind, ProbeSample, Readings, ResistModel, SimSnapshot, Stats, StreamlineRequest}, utils::{self, BodyFill}
 };
 
 use quarkstrom::{egui, winit::event::VirtualKeyCode, winit_input_helper::WinitInputHelper};
 
+use crate::{colors::Palette, keymap::{self, Action, Keymap}, screenshot::{Canvas, SCREENSHOT_RESOLUTIONS}};
+
 use palette::{rgb::Rgba, white_point::E, Hsluv, IntoColor};
 use ultraviolet::{Vec2, Vec4};
 
+use crossbeam_channel::{Receiver, Sender};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 
 use stopwatch::Stopwatch;
 
-pub static PAUSED: Lazy<AtomicBool> = Lazy::new(|| false.into());
-pub static SIM_TO_RENDERER_UPDATE_LOCK: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
-pub static RENDERER_TO_SIM_UPDATE_LOCK: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
-pub static BODIES: Lazy<Mutex<Vec<Body>>> = Lazy::new(|| Mutex::new(Vec::new()));
-pub static PLATES: Lazy<Mutex<Vec<Plate>>> = Lazy::new(|| Mutex::new(Vec::new()));
-pub static QUADTREE: Lazy<Mutex<Vec<Node>>> = Lazy::new(|| Mutex::new(Vec::new()));
-pub static DT: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(1.0));
-// pub static QE: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(4.5e-1));
-// pub static QP: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(1.0e-2));
-pub static QE: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(0.56e0));
-pub static QP: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(4.5e-2));
+// Pixels between field arrows, whatever the zoom
+const FIELD_ARROW_SPACING: f32 = 30.0;
+
+// Pixels per heatmap cell; arrows then use every few cells
+const FIELD_HEATMAP_SPACING: f32 = 10.0;
+
+// A trail restarts rather than draw a step longer than this, which happens when
+// a body wraps around a periodic boundary
+const TRAIL_MAX_JUMP: f32 = 10.0;
+
+// Range of the grid size, which [ and ] halve and double
+const MIN_GRID_SIZE: f32 = 1.25;
+const MAX_GRID_SIZE: f32 = 80.0;
+
+// Electron density counts bodies per square of this size, whatever the grid size
+const DENSITY_CELL_SIZE: f32 = 10.0;
+
+// Chevron spacing inside a battery of unit strength, and the most drawn in one plate
+const BATTERY_CHEVRON_SPACING: f32 = 8.0;
+const BATTERY_CHEVRON_MAX: usize = 200;
+
+// Ticks the shown snapshot may lag the simulation before the overlay turns it red
+const SNAPSHOT_AGE_WARNING: usize = 3;
+
+// Plate edits that can be undone, until changed in the settings
+const UNDO_DEPTH: usize = 100;
+
+// Plate sliders, as a whole, to tell which of them moved since the selection was made
+#[derive(Clone, Copy, PartialEq)]
+struct SliderValues {
+    battery: (f32, f32, f32, DriveProfile, f32, f32),
+    direction: f32,
+    resistor: (f32, f32),
+    emitter_rate: f32,
+    epsilon_r: f32,
+}
+
+// Measurements the plot window can follow; the plate ones follow the last single plate selected
+#[derive(Clone, Copy, PartialEq)]
+enum PlotQuantity {
+    Current,
+    BodyCount,
+    KineticEnergy,
+    BatteryStrength,
+}
+
+impl PlotQuantity {
+    const ALL: [PlotQuantity; 4] = [PlotQuantity::Current, PlotQuantity::BodyCount, PlotQuantity::KineticEnergy, PlotQuantity::BatteryStrength];
+
+    fn label(self) -> &'static str {
+        return match self {
+            PlotQuantity::Current => "Plate Current",
+            PlotQuantity::BodyCount => "Body Count",
+            PlotQuantity::KineticEnergy => "Kinetic Energy",
+            PlotQuantity::BatteryStrength => "Battery Strength",
+        };
+    }
+}
+
+// Samples kept by the plot window, until changed there
+const PLOT_LENGTH: usize = 2000;
+
+// A probe's trace in the scope window
+struct ScopeChannel {
+    probe: Probe,
+    name: String,
+    color: [u8; 4],
+    enabled: bool,
+    // Multiplies the values as drawn, so small and large readings can share the axis
+    scale: f32,
+    // (frame, value)
+    samples: VecDeque<[f64; 2]>,
+}
+
+// Which way a channel has to cross the trigger level to start a capture
+#[derive(Clone, Copy, PartialEq)]
+enum TriggerEdge {
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TriggerState {
+    // Channels roll on, dropping their oldest samples
+    FreeRunning,
+    // Channels roll on until the trigger channel crosses the level
+    Armed,
+    // Started at this frame, and fills the channels once before stopping
+    Capturing(usize),
+    Captured(usize),
+}
+
+// Colors given to scope channels in the order they're added
+const SCOPE_COLORS: [[u8; 4]; 6] = [
+    [255, 220, 60, 255],
+    [80, 200, 255, 255],
+    [255, 90, 200, 255],
+    [120, 230, 100, 255],
+    [255, 140, 50, 255],
+    [170, 140, 255, 255],
+];
+
+// Samples kept per scope channel, until changed there
+const SCOPE_LENGTH: usize = 5000;
+
+// Body brushes, used while their key is held and the left button dragged
+#[derive(Clone, Copy, PartialEq)]
+enum Brush {
+    Erase,
+    Paint,
+}
+
+// Brush radius limits in world units, and the sides of its outline
+const MIN_BRUSH_RADIUS: f32 = 1.0;
+const MAX_BRUSH_RADIUS: f32 = 500.0;
+const BRUSH_OUTLINE_SEGMENTS: usize = 48;
+
+// Longest the scale bar and the ruler's tick spacing get, as a fraction of the view's half-height
+const SCALE_BAR_FRACTION: f32 = 0.25;
+
+// Most ticks drawn along the ruler
+const RULER_MAX_TICKS: usize = 500;
+
+// Annotation text height in world units, and the smallest it gets on screen in points
+const ANNOTATION_SIZE: f32 = 6.0;
+const MIN_ANNOTATION_SIZE: f32 = 11.0;
 
+// Pixels from a selected plate's edge that grab it for resizing
+const RESIZE_HANDLE_SIZE: f32 = 6.0;
+
+// Sides of a plate that a resize drag moves
+#[derive(Clone, Copy, PartialEq)]
+struct ResizeEdges {
+    left: bool,
+    right: bool,
+    bottom: bool,
+    top: bool,
+}
+
+// Longest body field vector, as a fraction of the view's half-height
+const BODY_VECTOR_MAX_LENGTH: f32 = 0.05;
+
+// Arc length of each streamline step, and the field below which a line stops
+const STREAMLINE_STEP: f32 = 2.0;
+const STREAMLINE_MIN_FIELD: f32 = 1e-4;
+
+pub static PAUSED: Lazy<AtomicBool> = Lazy::new(|| false.into());
+pub static SHUTDOWN: Lazy<AtomicBool> = Lazy::new(|| false.into());
+pub static CRASH: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+// Scene snapshots from the simulation, and edits going back to it
+pub static SNAPSHOTS: Lazy<(Sender<SimSnapshot>, Receiver<SimSnapshot>)> = Lazy::new(|| crossbeam_channel::bounded(1));
+pub static EDITS: Lazy<(Sender<EditCommand>, Receiver<EditCommand>)> = Lazy::new(crossbeam_channel::unbounded);
 
 pub struct Renderer {
     pos: Vec2,
     scale: f32,
+    // Corners of the visible world rectangle, as of the last input
+    view_min: Vec2,
+    view_max: Vec2,
     settings_window_open: bool,
-    dt: f32,
+    // The simulation's constants and settings, as of the last snapshot plus our own changes
+    parameters: Parameters,
     time: f32,
     sw: Stopwatch,
+    // Time between rendered frames, averaged, and the bodies drawn in the last one
+    frame_sw: Stopwatch,
+    frame_time: f32,
+    bodies_rendered: usize,
 
     // Gui
+    show_overlay: bool,
     show_bodies: bool,
     show_plates: bool,
     show_quadtree: bool,
+    show_drift: bool,
+    show_field: bool,
+    show_heatmap: bool,
     last_flow_count: f32,
     flow_count: f32,
 
@@ -47,17 +202,200 @@ pub struct Renderer {
     pub bodies: Vec<Body>,
     pub plates: Vec<Plate>,
     quadtree: Vec<Node>,
+    measurements: Measurements,
+    // What the simulation is asked to measure, and what it was last sent
+    instruments: Instruments,
+    sent_instruments: Instruments,
+    // The simulation's readings and timings as of the last snapshot, and when that was taken
+    readings: Readings,
+    performance: Performance,
+    field_backend: String,
+    snapshot_taken: Instant,
+    // Field sampled over the view, when show_field or show_heatmap is on
+    field: Option<FieldSamples>,
+    // Scene file for the save and load buttons, and how the last attempt went
+    scene_path: String,
+    scene_status: Option<String>,
+    // Key for each action, the action waiting for its next key press, and how the last load or save went
+    keymap: Keymap,
+    rebinding: Option<Action>,
+    keymap_status: Option<String>,
+
+    // Scene colors, and how the last load or save of the settings file went
+    palette: Palette,
+    palette_status: Option<String>,
+
+    // Window size in pixels as of the last input, the resolution multiple of the screenshot to take on the
+    // next render, and how the last one went
+    window_size: (u16, u16),
+    screenshot_resolution: u32,
+    pending_screenshot: Option<u32>,
+    screenshot_status: Option<String>,
+    // Seed shared with the simulation, and the generator used to place new bodies
+    seed: u64,
+    rng: fastrand::Rng,
+    stats: Stats,
+    // Simulation frame of the last snapshot
+    frame: usize,
+    // Edits sent to the simulation so far, to tell when a snapshot includes them
+    edits_sent: u64,
     
     // Editing
     remove_selection: bool,
     setting_plate: Option<PlateType>,
+    // Cell a shift-drag of the selected plates started from, and the offset to move them by once released
+    move_start: Option<Vec2>,
+    pending_move: Option<Vec2>,
+    // Edges of the single selected plate under the cursor, the plate and edges being dragged,
+    // where they are now, and the bounds to give the plate once released
+    resize_hover: Option<ResizeEdges>,
+    resize_drag: Option<(usize, ResizeEdges)>,
+    resize_bounds: (Vec2, Vec2),
+    pending_resize: Option<(usize, Vec2, Vec2)>,
+    // Ctrl+D copies the selected plates next to themselves, with their bodies if asked
+    duplicate_selection: bool,
+    duplicate_bodies: bool,
+    // Clipboard text to paste at the hovered cell
+    pending_paste: Option<String>,
+    // Turn or flip to give the selected plates
+    pending_transform: Option<PlateTransform>,
+    // Plate edits that Ctrl+Z and Ctrl+Shift+Z step through
+    history: UndoStack,
+    undo_requested: bool,
+    redo_requested: bool,
     battery_strength: f32,
+    battery_direction: f32,
+    battery_frequency: f32,
+    battery_phase: f32,
+    battery_profile: DriveProfile,
+    battery_gain: f32,
+    battery_target: f32,
     resistor_strength: f32,
+    resistor_alpha: f32,
+    emitter_rate: f32,
+    epsilon_r: f32,
+    plate_charge_scale: f32,
+    plate_charge_scale_changed: bool,
+    // Name of the single selected plate
+    plate_name: String,
+    plate_name_changed: bool,
+    show_labels: bool,
+    show_plate_list: bool,
+
+    // Plot of one measurement over the simulation frames, as (frame, value)
+    show_plot: bool,
+    plot_quantity: PlotQuantity,
+    plot_samples: VecDeque<[f64; 2]>,
+    plot_length: usize,
+    plot_paused: bool,
+    // Plate the plate quantities are read from
+    plot_plate: Option<u32>,
+    plot_path: String,
+    plot_status: Option<String>,
+
+    // Scope of the probes read every step, sharing a time axis in steps
+    show_scope: bool,
+    scope_channels: Vec<ScopeChannel>,
+    next_probe_id: u32,
+    scope_length: usize,
+    scope_paused: bool,
+    // Probe id of the channel that triggers a capture, and the level it has to cross
+    trigger_channel: Option<u32>,
+    trigger_level: f32,
+    trigger_edge: TriggerEdge,
+    trigger_state: TriggerState,
+    // Trigger channel's previous reading, to catch it crossing the level
+    trigger_last: Option<f32>,
     selected_plate_indicies: Vec<usize>,
+    // Sliders as the selection loaded them; only sliders moved since then are applied, so a mixed selection
+    // keeps its differences until one is dragged
+    selection_sliders: SliderValues,
+    mixed_settings: bool,
+    // Shift-drag adds to the selection instead of replacing it
+    additive_selection: bool,
+    // Brush radius in world units, changed by scrolling while a brush key is held
+    brush_radius: f32,
+    // Brush under the cursor, for its outline
+    brush_cursor: Option<(Brush, Vec2)>,
+    // Where the brush was last frame, so a fast drag still sweeps a continuous stroke
+    brush_last: Option<Vec2>,
+    // This frame's stroke, applied in update_objects as a single edit
+    pending_brush: Option<(Brush, Vec2, Vec2)>,
+    // Deleting or pinning the inspected body, and why the last one did nothing
+    pending_body_edit: Option<EditCommand>,
+    body_edit_failure: Option<String>,
     body_density: usize,
+    // New plates are filled more thinly, or not at all, to stay under this
+    max_bodies: usize,
+    body_warning: Option<String>,
+    decimate_target: usize,
+    body_charge: f32,
+    body_fill: BodyFill,
+    ion_mass: f32,
+
+    // Fixed point charges
+    charge_mode: bool,
+    point_charge: f32,
+    pending_charges: Vec<Vec2>,
+
+    // Voltage probe
+    probe_mode: bool,
+    probe_points: Vec<Vec2>,
+
+    // Ruler, which stays up after the tool is put away until it's cleared
+    ruler_mode: bool,
+    ruler_points: Vec<Vec2>,
+
+    // Text labels in the world, saved with the scene
+    annotation_mode: bool,
+    annotations: Vec<Annotation>,
+    // World bounds of each annotation's text as last drawn, for picking them
+    annotation_bounds: Vec<(Vec2, Vec2)>,
+    selected_annotation: Option<usize>,
+    // The annotation whose text is being typed, and whether its field still needs focus
+    editing_annotation: Option<usize>,
+    focus_annotation: bool,
+    // Annotation being dragged, and its offset from the cursor
+    annotation_drag: Option<(usize, Vec2)>,
+    // A text field had the keyboard last frame, so keys are typing rather than shortcuts
+    typing: bool,
+    // The pointer was over a window last frame, e.g. the annotation popup's buttons
+    pointer_on_gui: bool,
+
+    // Cursor's world position and grid cell, and a scale bar, in the top right corner
+    show_coordinates: bool,
+    cursor: Option<Vec2>,
+
+    // Field lines, seeded from clicks and optionally along the battery edges
+    show_streamlines: bool,
+    streamline_mode: bool,
+    streamline_seeds: Vec<Vec2>,
+    streamline_batteries: bool,
+    // Seeds per grid cell of battery edge
+    streamline_density: f32,
+    streamline_steps: usize,
+    streamlines: Vec<Vec<Vec2>>,
+
+    // Field, potential and plate under the cursor
+    show_readout: bool,
+    readout: Option<CursorReading>,
+
+    // Recent positions of the bodies whose id is a multiple of trail_stride, by id, oldest first
+    show_trails: bool,
+    trail_length: usize,
+    trail_stride: usize,
+    trails: HashMap<u64, VecDeque<Vec2>>,
+
+    // Line along each visible body's field, scaled linearly or by log(1 + |E|)
+    show_body_vectors: bool,
+    body_vector_scale: f32,
+    body_vector_log: bool,
+    body_vector_min: f32,
 
     // Selection
     grid_size: f32,
+    // Off to select from and to the exact mouse position
+    snap_to_grid: bool,
     hovered_cell: Vec2,
     cell_start: Vec2,
     cell_end: Vec2,
@@ -74,10 +412,34 @@ impl Renderer {
         let max = Vec2::new(
             self.cell_start.x.max(self.cell_end.x),
             self.cell_start.y.max(self.cell_end.y),
-        ) + Vec2::one() * self.grid_size;
+        ) + Vec2::one() * self.cell_extent();
         return (min, max);
     }
 
+    // How far a selection reaches past its last cell: a whole cell when snapping, nothing otherwise
+    fn cell_extent(&self) -> f32 {
+        return if self.snap_to_grid { self.grid_size } else { 0.0 };
+    }
+
+    // Select [min, max), grown out to whole cells when snapping
+    fn set_selection(&mut self, min: Vec2, max: Vec2) {
+        if self.snap_to_grid {
+            self.cell_start = (min / self.grid_size).map(|t| t.floor()) * self.grid_size;
+            self.cell_end = (max / self.grid_size).map(|t| t.ceil() - 1.0).max_by_component(self.cell_start / self.grid_size) * self.grid_size;
+        } else {
+            self.cell_start = min;
+            self.cell_end = max;
+        }
+    }
+
+    // Change the grid, keeping the selection over the same region; plates stay where they are
+    fn set_grid(&mut self, grid_size: f32, snap_to_grid: bool) {
+        let (min, max) = self.get_selection();
+        self.grid_size = grid_size.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+        self.snap_to_grid = snap_to_grid;
+        self.set_selection(min, max);
+    }
+
     fn get_selected_plate_indicies(&self) -> Vec<usize> {
         let mut selected = Vec::new();
         let margin = 1.0;
@@ -100,6 +462,162 @@ impl Renderer {
         return selected;
     }
 
+    // Point the sliders at a plate's settings, since they're applied to the selected plates every frame
+    fn load_plate_settings(&mut self, idx: usize) {
+        let plate = self.plates[idx].clone();
+        self.plate_charge_scale = plate.charge_scale;
+        self.plate_name = plate.name.clone();
+
+        match plate.plate_type {
+            PlateType::Battery => {
+                self.battery_strength = plate.battery_strength();
+                self.battery_direction = plate.direction.to_degrees();
+                self.battery_frequency = plate.frequency;
+                self.battery_phase = plate.phase;
+                self.battery_profile = plate.profile;
+                self.battery_gain = plate.gain;
+                self.battery_target = plate.target_current;
+            },
+            PlateType::Resistor => {
+                self.resistor_strength = plate.resist;
+                self.resistor_alpha = plate.alpha;
+            },
+            PlateType::Diode => {
+                self.battery_direction = plate.direction.to_degrees();
+            },
+            PlateType::Emitter => {
+                self.emitter_rate = plate.emit_rate;
+            },
+            PlateType::Dielectric => {
+                self.epsilon_r = plate.epsilon_r;
+            },
+            _ => {}
+        }
+    }
+
+    fn slider_values(&self) -> SliderValues {
+        return SliderValues {
+            battery: (self.battery_strength, self.battery_frequency, self.battery_phase, self.battery_profile, self.battery_gain, self.battery_target),
+            direction: self.battery_direction,
+            resistor: (self.resistor_strength, self.resistor_alpha),
+            emitter_rate: self.emitter_rate,
+            epsilon_r: self.epsilon_r,
+        };
+    }
+
+    // Load the sliders from the selected plates, noting when plates of the same type disagree
+    fn load_selection_settings(&mut self) {
+        self.mixed_settings = false;
+        for (n, &idx) in self.selected_plate_indicies.iter().enumerate() {
+            let plate = &self.plates[idx];
+            let earlier = self.selected_plate_indicies[..n].iter().map(|&i| &self.plates[i]);
+            self.mixed_settings |= earlier
+                .filter(|other| other.plate_type == plate.plate_type)
+                .any(|other| !same_settings(plate, other));
+        }
+
+        for idx in self.selected_plate_indicies.clone() {
+            self.load_plate_settings(idx);
+        }
+        self.selection_sliders = self.slider_values();
+    }
+
+    // Select one plate, the same as dragging a selection over just it
+    fn select_plate(&mut self, idx: usize) {
+        let (min, max) = (self.plates[idx].min, self.plates[idx].max);
+        self.set_selection(min, max);
+        self.selection_active = true;
+        self.selected_plate_indicies = vec![idx];
+        self.load_selection_settings();
+    }
+
+    // Select every plate, with the selection around all of them
+    fn select_all(&mut self) {
+        if self.plates.is_empty() {
+            return;
+        }
+
+        let min = self.plates.iter().fold(Vec2::broadcast(f32::MAX), |min, plate| min.min_by_component(plate.min));
+        let max = self.plates.iter().fold(Vec2::broadcast(f32::MIN), |max, plate| max.max_by_component(plate.max));
+        self.set_selection(min, max);
+        self.selection_active = true;
+        self.selected_plate_indicies = (0..self.plates.len()).collect();
+        self.load_selection_settings();
+    }
+
+    // Whether the selected plates can move by offset without landing on any other plate
+    fn can_move_selection(&self, offset: Vec2) -> bool {
+        return self.selected_plate_indicies.iter().all(|&i| {
+            let mut moved = self.plates[i].clone();
+            moved.min += offset;
+            moved.max += offset;
+
+            self.plates.iter().enumerate().all(|(j, other)| self.selected_plate_indicies.contains(&j) || !moved.overlaps(other))
+        });
+    }
+
+    // The selected plates as clipboard JSON, placed relative to the selection's min corner
+    fn copy_selection(&self) -> String {
+        let (min, _) = self.get_selection();
+        let plates = self.selected_plate_indicies.iter().map(|&i| self.plates[i].duplicate(-min)).collect();
+        return PlateClipboard { plates }.to_json();
+    }
+
+    // Turn or flip the selected plates and the bodies in them, unless they'd land on other plates
+    fn transform_selection(&mut self, transform: PlateTransform) {
+        let selected = &self.selected_plate_indicies;
+        if selected.is_empty() {
+            return;
+        }
+
+        let min = selected.iter().fold(Vec2::broadcast(f32::MAX), |min, &i| min.min_by_component(self.plates[i].min));
+        let max = selected.iter().fold(Vec2::broadcast(f32::MIN), |max, &i| max.max_by_component(self.plates[i].max));
+        let center = if self.snap_to_grid { transform.snap_center((min + max) * 0.5, self.grid_size) } else { (min + max) * 0.5 };
+
+        let blocked = selected.iter().any(|&i| {
+            let moved = self.plates[i].transformed(transform, center);
+            self.plates.iter().enumerate().any(|(j, other)| !selected.contains(&j) && moved.overlaps(other))
+        });
+        if blocked {
+            self.body_warning = Some("Plates not turned: other plates are in the way".to_string());
+            return;
+        }
+
+        let ids = selected.iter().map(|&i| self.plates[i].id).collect();
+        self.make_edit(EditCommand::TransformPlates { ids, center, transform });
+
+        // The selection follows the plates
+        let (start, end) = self.get_selection();
+        let (a, b) = (transform.apply_point(start, center), transform.apply_point(end, center));
+        self.set_selection(a.min_by_component(b), a.max_by_component(b));
+
+        // The sliders take the new directions so they don't turn the plates back
+        self.load_selection_settings();
+    }
+
+    // Offset that puts copies of the selected plates one cell past them, to the right or else below
+    fn duplicate_offset(&self) -> Option<Vec2> {
+        let selected = &self.selected_plate_indicies;
+        let min = selected.iter().fold(Vec2::broadcast(f32::MAX), |min, &i| min.min_by_component(self.plates[i].min));
+        let max = selected.iter().fold(Vec2::broadcast(f32::MIN), |max, &i| max.max_by_component(self.plates[i].max));
+        let size = max - min + Vec2::broadcast(self.grid_size);
+
+        return [Vec2::new(size.x, 0.0), Vec2::new(0.0, -size.y)].into_iter().find(|&offset| {
+            selected.iter().all(|&i| {
+                let copy = self.plates[i].duplicate(offset);
+                self.plates.iter().all(|other| !copy.overlaps(other))
+            })
+        });
+    }
+
+    // Center the view on a plate with some room around it
+    fn zoom_to_plate(&mut self, idx: usize) {
+        let plate = &self.plates[idx];
+        let half = (plate.max - plate.min) * 0.5;
+        self.pos = plate.min + half;
+        self.scale = half.x.max(half.y).max(self.grid_size) * 1.5;
+    }
+
     fn deselect_all(&mut self) {
         self.selected_plate_indicies.clear();
         self.remove_selection = false;
@@ -109,6 +627,7 @@ impl Renderer {
     fn update_flow_rate(&mut self) {
         if self.selected_plate_indicies.len() == 1 {
             let plate = &self.plates[self.selected_plate_indicies[0]];
+            let index = PlateIndex::new(&self.plates, self.grid_size);
     
             for i in 0..self.bodies.len() {
                 let body = &mut self.bodies[i];
@@ -116,7 +635,7 @@ impl Renderer {
 
                 if !body_in_plate { continue; }
 
-                let new_pos = simulation::get_new_pos_clip(body, &self.plates, self.dt);
+                let new_pos = simulation::get_new_pos_clip(body, &self.plates, &index, self.parameters.dt, self.parameters.integration_mode);
                 let body_to_plate = plate.contains_point(new_pos);
 
                 if body_in_plate != body_to_plate {
@@ -126,487 +645,3278 @@ impl Renderer {
         }
     }
 
-    fn update_objects(&mut self) -> bool {
-        let mut updated = false;
-        let plate_type = self.setting_plate.take(); // take the value out of self.setting_plate
+    // Add the plotted quantity for the newest snapshot, switching to the selected plate if there's one
+    fn sample_plot(&mut self) {
+        if !self.show_plot || self.plot_paused {
+            return;
+        }
 
-        // Removing plates
-        if self.remove_selection {
-            for i in self.selected_plate_indicies.iter().rev() {
-                let plate = &self.plates[*i];
+        if let Some(plate) = self.selected_plate_indicies.first().filter(|_| self.selected_plate_indicies.len() == 1).map(|&i| &self.plates[i]) {
+            if self.plot_plate != Some(plate.id) {
+                self.plot_plate = Some(plate.id);
+                self.plot_samples.clear();
+            }
+        }
+        let plate = self.plates.iter().position(|plate| Some(plate.id) == self.plot_plate);
+
+        let value = match self.plot_quantity {
+            PlotQuantity::Current => self.plot_plate.and_then(|id| self.measurements.currents.get(&id).copied()),
+            PlotQuantity::BodyCount => Some(self.bodies.len() as f32),
+            PlotQuantity::KineticEnergy => Some(self.stats.kinetic_energy),
+            PlotQuantity::BatteryStrength => plate.map(|i| &self.plates[i]).filter(|plate| plate.plate_type == PlateType::Battery).map(|plate| {
+                if plate.gain > 0.0 { plate.regulated_emf } else { plate.battery_strength() }
+            }),
+        };
+        let Some(value) = value else { return };
+
+        // Snapshots keep coming while the simulation is paused; a rewind or a restart goes back in frames,
+        // which would fold the line over itself
+        let frame = self.frame as f64;
+        match self.plot_samples.back() {
+            Some(&[last, _]) if last == frame => return,
+            Some(&[last, _]) if last > frame => self.plot_samples.clear(),
+            _ => {},
+        }
+        self.plot_samples.push_back([frame, value as f64]);
+        while self.plot_samples.len() > self.plot_length {
+            self.plot_samples.pop_front();
+        }
+    }
 
-                // Remove bodies in plates
-                for j in (0..self.bodies.len()).rev() {
-                    let body = &mut self.bodies[j];
-                    if plate.contains_point(body.pos) {
-                        self.bodies.remove(j);
-                    }
-                }
+    // Add the probe readings from every step since the last snapshot, watching for the trigger
+    fn sample_scope(&mut self, samples: Vec<ProbeSample>) {
+        if !self.show_scope || self.scope_paused {
+            return;
+        }
 
-                // Remove plate
-                self.plates.remove(*i);
+        for sample in samples {
+            if matches!(self.trigger_state, TriggerState::Captured(_)) {
+                return;
             }
-            
-            self.deselect_all();
-            updated = true;
-        }
 
-        // Adding plates / Changing plate type
-        if let Some(plate_type) = plate_type {
-            if self.selected_plate_indicies.len() > 0 {
-                // Change the type of the plate
-                for i in 0..self.selected_plate_indicies.len() {
-                    let idx = self.selected_plate_indicies[i];
-                    let plate = &mut self.plates[idx];
-                    match plate_type {
-                        PlateType::Normal => plate.make_normal(),
-                        PlateType::Battery => plate.make_battery(self.battery_strength),
-                        PlateType::Resistor => plate.make_resistor(self.resistor_strength),
+            let reading = |id: u32| sample.values.iter().find(|&&(probe, _)| probe == id).map(|&(_, value)| value);
+
+            // Start the capture from the sample that crossed the level
+            if self.trigger_state == TriggerState::Armed {
+                if let Some(value) = self.trigger_channel.and_then(reading) {
+                    let level = self.trigger_level;
+                    let crossed = self.trigger_last.is_some_and(|last| match self.trigger_edge {
+                        TriggerEdge::Rising => last < level && value >= level,
+                        TriggerEdge::Falling => last > level && value <= level,
+                    });
+                    self.trigger_last = Some(value);
+
+                    if crossed {
+                        self.trigger_state = TriggerState::Capturing(sample.frame);
+                        for channel in &mut self.scope_channels {
+                            channel.samples.clear();
+                        }
                     }
                 }
-            } else {
-                // Create a new plate
-                let (min, mut max) = self.get_selection();
-                let mut plate = Plate::new(min, max);
-                match plate_type {
-                    PlateType::Normal => plate.make_normal(),
-                    PlateType::Battery => plate.make_battery(self.battery_strength),
-                    PlateType::Resistor => plate.make_resistor(self.resistor_strength),
-                }
-                self.plates.push(plate);
+            }
 
-                let area = (max.x - min.x) * (max.y - min.y) / (self.grid_size * self.grid_size);
-                let bodies_to_add = area * self.body_density as f32;
-                let margin = self.grid_size * 0.1;
+            let frame = sample.frame as f64;
+            for channel in &mut self.scope_channels {
+                let Some(value) = reading(channel.probe.id) else { continue };
 
-                for _ in 0..(bodies_to_add as usize) {
-                    let pos = Vec2::new(
-                        utils::random_in_range(min.x + margin, max.x - margin),
-                        utils::random_in_range(min.y + margin, max.y - margin),
-                    );
-                    let mut body = Body::new(pos, 1.0);
-                    self.bodies.push(body);
+                // A restarted simulation goes back in frames, which would fold the line over itself
+                if channel.samples.back().is_some_and(|&[last, _]| last >= frame) {
+                    channel.samples.clear();
+                }
+                channel.samples.push_back([frame, value as f64]);
+                while channel.samples.len() > self.scope_length {
+                    channel.samples.pop_front();
                 }
             }
-            
-            self.deselect_all();
-            updated = true;
-        }
 
-        // Changing plate strengths
-        if self.selection_active {
-            for i in 0..self.selected_plate_indicies.len() {
-                let idx = self.selected_plate_indicies[i];
-                let plate = &mut self.plates[idx];
-                
-                match plate.plate_type {
-                    PlateType::Battery => {
-                        let old_efield = plate.efield.clone();
-                        plate.make_battery(self.battery_strength);
-                        updated |= old_efield != plate.efield;
-                    },
-                    PlateType::Resistor => {
-                        let old_resist = plate.resist;
-                        plate.make_resistor(self.resistor_strength);
-                        updated |= old_resist != plate.resist;
-                    },
-                    _ => {}
+            if let TriggerState::Capturing(start) = self.trigger_state {
+                if sample.frame + 1 >= start + self.scope_length {
+                    self.trigger_state = TriggerState::Captured(start);
                 }
             }
         }
+    }
+
+    // Give a probe the next id and a channel in the scope
+    fn add_scope_channel(&mut self, kind: ProbeKind) {
+        let id = self.next_probe_id;
+        self.next_probe_id += 1;
 
-        return updated;
+        let name = match kind {
+            ProbeKind::Voltage { .. } => format!("#{} Voltage", id),
+            ProbeKind::Ammeter { plate } => format!("#{} Current, Plate #{}", id, plate),
+            ProbeKind::FluxLoop { .. } => format!("#{} Flux/2π", id),
+        };
+        self.scope_channels.push(ScopeChannel {
+            probe: Probe { id, kind },
+            name,
+            color: SCOPE_COLORS[id as usize % SCOPE_COLORS.len()],
+            enabled: true,
+            scale: 1.0,
+            samples: VecDeque::new(),
+        });
     }
-}
 
-impl quarkstrom::Renderer for Renderer {
-    fn new() -> Self {
-        Self {
-            pos: Vec2::zero(),
-            scale: 100.0,
-            settings_window_open: false,
-            dt: 1.0,
-            time: 0.0,
-            sw: Stopwatch::start_new(),
-            show_bodies: true,
-            show_plates: true,
-            show_quadtree: false,
-            last_flow_count: 0.0,
-            flow_count: 0.0,
-            depth_range: (0, 0),
-            bodies: Vec::new(),
-            plates: Vec::new(),
-            quadtree: Vec::new(),
-            remove_selection: false,
-            setting_plate: None,
-            battery_strength: 1.0,
-            resistor_strength: 0.5,
-            selected_plate_indicies: Vec::new(),
-            body_density: 4,
-            grid_size: 10.0,
-            hovered_cell: Vec2::zero(),
-            cell_start: Vec2::zero(),
-            cell_end: Vec2::zero(),
-            selection_active: false,
-            mouse_down: false,
+    // Write the plotted samples as frame,value rows
+    fn export_plot(&mut self) {
+        let mut text = format!("frame,{}\n", self.plot_quantity.label());
+        for &[frame, value] in &self.plot_samples {
+            text += &format!("{},{}\n", frame, value);
         }
-    }
 
-    fn input(&mut self, input: &WinitInputHelper, width: u16, height: u16) {
-        self.settings_window_open ^= input.key_pressed(VirtualKeyCode::E);
+        self.plot_status = Some(match std::fs::write(&self.plot_path, text) {
+            Ok(()) => format!("Exported {} samples to {}", self.plot_samples.len(), self.plot_path),
+            Err(err) => format!("Couldn't export {}: {}", self.plot_path, err),
+        });
+    }
 
-        if input.key_pressed(VirtualKeyCode::Space) {
-            let val = PAUSED.load(Ordering::Relaxed);
-            PAUSED.store(!val, Ordering::Relaxed)
+    // Smoothed current through the single selected plate
+    fn selected_current(&self) -> Option<(usize, f32)> {
+        if self.selected_plate_indicies.len() != 1 {
+            return None;
         }
 
-        if let Some((mx, my)) = input.mouse() {
-            // Scroll steps to double/halve the scale
-            let steps = 5.0;
-
-            // Modify input
-            let zoom = (-input.scroll_diff() / steps).exp2();
+        let idx = self.selected_plate_indicies[0];
+        let current = *self.measurements.currents.get(&self.plates.get(idx)?.id)?;
+        return Some((idx, current));
+    }
 
-            // Screen space -> view space
-            let target =
-                Vec2::new(mx * 2.0 - width as f32, height as f32 - my * 2.0) / height as f32;
+    // Line along the field on each body in view, skipping weak fields and capping the length
+    fn draw_body_vectors(&self, ctx: &mut Canvas) {
+        let max_length = self.scale * BODY_VECTOR_MAX_LENGTH;
 
-            // Move view position based on target
-            self.pos += target * self.scale * (1.0 - zoom);
+        for body in &self.bodies {
+            let pos = body.pos;
+            if pos.x < self.view_min.x || pos.y < self.view_min.y || pos.x > self.view_max.x || pos.y > self.view_max.y {
+                continue;
+            }
 
-            // Zoom
-            self.scale *= zoom;
-        }
+            let mag = body.efield.mag();
+            if !(mag >= self.body_vector_min && mag.is_finite()) {
+                continue;
+            }
 
-        // Grab
-        if input.mouse_held(2) {
-            let (mdx, mdy) = input.mouse_diff();
-            self.pos.x -= mdx / height as f32 * self.scale * 2.0;
-            self.pos.y += mdy / height as f32 * self.scale * 2.0;
+            let length = if self.body_vector_log { mag.ln_1p() } else { mag } * self.body_vector_scale;
+            ctx.draw_line(pos, pos + body.efield / mag * length.min(max_length), [0xff, 0x00, 0x00, 0xff]);
         }
+    }
 
-        let world_mouse = || -> Vec2 {
-            let (mx, my) = input.mouse().unwrap_or_default();
-            let mut mouse = Vec2::new(mx, my);
-            mouse *= 2.0 / height as f32;
-            mouse.y -= 1.0;
-            mouse.y *= -1.0;
-            mouse.x -= width as f32 / height as f32;
-            mouse * self.scale + self.pos
-        };
-
-        self.hovered_cell = Vec2::new(
-            (world_mouse().x / self.grid_size).floor() * self.grid_size,
-            (world_mouse().y / self.grid_size).floor() * self.grid_size,
-        );
+    // Add the newest positions to the trails, keeping trail_length of them and dropping the trails of bodies
+    // that are gone
+    fn update_trails(&mut self) {
+        let stride = self.trail_stride.max(1) as u64;
+        let mut trails = HashMap::with_capacity(self.trails.len());
+
+        for body in self.bodies.iter().filter(|body| body.id % stride == 0) {
+            let mut trail = self.trails.remove(&body.id).unwrap_or_default();
+            if let Some(&last) = trail.back() {
+                if (body.pos - last).mag_sq() > TRAIL_MAX_JUMP * TRAIL_MAX_JUMP {
+                    trail.clear();
+                }
+            }
 
-        // Selection
-        if input.mouse_pressed(0) {
-            self.mouse_down = true;
-            self.cell_start.x = self.hovered_cell.x;
-            self.cell_start.y = self.hovered_cell.y;
-            self.selection_active = true;
-            self.selected_plate_indicies = Vec::new();
+            trail.push_back(body.pos);
+            while trail.len() > self.trail_length {
+                trail.pop_front();
+            }
+            trails.insert(body.id, trail);
         }
 
-        if input.mouse_pressed(1) {
-            self.selection_active = false;
-            self.selected_plate_indicies = Vec::new();
+        self.trails = trails;
+    }
+
+    // Streamline seeds spread along the outside of every battery's edges
+    fn battery_seeds(&self) -> Vec<Vec2> {
+        let spacing = self.grid_size / self.streamline_density;
+        let margin = Vec2::broadcast(STREAMLINE_STEP * 0.5);
+
+        let mut seeds = Vec::new();
+        for plate in self.plates.iter().filter(|plate| plate.plate_type == PlateType::Battery) {
+            let min = plate.min - margin;
+            let max = plate.max + margin;
+            let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+
+            for i in 0..4 {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                let count = ((b - a).mag() / spacing).round().max(1.0) as usize;
+                for k in 0..count {
+                    seeds.push(a + (b - a) * ((k as f32 + 0.5) / count as f32));
+                }
+            }
         }
 
-        if input.mouse_released(0) {
-            self.mouse_down = false;
+        seeds
+    }
 
-            if self.selection_active {
-                self.selected_plate_indicies = self.get_selected_plate_indicies();
+    fn deselect_annotation(&mut self) {
+        self.selected_annotation = None;
+        self.editing_annotation = None;
+        self.annotation_drag = None;
+    }
 
-                if self.selected_plate_indicies.len() == 1 {
-                    let plate = self.plates[self.selected_plate_indicies[0]];
+    fn remove_annotation(&mut self, idx: usize) {
+        self.annotations.remove(idx);
+        self.deselect_annotation();
+    }
 
-                    match plate.plate_type {
-                        PlateType::Battery => {
-                            if plate.efield.x == 0.0 {
-                                self.battery_strength = plate.efield.y;
-                            } else {
-                                self.battery_strength = plate.efield.x;
-                            }
-                        },
-                        PlateType::Resistor => {
-                            self.resistor_strength = plate.resist;
-                        },
-                        _ => {}
-                    }
-                }
-            }
+    // Stop editing an annotation, dropping it if it was left empty
+    fn finish_annotation(&mut self) {
+        let Some(idx) = self.editing_annotation.take() else { return };
+        if self.annotations[idx].text.trim().is_empty() {
+            self.remove_annotation(idx);
         }
+    }
 
-        if input.mouse_held(0) {
-            self.cell_end.x = self.hovered_cell.x;
-            self.cell_end.y = self.hovered_cell.y;
-        }
+    // Convert a world position to egui screen coordinates
+    fn world_to_screen(&self, ctx: &egui::Context, pos: Vec2) -> egui::Pos2 {
+        let rect = ctx.screen_rect();
+        let half_height = rect.height() * 0.5;
+        let view = (pos - self.pos) / self.scale;
 
-        if input.key_pressed(VirtualKeyCode::Back) {
-            if self.selection_active {
-                self.remove_selection = true;
-                self.selection_active = false;
-            }
-        }
+        return egui::pos2(
+            rect.center().x + view.x * half_height,
+            rect.center().y - view.y * half_height,
+        );
+    }
 
-        if input.key_pressed(VirtualKeyCode::Key1) {
-            self.setting_plate = Some(PlateType::Normal);
+    // Convert egui screen coordinates to a world position
+    fn screen_to_world(&self, ctx: &egui::Context, pos: egui::Pos2) -> Vec2 {
+        let rect = ctx.screen_rect();
+        let half_height = rect.height() * 0.5;
+        let view = Vec2::new(pos.x - rect.center().x, rect.center().y - pos.y) / half_height;
+
+        return self.pos + view * self.scale;
+    }
+
+    fn set_plate_type(&self, plate: &mut Plate, plate_type: PlateType) {
+        match plate_type {
+            PlateType::Normal => plate.make_normal(),
+            PlateType::Battery => {
+                plate.make_ac_battery(self.battery_strength, self.battery_direction.to_radians(), self.battery_frequency, self.battery_phase);
+                plate.profile = self.battery_profile;
+                plate.gain = self.battery_gain;
+                plate.target_current = self.battery_target;
+            },
+            PlateType::Resistor => plate.make_resistor(self.resistor_strength, self.resistor_alpha),
+            PlateType::Capacitor => plate.make_capacitor(),
+            PlateType::Diode => plate.make_diode(self.battery_direction.to_radians()),
+            PlateType::Sink => plate.make_sink(),
+            PlateType::Emitter => plate.make_emitter(self.emitter_rate),
+            PlateType::Insulator => plate.make_insulator(),
+            PlateType::Dielectric => plate.make_dielectric(self.epsilon_r),
         }
+    }
 
-        if input.key_pressed(VirtualKeyCode::Key2) {
-            self.setting_plate = Some(PlateType::Battery);
+    // Send an edit to the simulation, which owns the scene
+    fn send_edit(&mut self, edit: EditCommand) {
+        if edit.changes_scene() {
+            self.edits_sent += 1;
         }
+        let _ = EDITS.0.send(edit);
+    }
 
-        if input.key_pressed(VirtualKeyCode::Key3) {
-            self.setting_plate = Some(PlateType::Resistor);
+    // Tell the simulation what to measure, when that changed this frame
+    fn send_instruments(&mut self) {
+        if self.instruments != self.sent_instruments {
+            self.sent_instruments = self.instruments.clone();
+            self.send_edit(EditCommand::SetInstruments(self.instruments.clone()));
         }
     }
 
-    fn render(&mut self, ctx: &mut quarkstrom::RenderContext) {
-        {
-            let mut lock = SIM_TO_RENDERER_UPDATE_LOCK.lock();
-            if *lock {
-                let mut body_lock = BODIES.lock();
-                let mut plate_lock = PLATES.lock();
+    fn stop_inspecting(&mut self) {
+        self.readings.inspected = None;
+        self.send_edit(EditCommand::Inspect(None));
+    }
 
-                // Get bodies from the simulation
-                std::mem::swap(&mut self.bodies, &mut body_lock);
+    // Apply an undoable edit to the local copy and send it to the simulation
+    fn make_edit(&mut self, edit: EditCommand) {
+        let undo = match &edit {
+            EditCommand::AddPlate { plate, .. } => {
+                vec![EditCommand::RemovePlates { ids: vec![plate.id], min: Vec2::zero(), max: Vec2::zero() }]
+            },
+            EditCommand::RemovePlates { ids, .. } => {
+                // The plates come back empty and their bodies where they were
+                let mut undo: Vec<EditCommand> = self.plates.iter()
+                    .filter(|plate| ids.contains(&plate.id))
+                    .map(|plate| EditCommand::AddPlate { plate: plate.clone(), bodies: Vec::new() })
+                    .collect();
+                let removed = self.bodies.iter().filter(|body| edit.removes_body(body, &self.plates)).copied().collect();
+                undo.push(EditCommand::AddBodies(removed));
+                undo
+            },
+            EditCommand::MovePlates { ids, offset } => {
+                vec![EditCommand::MovePlates { ids: ids.clone(), offset: -*offset }]
+            },
+            EditCommand::TransformPlates { ids, center, transform } => {
+                vec![EditCommand::TransformPlates { ids: ids.clone(), center: *center, transform: transform.inverse() }]
+            },
+            EditCommand::ResizePlate { id, .. } => {
+                let plate = self.plates.iter().find(|plate| plate.id == *id);
+                let (min, max) = plate.map_or((Vec2::zero(), Vec2::zero()), |plate| (plate.min, plate.max));
+                let removed = self.bodies.iter().filter(|body| edit.removes_body(body, &self.plates)).copied().collect();
+                vec![EditCommand::ResizePlate { id: *id, min, max }, EditCommand::AddBodies(removed)]
+            },
+            _ => Vec::new(),
+        };
 
-                // Get plates from the simulation
-                std::mem::swap(&mut self.plates, &mut plate_lock);
+        edit.apply(&mut self.bodies, &mut self.plates);
+        self.history.record(edit.clone(), undo);
+        self.send_edit(edit);
+    }
 
-                // Get quadtree from the simulation
-                std::mem::swap(&mut self.quadtree, &mut QUADTREE.lock());
+    // Send a plate that was changed in place, remembering how it was before
+    fn send_plate_change(&mut self, idx: usize, before: Plate) {
+        let edit = EditCommand::SetPlate(self.plates[idx].clone());
+        self.history.record(edit.clone(), vec![EditCommand::SetPlate(before)]);
+        self.send_edit(edit);
+    }
 
-                // Update objects
-                if self.update_objects() {
-                    *body_lock = self.bodies.clone();
-                    *plate_lock = self.plates.clone();
+    // Apply edits from the undo history to both copies of the scene
+    fn apply_history(&mut self, edits: Vec<EditCommand>) {
+        for edit in edits {
+            edit.apply(&mut self.bodies, &mut self.plates);
+            self.send_edit(edit);
+        }
 
-                    let mut lock = RENDERER_TO_SIM_UPDATE_LOCK.lock();
-                    *lock |= true;
-                }
+        // Plates may have come and gone under the selection
+        self.deselect_all();
+    }
 
-                // Update flow rate
-                self.update_flow_rate();
+    // Randomly remove free bodies down to the target count, keeping each plate's share
+    fn decimate(&mut self) {
+        let edit = EditCommand::Decimate { target: self.decimate_target, seed: self.rng.u64(..) };
+        edit.apply(&mut self.bodies, &mut self.plates);
+        self.send_edit(edit);
+        self.body_warning = None;
+    }
 
-                // Reset flow count every second
-                if self.sw.elapsed().as_secs() >= 1 {
-                    self.sw.restart();
-                    self.last_flow_count = self.flow_count;
-                    self.flow_count = 0.0;
-                }
+    // Restart both the renderer's and the simulation's random numbers from the seed
+    fn reseed(&mut self) {
+        self.rng = fastrand::Rng::with_seed(self.seed);
+        self.send_edit(EditCommand::Reseed(self.seed));
+    }
 
-                // Update dt
-                self.time += self.dt;
-            }
+    fn save_scene(&mut self) {
+        let scene = Scene {
+            plates: self.plates.clone(),
+            bodies: self.bodies.clone(),
+            dt: self.parameters.dt,
+            qe: self.parameters.qe,
+            qp: self.parameters.qp,
+            temperature: self.parameters.temperature,
+            seed: self.seed,
+            annotations: self.annotations.clone(),
+        };
 
-            // Update complete
-            *lock = false;
-        }
+        self.scene_status = Some(match scene.save(&self.scene_path) {
+            Ok(()) => format!("Saved {}", self.scene_path),
+            Err(err) => format!("Couldn't save {}: {}", self.scene_path, err),
+        });
+    }
 
-        ctx.clear_circles();
-        ctx.clear_lines();
-        ctx.clear_rects();
-        ctx.set_view_pos(self.pos);
-        ctx.set_view_scale(self.scale);
+    fn load_scene(&mut self) {
+        let scene = match Scene::load(&self.scene_path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                self.scene_status = Some(format!("Couldn't load {}: {}", self.scene_path, err));
+                return;
+            }
+        };
 
-        let mut show_selection = true;
+        self.parameters = Parameters { dt: scene.dt, qe: scene.qe, qp: scene.qp, temperature: scene.temperature, ..self.parameters };
+        self.send_edit(EditCommand::SetParameters(self.parameters));
 
-        if !self.bodies.is_empty() {
-            if self.show_bodies {
-                for i in 0..self.bodies.len() {
-                    // Draw body
-                    ctx.draw_circle(self.bodies[i].pos, self.bodies[i].radius, [50, 180, 240, 255]);
-                
-                    // Draw acceleration
-                    // ctx.draw_line(
-                    //     self.bodies[i].pos,
-                    //     self.bodies[i].pos + self.bodies[i].acc.normalized() * 5.0,
-                    //     [0xff, 0x00, 0x00, 0xff],
-                    // );
-                }
+        let edit = EditCommand::ReplaceScene { bodies: scene.bodies, plates: scene.plates };
+        edit.apply(&mut self.bodies, &mut self.plates);
+        self.send_edit(edit);
+        self.seed = scene.seed;
+        self.reseed();
+        self.history.clear();
+
+        self.annotations = scene.annotations;
+        self.deselect_annotation();
+
+        self.deselect_all();
+        self.scene_status = Some(format!("Loaded {}", self.scene_path));
+    }
+
+    // Bodies to fill a new plate with, or None if the body limit leaves no room for any
+    fn fill_plate(&mut self, plate: &Plate) -> Option<Vec<Body>> {
+        // Insulators start empty
+        let (min, max) = (plate.min, plate.max);
+        let area = (max.x - min.x) * (max.y - min.y) / (DENSITY_CELL_SIZE * DENSITY_CELL_SIZE);
+        let mut sites = if plate.is_conductor() { (area * self.body_density as f32) as usize } else { 0 };
+        let margin = self.grid_size * 0.1;
+
+        // Thin the fill to stay under the body limit, refusing the plate if there's no room at all
+        let per_site = match self.body_fill {
+            BodyFill::Electrons | BodyFill::Ions => 1,
+            BodyFill::Neutral | BodyFill::ElectronsAndHoles => 2,
+        };
+        let room = self.max_bodies.saturating_sub(self.bodies.len()) / per_site;
+        self.body_warning = None;
+        if sites > room {
+            if room == 0 {
+                self.body_warning = Some(format!("Plate not placed: already at the {} body limit", self.max_bodies));
+                return None;
             }
+            self.body_warning = Some(format!("Fill reduced from {} to {} bodies to stay under the limit", sites * per_site, room * per_site));
+            sites = room;
         }
 
-        if !self.plates.is_empty() {
-            if self.show_plates {
-                for i in 0..self.plates.len() {
-                    // Draw plate
-                    match self.plates[i].plate_type {
-                        PlateType::Normal => {
-                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, [50, 50, 50, 255]);
-                        },
-                        PlateType::Battery => {
-                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, [30, 100, 30, 255]);
-                        },
-                        PlateType::Resistor => {
-                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, [120, 70, 10, 255]);
-                        }
-                    }
-                }
-                
-                if self.selection_active {
-                    if !self.selected_plate_indicies.is_empty() {
-                        show_selection = false;
-                    }
+        return Some(self.new_bodies(sites, min + Vec2::one() * margin, max - Vec2::one() * margin));
+    }
 
-                    for i in 0..self.selected_plate_indicies.len() {
-                        let idx = self.selected_plate_indicies[i];
-                        let plate = &self.plates[idx];
-                        let min = plate.min;
-                        let max = plate.max;
-    
-                        // Draw outline
-                        ctx.draw_line(min, Vec2::new(min.x, max.y), [255, 255, 255, 255]);
-                        ctx.draw_line(min, Vec2::new(max.x, min.y), [255, 255, 255, 255]);
-                        ctx.draw_line(max, Vec2::new(min.x, max.y), [255, 255, 255, 255]);
-                        ctx.draw_line(max, Vec2::new(max.x, min.y), [255, 255, 255, 255]);
-                    }
-                }
+    // Bodies of the chosen fill spread over [min, max]
+    fn new_bodies(&mut self, sites: usize, min: Vec2, max: Vec2) -> Vec<Body> {
+        let mut bodies = utils::uniform_rect_species(&mut self.rng, sites, min, max, self.body_fill, self.ion_mass);
+
+        // Electrons take the chosen sign and ions balance them
+        for body in &mut bodies {
+            match body.species {
+                Species::Electron => body.charge = self.body_charge,
+                Species::Ion | Species::Hole => body.charge = -self.body_charge,
             }
         }
 
-        if self.show_quadtree && !self.quadtree.is_empty() {
-            let mut depth_range = self.depth_range;
-            if depth_range.0 >= depth_range.1 {
-                let mut stack = Vec::new();
-                stack.push((Quadtree::ROOT, 0));
+        return bodies;
+    }
 
-                let mut min_depth = usize::MAX;
-                let mut max_depth = 0;
-                while let Some((node, depth)) = stack.pop() {
-                    let node = &self.quadtree[node];
+    // Top the free bodies under the brush up to the fill density, only inside conductor plates
+    fn paint_bodies(&mut self, center: Vec2) {
+        let radius = self.brush_radius;
+        let in_brush = |pos: Vec2, plates: &[Plate]| {
+            (pos - center).mag_sq() <= radius * radius && plates.iter().any(|plate| plate.is_conductor() && plate.contains_point(pos))
+        };
 
-                    if node.is_leaf() {
-                        if depth < min_depth {
-                            min_depth = depth;
-                        }
-                        if depth > max_depth {
-                            max_depth = depth;
-                        }
-                    } else {
-                        for i in 0..4 {
-                            stack.push((node.children + i, depth + 1));
-                        }
-                    }
-                }
+        // A full fill of the brush's square, cut down to the part of the circle inside plates
+        let area = (2.0 * radius) * (2.0 * radius) / (DENSITY_CELL_SIZE * DENSITY_CELL_SIZE);
+        let sites = (area * self.body_density as f32) as usize;
+        let mut bodies = self.new_bodies(sites, center - Vec2::broadcast(radius), center + Vec2::broadcast(radius));
+        bodies.retain(|body| in_brush(body.pos, &self.plates));
+
+        let present = self.bodies.iter().filter(|body| !body.fixed && in_brush(body.pos, &self.plates)).count();
+        let missing = bodies.len().saturating_sub(present);
+        let room = self.max_bodies.saturating_sub(self.bodies.len());
+        if missing > room {
+            self.body_warning = Some(format!("Painting stopped at the {} body limit", self.max_bodies));
+        }
 
-                depth_range = (min_depth, max_depth);
+        bodies.truncate(missing.min(room));
+        if bodies.is_empty() {
+            return;
+        }
+
+        let edit = EditCommand::AddBodies(bodies);
+        edit.apply(&mut self.bodies, &mut self.plates);
+        self.send_edit(edit);
+    }
+
+    // Paste clipboard plates with the copied selection's min corner on the hovered cell, then select them
+    fn paste_plates(&mut self, text: &str) {
+        let Some(clipboard) = PlateClipboard::from_json(text) else { return };
+        let anchor = self.hovered_cell;
+        let pasted: Vec<Plate> = clipboard.plates.iter().map(|plate| plate.duplicate(anchor)).collect();
+
+        if pasted.is_empty() {
+            return;
+        }
+        if pasted.iter().any(|plate| self.plates.iter().any(|other| plate.overlaps(other))) {
+            self.body_warning = Some("Plates not pasted: other plates are in the way".to_string());
+            return;
+        }
+
+        let first = self.plates.len();
+        for plate in pasted {
+            let Some(bodies) = self.fill_plate(&plate) else { break };
+            let edit = EditCommand::AddPlate { plate, bodies };
+            self.make_edit(edit);
+        }
+        if self.plates.len() == first {
+            return;
+        }
+
+        let max = self.plates[first..].iter().fold(anchor, |max, plate| max.max_by_component(plate.max));
+        self.set_selection(anchor, max);
+        self.selection_active = true;
+        self.selected_plate_indicies = (first..self.plates.len()).collect();
+        self.load_selection_settings();
+    }
+
+    // Apply edits to the local copy for display and send them to the simulation
+    fn update_objects(&mut self) {
+        let plate_type = self.setting_plate.take(); // take the value out of self.setting_plate
+
+        // Stepping through the edit history
+        if std::mem::take(&mut self.undo_requested) {
+            if let Some(edits) = self.history.undo() {
+                self.apply_history(edits);
             }
-            let (min_depth, max_depth) = depth_range;
+        }
+        if std::mem::take(&mut self.redo_requested) {
+            if let Some(edits) = self.history.redo() {
+                self.apply_history(edits);
+            }
+        }
 
-            let mut stack = Vec::new();
-            stack.push((Quadtree::ROOT, 0));
-            while let Some((node, depth)) = stack.pop() {
-                let node = &self.quadtree[node];
+        // Placing fixed charges
+        if !self.pending_charges.is_empty() {
+            let charges: Vec<Body> = self.pending_charges.drain(..).map(|pos| Body::new_fixed(pos, self.point_charge)).collect();
+            self.bodies.extend_from_slice(&charges);
+            self.send_edit(EditCommand::AddBodies(charges));
+        }
 
-                if node.is_branch() && depth < max_depth {
-                    for i in 0..4 {
-                        stack.push((node.children + i, depth + 1));
+        // Brush strokes, one edit per frame however far the cursor moved
+        if let Some((brush, from, to)) = self.pending_brush.take() {
+            match brush {
+                Brush::Erase => {
+                    let edit = EditCommand::EraseBodies { from, to, radius: self.brush_radius };
+                    if self.bodies.iter().any(|body| edit.removes_body(body, &self.plates)) {
+                        edit.apply(&mut self.bodies, &mut self.plates);
+                        self.send_edit(edit);
                     }
-                } else if depth >= min_depth {
-                    let quad = node.quad;
-                    let half = Vec2::new(0.5, 0.5) * quad.size;
-                    let min = quad.center - half;
-                    let max = quad.center + half;
+                },
+                Brush::Paint => self.paint_bodies(to),
+            }
+        }
 
-                    let t = ((depth - min_depth + !node.is_empty() as usize) as f32)
-                        / (max_depth - min_depth + 1) as f32;
+        // Deleting or pinning the inspected body
+        if let Some(edit) = self.pending_body_edit.take() {
+            self.body_edit_failure = None;
+            edit.apply(&mut self.bodies, &mut self.plates);
+            self.send_edit(edit);
+        }
 
-                    let start_h = -100.0;
-                    let end_h = 80.0;
-                    let h = start_h + (end_h - start_h) * t;
-                    let s = 100.0;
-                    let l = t * 100.0;
+        // Removing plates
+        if self.remove_selection {
+            let (min, max) = self.get_selection();
+            let ids = self.selected_plate_indicies.iter().map(|&i| self.plates[i].id).collect();
+            let edit = EditCommand::RemovePlates { ids, min, max };
+            self.make_edit(edit);
 
-                    let c = Hsluv::new(h, s, l);
-                    let rgba: Rgba = c.into_color();
-                    let color: [u8; 4] = rgba.into_format().into();
+            self.deselect_all();
+        }
 
-                    ctx.draw_rect(min, max, [color[0], color[1], color[2], 0x80]);
+        // Adding plates / Changing plate type
+        if let Some(plate_type) = plate_type {
+            if self.selected_plate_indicies.len() > 0 {
+                // Change the type of the plate
+                for i in 0..self.selected_plate_indicies.len() {
+                    let idx = self.selected_plate_indicies[i];
+                    let before = self.plates[idx].clone();
+                    let mut plate = before.clone();
+                    self.set_plate_type(&mut plate, plate_type);
+                    self.plates[idx] = plate;
+                    self.send_plate_change(idx, before);
+                }
+            } else {
+                // Create a new plate
+                let (min, max) = self.get_selection();
+                let mut plate = Plate::new(min, max);
+
+                // Plates never overlap, so every point belongs to at most one plate, and need some area
+                let empty = max.x <= min.x || max.y <= min.y;
+                if empty || self.plates.iter().any(|other| other.overlaps(&plate)) {
+                    self.deselect_all();
+                    return;
                 }
+
+                self.set_plate_type(&mut plate, plate_type);
+
+                let Some(bodies) = self.fill_plate(&plate) else {
+                    self.deselect_all();
+                    return;
+                };
+
+                let edit = EditCommand::AddPlate { plate, bodies };
+                self.make_edit(edit);
             }
+            
+            self.deselect_all();
         }
-    
-        // Draw hovered cell
-        if self.selection_active {
-            if show_selection || self.mouse_down {
-                let min = Vec2::new(
-                    self.cell_start.x.min(self.cell_end.x),
-                    self.cell_start.y.min(self.cell_end.y),
-                );
-                let max = Vec2::new(
-                    self.cell_start.x.max(self.cell_end.x),
-                    self.cell_start.y.max(self.cell_end.y),
-                );
-    
-                let beg = min;
-                let end = max + Vec2::one() * self.grid_size;
-    
-                ctx.draw_line(beg, Vec2::new(beg.x, end.y), [0xff, 0xff, 0xff, 0xff]);
-                ctx.draw_line(beg, Vec2::new(end.x, beg.y), [0xff, 0xff, 0xff, 0xff]);
-                ctx.draw_line(end, Vec2::new(beg.x, end.y), [0xff, 0xff, 0xff, 0xff]);
-                ctx.draw_line(end, Vec2::new(end.x, beg.y), [0xff, 0xff, 0xff, 0xff]);
+
+        // Changing plate charge
+        if self.selection_active && self.plate_charge_scale_changed {
+            for i in 0..self.selected_plate_indicies.len() {
+                let idx = self.selected_plate_indicies[i];
+                let before = self.plates[idx].clone();
+                self.plates[idx].charge_scale = self.plate_charge_scale;
+                self.send_plate_change(idx, before);
+            }
+        }
+        self.plate_charge_scale_changed = false;
+
+        // Resizing the selected plate, unless it would grow into another one
+        if let Some((idx, min, max)) = self.pending_resize.take() {
+            let plate = &self.plates[idx];
+            let mut resized = plate.clone();
+            resized.min = min;
+            resized.max = max;
+
+            let blocked = self.plates.iter().enumerate().any(|(j, other)| j != idx && resized.overlaps(other));
+            if !blocked && (min, max) != (plate.min, plate.max) {
+                let edit = EditCommand::ResizePlate { id: plate.id, min, max };
+                self.make_edit(edit);
+                self.select_plate(idx);
             }
-        } else {
-            let beg = self.hovered_cell;
-            let end = self.hovered_cell + Vec2::one() * self.grid_size;
-        
-            ctx.draw_line(beg, Vec2::new(beg.x, end.y), [0xff, 0xff, 0xff, 0xff]);
-            ctx.draw_line(beg, Vec2::new(end.x, beg.y), [0xff, 0xff, 0xff, 0xff]);
-            ctx.draw_line(end, Vec2::new(beg.x, end.y), [0xff, 0xff, 0xff, 0xff]);
-            ctx.draw_line(end, Vec2::new(end.x, beg.y), [0xff, 0xff, 0xff, 0xff]);
         }
-    }
 
-    fn gui(&mut self, ctx: &quarkstrom::egui::Context) {
-        egui::Window::new("")
-            .open(&mut self.settings_window_open)
-            .show(ctx, |ui| {
-                // Number of bodies
-                ui.label(format!("Bodies: {}", self.bodies.len()));
+        // Moving the selected plates, carrying the bodies in them along
+        if let Some(offset) = self.pending_move.take() {
+            if self.can_move_selection(offset) {
+                let ids = self.selected_plate_indicies.iter().map(|&i| self.plates[i].id).collect();
+                let edit = EditCommand::MovePlates { ids, offset };
+                self.make_edit(edit);
 
-                // Flow rate
-                if self.selected_plate_indicies.len() == 1 {
-                    ui.label(format!("Flow Rate: {}", self.last_flow_count));
-                }
+                // The selection follows the plates
+                self.cell_start += offset;
+                self.cell_end += offset;
+            }
+        }
 
-                ui.checkbox(&mut self.show_bodies, "Show Bodies");
-                ui.checkbox(&mut self.show_quadtree, "Show Quadtree");
-                ui.checkbox(&mut self.show_plates, "Show Plates");
+        // Turning or flipping the selected plates about the selection's center
+        if let Some(transform) = self.pending_transform.take() {
+            self.transform_selection(transform);
+        }
+
+        // Duplicating the selected plates, then selecting the copies so they can be moved right away
+        if self.duplicate_selection && !self.selected_plate_indicies.is_empty() {
+            self.body_warning = None;
+            match self.duplicate_offset() {
+                Some(offset) => {
+                    let first = self.plates.len();
+                    let mut room = self.max_bodies.saturating_sub(self.bodies.len());
+                    let mut skipped = 0;
+
+                    for i in self.selected_plate_indicies.clone() {
+                        let plate = self.plates[i].duplicate(offset);
+                        let mut bodies = Vec::new();
+
+                        if self.duplicate_bodies {
+                            let original = &self.plates[i];
+                            for body in self.bodies.iter().filter(|body| original.contains_point(body.pos)) {
+                                if room == 0 {
+                                    skipped += 1;
+                                    continue;
+                                }
+                                let mut copy = *body;
+                                copy.pos += offset;
+                                bodies.push(copy);
+                                room -= 1;
+                            }
+                        }
+
+                        let edit = EditCommand::AddPlate { plate, bodies };
+                        self.make_edit(edit);
+                    }
+
+                    if skipped > 0 {
+                        self.body_warning = Some(format!("{} bodies not copied to stay under the {} body limit", skipped, self.max_bodies));
+                    }
+
+                    self.cell_start += offset;
+                    self.cell_end += offset;
+                    self.selected_plate_indicies = (first..self.plates.len()).collect();
+                    self.load_selection_settings();
+                },
+                None => {
+                    self.body_warning = Some("Nowhere to put the copies: other plates are in the way".to_string());
+                },
+            }
+        }
+        self.duplicate_selection = false;
+
+        // Pasting plates from the clipboard
+        if let Some(text) = self.pending_paste.take() {
+            self.paste_plates(&text);
+        }
+
+        // Renaming the selected plate
+        if self.plate_name_changed && self.selected_plate_indicies.len() == 1 {
+            let idx = self.selected_plate_indicies[0];
+            let before = self.plates[idx].clone();
+            self.plates[idx].name = self.plate_name.clone();
+            self.send_plate_change(idx, before);
+        }
+        self.plate_name_changed = false;
+
+        // Changing plate strengths, for the sliders that moved since the selection was made
+        if self.selection_active {
+            let sliders = self.slider_values();
+            let loaded = self.selection_sliders;
+            for i in 0..self.selected_plate_indicies.len() {
+                let idx = self.selected_plate_indicies[i];
+                let before = self.plates[idx].clone();
+                let plate = &mut self.plates[idx];
+                let mut updated = false;
                 
-                {
-                    let mut dt = DT.lock();
-                    ui.add(egui::Slider::new(&mut *dt, 0.1..=1.0).text("Time Step"));
-                    self.dt = *dt;
-                }
-                {
-                    let mut qe = QE.lock();
-                    ui.add(egui::Slider::new(&mut *qe, 1e-2..=1.0).text("Electron Charge"));
-                }
-                {
-                    let mut qp = QP.lock();
-                    ui.add(egui::Slider::new(&mut *qp, 1e-3..=1.0e-1).text("Plate Charge"));
+                match plate.plate_type {
+                    PlateType::Battery if sliders.battery != loaded.battery || sliders.direction != loaded.direction => {
+                        let old_battery = (plate.efield, plate.frequency, plate.phase, plate.profile, plate.gain, plate.target_current);
+                        plate.make_ac_battery(self.battery_strength, self.battery_direction.to_radians(), self.battery_frequency, self.battery_phase);
+                        plate.profile = self.battery_profile;
+                        plate.gain = self.battery_gain;
+                        plate.target_current = self.battery_target;
+                        updated |= old_battery != (plate.efield, plate.frequency, plate.phase, plate.profile, plate.gain, plate.target_current);
+                    },
+                    PlateType::Resistor if sliders.resistor != loaded.resistor => {
+                        let old_resist = (plate.resist, plate.alpha);
+                        plate.make_resistor(self.resistor_strength, self.resistor_alpha);
+                        updated |= old_resist != (plate.resist, plate.alpha);
+                    },
+                    PlateType::Diode if sliders.direction != loaded.direction => {
+                        let old_direction = plate.direction;
+                        plate.make_diode(self.battery_direction.to_radians());
+                        updated |= old_direction != plate.direction;
+                    },
+                    PlateType::Emitter if sliders.emitter_rate != loaded.emitter_rate => {
+                        let old_rate = plate.emit_rate;
+                        plate.make_emitter(self.emitter_rate);
+                        updated |= old_rate != plate.emit_rate;
+                    },
+                    PlateType::Dielectric if sliders.epsilon_r != loaded.epsilon_r => {
+                        let old_epsilon = plate.epsilon_r;
+                        plate.make_dielectric(self.epsilon_r);
+                        updated |= old_epsilon != plate.epsilon_r;
+                    },
+                    _ => {}
                 }
 
-                ui.add(egui::Slider::new(&mut self.body_density, 1..=6).text("Electron Density"));
-                ui.add(egui::Slider::new(&mut self.battery_strength, -5.0..=5.0).text("Battery Strength"));
-                ui.add(egui::Slider::new(&mut self.resistor_strength, 0.0..=1.0).text("Resistor Strength"));
-    
-                if self.show_quadtree {
-                    let range = &mut self.depth_range;
-                    ui.horizontal(|ui| {
-                        ui.label("Depth Range:");
-                        ui.add(egui::DragValue::new(&mut range.0).speed(0.05));
-                        ui.label("to");
-                        ui.add(egui::DragValue::new(&mut range.1).speed(0.05));
-                    });
+                if updated {
+                    self.send_plate_change(idx, before);
                 }
-            });
+            }
+        }
+    }
+}
+
+impl quarkstrom::Renderer for Renderer {
+    fn new() -> Self {
+        // A missing keymap file just means the default keys
+        let path = Keymap::path();
+        let (keymap, keymap_status) = match Keymap::load(&path) {
+            Ok(keymap) => (keymap, Some(format!("Loaded {}", path.display()))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (Keymap::default(), None),
+            Err(err) => (Keymap::default(), Some(format!("Couldn't load {}: {}", path.display(), err))),
+        };
+
+        // Likewise the colors
+        let path = Palette::path();
+        let (palette, palette_status) = match Palette::load(&path) {
+            Ok(palette) => (palette, Some(format!("Loaded {}", path.display()))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (Palette::default(), None),
+            Err(err) => (Palette::default(), Some(format!("Couldn't load {}: {}", path.display(), err))),
+        };
+
+        Self {
+            pos: Vec2::zero(),
+            scale: 100.0,
+            view_min: Vec2::zero(),
+            view_max: Vec2::zero(),
+            settings_window_open: false,
+            parameters: Parameters::default(),
+            time: 0.0,
+            sw: Stopwatch::start_new(),
+            frame_sw: Stopwatch::start_new(),
+            frame_time: 0.0,
+            bodies_rendered: 0,
+            show_overlay: true,
+            show_bodies: true,
+            show_plates: true,
+            show_quadtree: false,
+            show_drift: true,
+            show_field: false,
+            show_heatmap: false,
+            last_flow_count: 0.0,
+            flow_count: 0.0,
+            depth_range: (0, 0),
+            bodies: Vec::new(),
+            plates: Vec::new(),
+            quadtree: Vec::new(),
+            measurements: Measurements::default(),
+            instruments: Instruments::default(),
+            sent_instruments: Instruments::default(),
+            readings: Readings::default(),
+            performance: Performance::default(),
+            field_backend: String::new(),
+            snapshot_taken: Instant::now(),
+            field: None,
+            scene_path: "scene.json".to_string(),
+            scene_status: None,
+            keymap,
+            rebinding: None,
+            keymap_status,
+            palette,
+            palette_status,
+            window_size: (0, 0),
+            screenshot_resolution: 1,
+            pending_screenshot: None,
+            screenshot_status: None,
+            seed: 0,
+            rng: fastrand::Rng::with_seed(0),
+            stats: Stats::default(),
+            frame: 0,
+            edits_sent: 0,
+            remove_selection: false,
+            setting_plate: None,
+            move_start: None,
+            pending_move: None,
+            resize_hover: None,
+            resize_drag: None,
+            resize_bounds: (Vec2::zero(), Vec2::zero()),
+            pending_resize: None,
+            duplicate_selection: false,
+            duplicate_bodies: true,
+            pending_paste: None,
+            pending_transform: None,
+            history: UndoStack::new(UNDO_DEPTH),
+            undo_requested: false,
+            redo_requested: false,
+            battery_strength: 1.0,
+            battery_direction: 0.0,
+            battery_frequency: 0.0,
+            battery_phase: 0.0,
+            battery_profile: DriveProfile::Uniform,
+            battery_gain: 0.0,
+            battery_target: 0.5,
+            resistor_strength: 0.5,
+            resistor_alpha: 0.0,
+            emitter_rate: 1.0,
+            epsilon_r: 2.0,
+            plate_charge_scale: 1.0,
+            plate_charge_scale_changed: false,
+            plate_name: String::new(),
+            plate_name_changed: false,
+            show_labels: false,
+            show_plate_list: false,
+            show_plot: false,
+            plot_quantity: PlotQuantity::Current,
+            plot_samples: VecDeque::new(),
+            plot_length: PLOT_LENGTH,
+            plot_paused: false,
+            plot_plate: None,
+            plot_path: "plot.csv".to_string(),
+            plot_status: None,
+            show_scope: false,
+            scope_channels: Vec::new(),
+            next_probe_id: 0,
+            scope_length: SCOPE_LENGTH,
+            scope_paused: false,
+            trigger_channel: None,
+            trigger_level: 0.0,
+            trigger_edge: TriggerEdge::Rising,
+            trigger_state: TriggerState::FreeRunning,
+            trigger_last: None,
+            selected_plate_indicies: Vec::new(),
+            selection_sliders: SliderValues {
+                battery: (1.0, 0.0, 0.0, DriveProfile::Uniform, 0.0, 0.0),
+                direction: 0.0,
+                resistor: (0.0, 0.0),
+                emitter_rate: 0.0,
+                epsilon_r: 1.0,
+            },
+            mixed_settings: false,
+            additive_selection: false,
+            brush_radius: 20.0,
+            brush_cursor: None,
+            brush_last: None,
+            pending_brush: None,
+            pending_body_edit: None,
+            body_edit_failure: None,
+            body_density: 4,
+            max_bodies: 200_000,
+            body_warning: None,
+            decimate_target: 50_000,
+            body_charge: ELECTRON_CHARGE,
+            body_fill: BodyFill::Electrons,
+            ion_mass: ION_MASS,
+            charge_mode: false,
+            point_charge: 10.0,
+            pending_charges: Vec::new(),
+            probe_mode: false,
+            probe_points: Vec::new(),
+            ruler_mode: false,
+            ruler_points: Vec::new(),
+            annotation_mode: false,
+            annotations: Vec::new(),
+            annotation_bounds: Vec::new(),
+            selected_annotation: None,
+            editing_annotation: None,
+            focus_annotation: false,
+            annotation_drag: None,
+            typing: false,
+            pointer_on_gui: false,
+            show_coordinates: true,
+            cursor: None,
+            show_streamlines: false,
+            streamline_mode: false,
+            streamline_seeds: Vec::new(),
+            streamline_batteries: true,
+            streamline_density: 1.0,
+            streamline_steps: 200,
+            streamlines: Vec::new(),
+            show_readout: false,
+            readout: None,
+            show_trails: false,
+            trail_length: 20,
+            trail_stride: 1,
+            trails: HashMap::new(),
+            show_body_vectors: false,
+            body_vector_scale: 5.0,
+            body_vector_log: false,
+            body_vector_min: 1e-3,
+            grid_size: 10.0,
+            snap_to_grid: true,
+            hovered_cell: Vec2::zero(),
+            cell_start: Vec2::zero(),
+            cell_end: Vec2::zero(),
+            selection_active: false,
+            mouse_down: false,
+        }
+    }
+
+    fn input(&mut self, input: &WinitInputHelper, width: u16, height: u16) {
+        self.window_size = (width, height);
+
+        // The next key press goes to the action being rebound, and does nothing else; Escape cancels
+        if let Some(action) = self.rebinding {
+            if input.key_pressed(VirtualKeyCode::Escape) {
+                self.rebinding = None;
+            } else if let Some(key) = keymap::BINDABLE_KEYS.into_iter().find(|&key| input.key_pressed(key)) {
+                self.keymap.bind(action, key);
+                self.rebinding = None;
+            }
+            return;
+        }
+
+        // Keys are going to a text field, and a click elsewhere takes its focus away first
+        if self.typing {
+            return;
+        }
+
+        self.settings_window_open ^= self.keymap.pressed(input, Action::ToggleSettings);
+
+        if self.keymap.pressed(input, Action::Screenshot) {
+            self.pending_screenshot = Some(self.screenshot_resolution);
+        }
+
+        if self.keymap.pressed(input, Action::TogglePause) {
+            let val = PAUSED.load(Ordering::Relaxed);
+            PAUSED.store(!val, Ordering::Relaxed)
+        }
+
+        // Run the simulation backwards while the rewind key is held
+        let rewind = self.keymap.held(input, Action::Rewind);
+        if rewind != self.parameters.rewind {
+            self.parameters.rewind = rewind;
+            self.send_edit(EditCommand::SetParameters(self.parameters));
+        }
+
+        // Scroll steps to double/halve the scale, or the brush radius while a brush key is held
+        let steps = 5.0;
+        let brush = if self.keymap.held(input, Action::EraseBodies) {
+            Some(Brush::Erase)
+        } else if self.keymap.held(input, Action::PaintBodies) {
+            Some(Brush::Paint)
+        } else {
+            None
+        };
+
+        if brush.is_some() {
+            let radius = self.brush_radius * (input.scroll_diff() / steps).exp2();
+            self.brush_radius = radius.clamp(MIN_BRUSH_RADIUS, MAX_BRUSH_RADIUS);
+        } else if let Some((mx, my)) = input.mouse() {
+
+            // Modify input
+            let zoom = (-input.scroll_diff() / steps).exp2();
+
+            // Screen space -> view space
+            let target =
+                Vec2::new(mx * 2.0 - width as f32, height as f32 - my * 2.0) / height as f32;
+
+            // Move view position based on target
+            self.pos += target * self.scale * (1.0 - zoom);
+
+            // Zoom
+            self.scale *= zoom;
+        }
+
+        // Grab
+        if input.mouse_held(2) {
+            let (mdx, mdy) = input.mouse_diff();
+            self.pos.x -= mdx / height as f32 * self.scale * 2.0;
+            self.pos.y += mdy / height as f32 * self.scale * 2.0;
+        }
+
+        // Halving and doubling the grid
+        if self.keymap.pressed(input, Action::HalveGrid) {
+            self.set_grid(self.grid_size * 0.5, self.snap_to_grid);
+        }
+        if self.keymap.pressed(input, Action::DoubleGrid) {
+            self.set_grid(self.grid_size * 2.0, self.snap_to_grid);
+        }
+
+        let world_mouse = || -> Vec2 {
+            let (mx, my) = input.mouse().unwrap_or_default();
+            let mut mouse = Vec2::new(mx, my);
+            mouse *= 2.0 / height as f32;
+            mouse.y -= 1.0;
+            mouse.y *= -1.0;
+            mouse.x -= width as f32 / height as f32;
+            mouse * self.scale + self.pos
+        };
+        let cursor = input.mouse().map(|_| world_mouse());
+
+        self.hovered_cell = if self.snap_to_grid {
+            Vec2::new(
+                (world_mouse().x / self.grid_size).floor() * self.grid_size,
+                (world_mouse().y / self.grid_size).floor() * self.grid_size,
+            )
+        } else {
+            world_mouse()
+        };
+
+        // Toggle fixed charge placement, with - and = choosing the sign
+        if self.keymap.pressed(input, Action::ToggleCharges) {
+            self.charge_mode = !self.charge_mode;
+            self.probe_mode = false;
+            self.ruler_mode = false;
+            self.annotation_mode = false;
+            self.streamline_mode = false;
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+        }
+
+        if self.charge_mode {
+            if self.keymap.pressed(input, Action::NegativeCharge) {
+                self.point_charge = -self.point_charge.abs();
+            }
+
+            if self.keymap.pressed(input, Action::PositiveCharge) {
+                self.point_charge = self.point_charge.abs();
+            }
+
+            if input.mouse_pressed(0) {
+                self.pending_charges.push(world_mouse());
+            }
+        }
+
+        // Toggle the voltage probe
+        if self.keymap.pressed(input, Action::ToggleProbe) && !input.held_control() {
+            self.probe_mode = !self.probe_mode;
+            self.charge_mode = false;
+            self.ruler_mode = false;
+            self.annotation_mode = false;
+            self.streamline_mode = false;
+            self.probe_points.clear();
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+            self.instruments.probe = None;
+        }
+
+        // Place probe points, starting a new pair once both are set
+        if self.probe_mode && input.mouse_pressed(0) {
+            if self.probe_points.len() >= 2 {
+                self.probe_points.clear();
+            }
+            self.probe_points.push(world_mouse());
+
+            self.instruments.probe = if self.probe_points.len() == 2 {
+                Some((self.probe_points[0], self.probe_points[1]))
+            } else {
+                None
+            };
+        }
+
+        // Toggle the ruler; its points stay when it's put away
+        if self.keymap.pressed(input, Action::ToggleRuler) {
+            self.ruler_mode = !self.ruler_mode;
+            self.charge_mode = false;
+            self.probe_mode = false;
+            self.annotation_mode = false;
+            self.streamline_mode = false;
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+        }
+
+        // Place ruler ends, starting a new ruler once both are set
+        if self.ruler_mode && input.mouse_pressed(0) {
+            if self.ruler_points.len() >= 2 {
+                self.ruler_points.clear();
+            }
+            self.ruler_points.push(world_mouse());
+            self.instruments.ruler = if self.ruler_points.len() == 2 { Some((self.ruler_points[0], self.ruler_points[1])) } else { None };
+        }
+
+        // Toggle streamline seeding, which also turns the streamlines on
+        if self.keymap.pressed(input, Action::ToggleStreamlines) {
+            self.streamline_mode = !self.streamline_mode;
+            self.show_streamlines |= self.streamline_mode;
+            self.charge_mode = false;
+            self.probe_mode = false;
+            self.ruler_mode = false;
+            self.annotation_mode = false;
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+        }
+
+        if self.streamline_mode && input.mouse_pressed(0) {
+            self.streamline_seeds.push(world_mouse());
+        }
+
+        // Brushing takes the left button, and drops the selection so the drag doesn't also select
+        let mode_active = self.probe_mode || self.charge_mode || self.ruler_mode || self.annotation_mode || self.streamline_mode;
+        if self.keymap.pressed(input, Action::EraseBodies) || self.keymap.pressed(input, Action::PaintBodies) {
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+        }
+        self.brush_cursor = brush.zip(cursor);
+        match (brush, cursor) {
+            (Some(brush), Some(pos)) if input.mouse_held(0) && !mode_active => {
+                let from = self.brush_last.unwrap_or(pos);
+                self.pending_brush = Some((brush, from, pos));
+                self.brush_last = Some(pos);
+            },
+            _ => self.brush_last = None,
+        }
+
+        let tool_active = mode_active || brush.is_some();
+
+        // Ctrl-click picks the body under the cursor (within 10 pixels) to inspect
+        let inspect_click = input.mouse_pressed(0) && input.held_control();
+        if inspect_click && !tool_active {
+            let radius = 10.0 * 2.0 * self.scale / height as f32;
+            // Not an edit to the scene, so it isn't counted as one
+            let _ = EDITS.0.send(EditCommand::Inspect(Some((world_mouse(), radius))));
+        }
+
+        // Toggle annotation placing, which also finishes the one being typed
+        if self.keymap.pressed(input, Action::ToggleAnnotations) {
+            self.annotation_mode = !self.annotation_mode;
+            self.charge_mode = false;
+            self.probe_mode = false;
+            self.ruler_mode = false;
+            self.streamline_mode = false;
+            self.selected_plate_indicies.clear();
+            self.selection_active = false;
+            self.finish_annotation();
+            self.deselect_annotation();
+        }
+
+        // Click an annotation to edit it and drag it around, or click elsewhere to place a new one
+        if self.annotation_mode {
+            if let (true, false, Some(pos)) = (input.mouse_pressed(0), self.pointer_on_gui, cursor) {
+                let hit = self.annotation_bounds.iter().rposition(|&(min, max)| {
+                    pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y
+                });
+                let hit = hit.filter(|&i| i < self.annotations.len());
+
+                // Finishing the last one may drop it for being empty, shifting the ones after it down
+                let (editing, count) = (self.editing_annotation, self.annotations.len());
+                self.finish_annotation();
+                let hit = match (hit, editing) {
+                    (Some(i), Some(e)) if self.annotations.len() < count && e == i => None,
+                    (Some(i), Some(e)) if self.annotations.len() < count && e < i => Some(i - 1),
+                    _ => hit,
+                };
+
+                let idx = hit.unwrap_or_else(|| {
+                    self.annotations.push(Annotation { pos, text: String::new() });
+                    self.annotations.len() - 1
+                });
+                self.selected_annotation = Some(idx);
+                self.editing_annotation = Some(idx);
+                self.focus_annotation = true;
+                self.annotation_drag = hit.map(|i| (i, self.annotations[i].pos - pos));
+            }
+
+            if let (Some((idx, offset)), Some(pos)) = (self.annotation_drag, cursor) {
+                self.annotations[idx].pos = pos + offset;
+            }
+            if input.mouse_released(0) {
+                self.annotation_drag = None;
+            }
+
+            if self.keymap.pressed(input, Action::DeleteSelection) {
+                if let Some(idx) = self.selected_annotation {
+                    self.remove_annotation(idx);
+                }
+            }
+        }
+
+        // Shift-drag from a selected plate moves the selected plates by whole cells; from anywhere else it adds to the selection
+        let on_selected = cursor.is_some_and(|pos| self.selected_plate_indicies.iter().any(|&i| self.plates[i].contains_point(pos)));
+        let move_click = input.mouse_pressed(0) && input.held_shift() && self.selection_active && on_selected;
+        if move_click && !inspect_click && !tool_active {
+            self.move_start = Some(self.hovered_cell);
+        }
+
+        // Dragging an edge or corner of the single selected plate resizes it, snapped to the grid lines
+        let single_plate = if self.selection_active && self.selected_plate_indicies.len() == 1 { Some(self.selected_plate_indicies[0]) } else { None };
+        if self.resize_drag.is_none() {
+            let tolerance = RESIZE_HANDLE_SIZE * 2.0 * self.scale / height as f32;
+            self.resize_hover = match (single_plate, cursor) {
+                (Some(idx), Some(pos)) if !tool_active => edges_near(&self.plates[idx], pos, tolerance),
+                _ => None,
+            };
+        }
+
+        let resize_click = input.mouse_pressed(0) && !move_click && !inspect_click && self.resize_hover.is_some();
+        if resize_click {
+            if let (Some(idx), Some(edges)) = (single_plate, self.resize_hover) {
+                self.resize_drag = Some((idx, edges));
+                self.resize_bounds = (self.plates[idx].min, self.plates[idx].max);
+            }
+        }
+
+        if let (Some((idx, edges)), Some(pos)) = (self.resize_drag, cursor) {
+            let snapped = if self.snap_to_grid { (pos / self.grid_size).map(|t| t.round()) * self.grid_size } else { pos };
+            self.resize_bounds = resized_bounds(&self.plates[idx], edges, snapped, self.grid_size);
+        }
+
+        // Selection
+        if input.mouse_pressed(0) && !inspect_click && !move_click && !resize_click && !tool_active {
+            self.mouse_down = true;
+            self.cell_start.x = self.hovered_cell.x;
+            self.cell_start.y = self.hovered_cell.y;
+            self.additive_selection = input.held_shift() && self.selection_active;
+            if !self.additive_selection {
+                self.selected_plate_indicies = Vec::new();
+            }
+            self.selection_active = true;
+        }
+
+        if input.mouse_pressed(1) {
+            self.stop_inspecting();
+            self.selection_active = false;
+            self.selected_plate_indicies = Vec::new();
+        }
+
+        if input.mouse_released(0) {
+            self.mouse_down = false;
+
+            if let Some(start) = self.move_start.take() {
+                let offset = self.hovered_cell - start;
+                if offset != Vec2::zero() {
+                    self.pending_move = Some(offset);
+                }
+            } else if let Some((idx, _)) = self.resize_drag.take() {
+                let (min, max) = self.resize_bounds;
+                self.pending_resize = Some((idx, min, max));
+            } else if self.selection_active {
+                let mut selected = self.get_selected_plate_indicies();
+                if self.additive_selection {
+                    selected.retain(|i| !self.selected_plate_indicies.contains(i));
+                    self.selected_plate_indicies.extend(selected);
+
+                    // Grow the selection to cover the plates picked earlier too
+                    let (mut min, mut max) = self.get_selection();
+                    for &i in &self.selected_plate_indicies {
+                        min = min.min_by_component(self.plates[i].min);
+                        max = max.max_by_component(self.plates[i].max);
+                    }
+                    self.set_selection(min, max);
+                } else {
+                    self.selected_plate_indicies = selected;
+                }
+                self.additive_selection = false;
+                self.load_selection_settings();
+            }
+        }
+
+        if input.mouse_held(0) && self.move_start.is_none() && self.resize_drag.is_none() {
+            self.cell_end.x = self.hovered_cell.x;
+            self.cell_end.y = self.hovered_cell.y;
+        }
+
+        if input.held_control() && self.keymap.pressed(input, Action::Undo) {
+            if input.held_shift() {
+                self.redo_requested = true;
+            } else {
+                self.undo_requested = true;
+            }
+        }
+
+        // Turning and mirroring the selected plates
+        if self.selection_active && self.keymap.pressed(input, Action::TurnSelection) {
+            self.pending_transform = Some(if input.held_shift() { PlateTransform::RotateRight } else { PlateTransform::RotateLeft });
+        }
+        if self.selection_active && self.keymap.pressed(input, Action::MirrorSelection) {
+            self.pending_transform = Some(if input.held_shift() { PlateTransform::MirrorVertical } else { PlateTransform::MirrorHorizontal });
+        }
+
+        if input.held_control() && self.keymap.pressed(input, Action::DuplicateSelection) {
+            self.duplicate_selection = self.selection_active;
+        }
+
+        if input.held_control() && self.keymap.pressed(input, Action::SelectAll) {
+            self.select_all();
+        }
+
+        if self.keymap.pressed(input, Action::DeleteSelection) {
+            if self.selection_active {
+                // Delete the flux loops that touch the selection along with the plates
+                let (min, max) = self.get_selection();
+                self.instruments.flux_loops.retain(|&(loop_min, loop_max)| {
+                    loop_max.x <= min.x || loop_min.x >= max.x || loop_max.y <= min.y || loop_min.y >= max.y
+                });
+
+                self.remove_selection = true;
+                self.selection_active = false;
+            }
+        }
+
+        // Turn the selection into a Gauss's law flux loop
+        if self.keymap.pressed(input, Action::FluxLoop) {
+            if self.selection_active {
+                let selection = self.get_selection();
+                self.instruments.flux_loops.push(selection);
+                self.selection_active = false;
+                self.selected_plate_indicies.clear();
+            }
+        }
+
+        // Flip the polarity of the selected batteries
+        if self.keymap.pressed(input, Action::FlipBattery) {
+            if self.selection_active {
+                self.battery_direction = (self.battery_direction + 180.0) % 360.0;
+            }
+        }
+
+        if self.keymap.pressed(input, Action::SetNormal) {
+            self.setting_plate = Some(PlateType::Normal);
+        }
+
+        if self.keymap.pressed(input, Action::SetBattery) {
+            self.setting_plate = Some(PlateType::Battery);
+        }
+
+        if self.keymap.pressed(input, Action::SetResistor) {
+            self.setting_plate = Some(PlateType::Resistor);
+        }
+
+        if self.keymap.pressed(input, Action::SetDiode) {
+            self.setting_plate = Some(PlateType::Diode);
+        }
+
+        if self.keymap.pressed(input, Action::SetSink) {
+            self.setting_plate = Some(PlateType::Sink);
+        }
+
+        if self.keymap.pressed(input, Action::SetEmitter) {
+            self.setting_plate = Some(PlateType::Emitter);
+        }
+
+        if self.keymap.pressed(input, Action::SetInsulator) {
+            self.setting_plate = Some(PlateType::Insulator);
+        }
+
+        if self.keymap.pressed(input, Action::SetDielectric) {
+            self.setting_plate = Some(PlateType::Dielectric);
+        }
+
+        if self.keymap.pressed(input, Action::SetCapacitor) && !input.held_control() {
+            self.setting_plate = Some(PlateType::Capacitor);
+        }
+
+        // Let the simulation count the bodies in the selection
+        self.instruments.region = if self.selection_active { Some(self.get_selection()) } else { None };
+
+        let half = Vec2::new(self.scale * width as f32 / height as f32, self.scale);
+        self.view_min = self.pos - half;
+        self.view_max = self.pos + half;
+
+        // Ask for the field over the view, with the same number of cells on screen at any zoom
+        self.instruments.field_request = if self.show_field || self.show_heatmap {
+            let spacing = if self.show_heatmap { FIELD_HEATMAP_SPACING } else { FIELD_ARROW_SPACING };
+            Some(FieldRequest {
+                min: self.view_min,
+                max: self.view_max,
+                columns: (width as f32 / spacing).ceil().max(1.0) as usize,
+                rows: (height as f32 / spacing).ceil().max(1.0) as usize,
+            })
+        } else {
+            None
+        };
+
+        // Read the field and potential under the cursor
+        self.instruments.cursor = if self.show_readout { cursor } else { None };
+        self.cursor = cursor;
+
+        // Trace from the clicked seeds and the battery edges
+        self.instruments.streamline_request = if self.show_streamlines {
+            let mut seeds = self.streamline_seeds.clone();
+            if self.streamline_batteries {
+                seeds.extend(self.battery_seeds());
+            }
+            Some(StreamlineRequest {
+                seeds,
+                step: STREAMLINE_STEP,
+                max_steps: self.streamline_steps,
+                min_field: STREAMLINE_MIN_FIELD,
+            })
+        } else {
+            None
+        };
+    }
+
+    fn render(&mut self, ctx: &mut quarkstrom::RenderContext) {
+        // Draw calls are kept while a screenshot is wanted, to draw them again into an image
+        let mut canvas = Canvas::new(ctx, self.pending_screenshot.is_some());
+        let ctx = &mut canvas;
+
+        simulation::smooth(&mut self.frame_time, self.frame_sw.elapsed().as_secs_f32());
+        self.frame_sw.restart();
+
+        // Take the newest snapshot from the simulation
+        if let Ok(snapshot) = SNAPSHOTS.1.try_recv() {
+            // Keep showing our own edits until the simulation has applied them
+            if snapshot.edits_applied == self.edits_sent {
+                self.parameters = snapshot.parameters;
+                self.bodies = snapshot.bodies;
+                if let Some(plates) = snapshot.plates {
+                    self.plates = plates;
+                }
+
+                if self.show_trails {
+                    self.update_trails();
+                } else {
+                    self.trails = HashMap::new();
+                }
+            }
+            self.quadtree = snapshot.quadtree;
+            self.field = snapshot.field;
+            self.readout = snapshot.cursor;
+            if let Some(streamlines) = snapshot.streamlines {
+                self.streamlines = streamlines;
+            }
+            self.stats = snapshot.stats;
+            self.frame = snapshot.frame;
+            self.snapshot_taken = snapshot.taken;
+            self.readings = snapshot.readings;
+            self.performance = snapshot.performance;
+            self.field_backend = snapshot.field_backend;
+
+            // The simulation's seed comes from the command line; take it until we pick one of our own
+            if snapshot.seed != self.seed && snapshot.edits_applied == 0 {
+                self.seed = snapshot.seed;
+                self.rng = fastrand::Rng::with_seed(self.seed);
+            }
+
+            self.measurements = snapshot.measurements;
+            if let Some(failure) = snapshot.failed_edits.last() {
+                self.body_edit_failure = Some(failure.clone());
+            }
+            self.sample_plot();
+            self.sample_scope(snapshot.probe_samples);
+
+            // Update flow rate
+            self.update_flow_rate();
+
+            // Reset flow count every second
+            if self.sw.elapsed().as_secs() >= 1 {
+                self.sw.restart();
+                self.last_flow_count = self.flow_count;
+                self.flow_count = 0.0;
+            }
+
+            // Update dt
+            self.time += self.parameters.dt;
+        }
+
+        // Update objects
+        self.update_objects();
+
+        ctx.clear_circles();
+        ctx.clear_lines();
+        ctx.clear_rects();
+        ctx.set_view_pos(self.pos);
+        ctx.set_view_scale(self.scale);
+
+        let mut show_selection = true;
+
+        // Background over the whole view, behind everything else
+        ctx.draw_rect(self.view_min, self.view_max, self.palette.background);
+
+        // Heatmap goes first, behind everything but the background
+        if self.show_heatmap {
+            if let Some(field) = &self.field {
+                draw_field_heatmap(ctx, field);
+            }
+        }
+
+        if self.show_trails {
+            for trail in self.trails.values() {
+                draw_trail(ctx, trail);
+            }
+        }
+
+        self.bodies_rendered = if self.show_bodies { self.bodies.len() } else { 0 };
+        if !self.bodies.is_empty() {
+            if self.show_bodies {
+                for i in 0..self.bodies.len() {
+                    // Draw body
+                    let color = match self.bodies[i].species {
+                        Species::Ion => self.palette.ion,
+                        Species::Hole => self.palette.hole,
+                        Species::Electron if self.bodies[i].charge > 0.0 => self.palette.positive,
+                        Species::Electron => self.palette.electron,
+                    };
+                    ctx.draw_circle(self.bodies[i].pos, self.bodies[i].radius, color);
+
+                    // Fixed charges get a +/- glyph
+                    if self.bodies[i].fixed {
+                        draw_charge_glyph(ctx, &self.bodies[i]);
+                    }
+                }
+            }
+
+            if self.show_body_vectors {
+                self.draw_body_vectors(ctx);
+            }
+        }
+
+        if !self.plates.is_empty() {
+            if self.show_plates {
+                for i in 0..self.plates.len() {
+                    // Draw plate
+                    match self.plates[i].plate_type {
+                        PlateType::Normal => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.normal);
+                        },
+                        PlateType::Battery => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.battery);
+                            draw_battery_chevrons(ctx, &self.plates[i]);
+                        },
+                        PlateType::Resistor => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, resistor_color(self.plates[i].resist, self.palette.resistor));
+                        },
+                        PlateType::Capacitor => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.capacitor);
+                        },
+                        PlateType::Diode => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.diode);
+                            draw_diode_marker(ctx, &self.plates[i]);
+                        },
+                        PlateType::Sink => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.sink);
+                        },
+                        PlateType::Emitter => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.emitter);
+                        },
+                        PlateType::Insulator => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.insulator);
+                        },
+                        PlateType::Dielectric => {
+                            ctx.draw_rect(self.plates[i].min, self.plates[i].max, self.palette.dielectric);
+                        }
+                    }
+
+                    // Draw mean drift
+                    if self.show_drift {
+                        if let Some(&drift) = self.measurements.drift.get(i) {
+                            draw_drift_arrow(ctx, &self.plates[i], drift);
+                        }
+                    }
+                }
+                
+                if self.selection_active {
+                    if !self.selected_plate_indicies.is_empty() {
+                        show_selection = false;
+                    }
+
+                    for i in 0..self.selected_plate_indicies.len() {
+                        let idx = self.selected_plate_indicies[i];
+                        let plate = &self.plates[idx];
+                        let min = plate.min;
+                        let max = plate.max;
+    
+                        // Draw outline
+                        ctx.draw_line(min, Vec2::new(min.x, max.y), self.palette.selection);
+                        ctx.draw_line(min, Vec2::new(max.x, min.y), self.palette.selection);
+                        ctx.draw_line(max, Vec2::new(min.x, max.y), self.palette.selection);
+                        ctx.draw_line(max, Vec2::new(max.x, min.y), self.palette.selection);
+                    }
+                }
+            }
+        }
+
+        if self.show_field {
+            if let Some(field) = &self.field {
+                // Heatmap cells are finer than the arrows, so only some of them get one
+                let stride = if self.show_heatmap { (FIELD_ARROW_SPACING / FIELD_HEATMAP_SPACING) as usize } else { 1 };
+                draw_field_arrows(ctx, field, stride);
+            }
+        }
+
+        if self.show_quadtree && !self.quadtree.is_empty() {
+            let mut depth_range = self.depth_range;
+            if depth_range.0 >= depth_range.1 {
+                let mut stack = Vec::new();
+                stack.push((Quadtree::ROOT, 0));
+
+                let mut min_depth = usize::MAX;
+                let mut max_depth = 0;
+                while let Some((node, depth)) = stack.pop() {
+                    let node = &self.quadtree[node];
+
+                    if node.is_leaf() {
+                        if depth < min_depth {
+                            min_depth = depth;
+                        }
+                        if depth > max_depth {
+                            max_depth = depth;
+                        }
+                    } else {
+                        for i in 0..4 {
+                            stack.push((node.children + i, depth + 1));
+                        }
+                    }
+                }
+
+                depth_range = (min_depth, max_depth);
+            }
+            let (min_depth, max_depth) = depth_range;
+
+            let mut stack = Vec::new();
+            stack.push((Quadtree::ROOT, 0));
+            while let Some((node, depth)) = stack.pop() {
+                let node = &self.quadtree[node];
+
+                if node.is_branch() && depth < max_depth {
+                    for i in 0..4 {
+                        stack.push((node.children + i, depth + 1));
+                    }
+                } else if depth >= min_depth {
+                    let quad = node.quad;
+                    let half = Vec2::new(0.5, 0.5) * quad.size;
+                    let min = quad.center - half;
+                    let max = quad.center + half;
+
+                    let t = ((depth - min_depth + !node.is_empty() as usize) as f32)
+                        / (max_depth - min_depth + 1) as f32;
+
+                    let start_h = -100.0;
+                    let end_h = 80.0;
+                    let h = start_h + (end_h - start_h) * t;
+                    let s = 100.0;
+                    let l = t * 100.0;
+
+                    let c = Hsluv::new(h, s, l);
+                    let rgba: Rgba = c.into_color();
+                    let color: [u8; 4] = rgba.into_format().into();
+
+                    ctx.draw_rect(min, max, [color[0], color[1], color[2], 0x80]);
+                }
+            }
+        }
+    
+        // Draw world bounds
+        if self.parameters.boundary != Boundary::None {
+            let size = self.parameters.world_size;
+            let min = -size * 0.5;
+            let max = size * 0.5;
+
+            ctx.draw_line(min, Vec2::new(min.x, max.y), [80, 80, 80, 255]);
+            ctx.draw_line(min, Vec2::new(max.x, min.y), [80, 80, 80, 255]);
+            ctx.draw_line(max, Vec2::new(min.x, max.y), [80, 80, 80, 255]);
+            ctx.draw_line(max, Vec2::new(max.x, min.y), [80, 80, 80, 255]);
+        }
+
+        // Draw flux loops
+        for &(min, max) in &self.instruments.flux_loops {
+            ctx.draw_line(min, Vec2::new(min.x, max.y), [80, 220, 255, 255]);
+            ctx.draw_line(min, Vec2::new(max.x, min.y), [80, 220, 255, 255]);
+            ctx.draw_line(max, Vec2::new(min.x, max.y), [80, 220, 255, 255]);
+            ctx.draw_line(max, Vec2::new(max.x, min.y), [80, 220, 255, 255]);
+        }
+
+        // Scope probes, in their channel's color
+        if self.show_scope {
+            for channel in self.scope_channels.iter().filter(|channel| channel.enabled) {
+                let (min, max) = match channel.probe.kind {
+                    ProbeKind::Voltage { a, b } => {
+                        ctx.draw_line(a, b, channel.color);
+                        ctx.draw_circle(a, self.scale * 0.008, channel.color);
+                        ctx.draw_circle(b, self.scale * 0.008, channel.color);
+                        continue;
+                    },
+                    ProbeKind::Ammeter { plate } => match self.plates.iter().find(|other| other.id == plate) {
+                        Some(plate) => (plate.min, plate.max),
+                        None => continue,
+                    },
+                    ProbeKind::FluxLoop { min, max } => (min, max),
+                };
+                ctx.draw_line(min, Vec2::new(min.x, max.y), channel.color);
+                ctx.draw_line(min, Vec2::new(max.x, min.y), channel.color);
+                ctx.draw_line(max, Vec2::new(min.x, max.y), channel.color);
+                ctx.draw_line(max, Vec2::new(max.x, min.y), channel.color);
+            }
+        }
+
+        // Ring around the inspected body
+        if let Some(body) = self.readings.inspected {
+            let radius = body.radius + self.scale * 0.02;
+            let segments = 24;
+            for i in 0..segments {
+                let a0 = i as f32 / segments as f32 * std::f32::consts::TAU;
+                let a1 = (i + 1) as f32 / segments as f32 * std::f32::consts::TAU;
+                let p0 = body.pos + Vec2::new(a0.cos(), a0.sin()) * radius;
+                let p1 = body.pos + Vec2::new(a1.cos(), a1.sin()) * radius;
+                ctx.draw_line(p0, p1, [255, 255, 0, 255]);
+            }
+        }
+
+        if self.show_streamlines {
+            for line in &self.streamlines {
+                draw_streamline(ctx, line, self.scale * 0.02);
+            }
+            for &seed in &self.streamline_seeds {
+                ctx.draw_circle(seed, self.scale * 0.006, [160, 255, 160, 255]);
+            }
+        }
+
+        // Draw voltage probe
+        if self.probe_mode {
+            let radius = self.scale * 0.01;
+            for (i, &point) in self.probe_points.iter().enumerate() {
+                let color = if i == 0 { [255, 80, 80, 255] } else { [80, 160, 255, 255] };
+                ctx.draw_circle(point, radius, color);
+            }
+
+            if self.probe_points.len() == 2 {
+                ctx.draw_line(self.probe_points[0], self.probe_points[1], [255, 255, 120, 255]);
+            }
+        }
+
+        // Brush outline
+        if let Some((brush, center)) = self.brush_cursor {
+            let color = match brush {
+                Brush::Erase => [255, 90, 90, 255],
+                Brush::Paint => [90, 255, 140, 255],
+            };
+            let point = |i: usize| {
+                let angle = i as f32 / BRUSH_OUTLINE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + Vec2::new(angle.cos(), angle.sin()) * self.brush_radius
+            };
+            for i in 0..BRUSH_OUTLINE_SEGMENTS {
+                ctx.draw_line(point(i), point(i + 1), color);
+            }
+        }
+
+        // Ruler, with ticks at the scale bar's spacing
+        match self.ruler_points[..] {
+            [a] => ctx.draw_circle(a, self.scale * 0.006, [255, 255, 255, 255]),
+            [a, b] => draw_ruler(ctx, a, b, nice_length(self.scale * SCALE_BAR_FRACTION), self.scale * 0.01),
+            _ => {},
+        }
+
+        // Grabbed or hovered edges of the selected plate, and the outline it's being resized to
+        if let Some((idx, _)) = self.resize_drag {
+            let (min, max) = self.resize_bounds;
+            let blocked = self.plates.iter().enumerate().any(|(j, other)| {
+                j != idx && other.min.x < max.x && other.max.x > min.x && other.min.y < max.y && other.max.y > min.y
+            });
+            let color = if blocked { [255, 60, 60, 200] } else { [255, 220, 80, 255] };
+
+            ctx.draw_line(min, Vec2::new(min.x, max.y), color);
+            ctx.draw_line(min, Vec2::new(max.x, min.y), color);
+            ctx.draw_line(max, Vec2::new(min.x, max.y), color);
+            ctx.draw_line(max, Vec2::new(max.x, min.y), color);
+        } else if let (Some(edges), Some(&idx)) = (self.resize_hover, self.selected_plate_indicies.first()) {
+            let (min, max) = (self.plates[idx].min, self.plates[idx].max);
+            let color = [255, 220, 80, 255];
+
+            if edges.left {
+                ctx.draw_line(min, Vec2::new(min.x, max.y), color);
+            }
+            if edges.right {
+                ctx.draw_line(Vec2::new(max.x, min.y), max, color);
+            }
+            if edges.bottom {
+                ctx.draw_line(min, Vec2::new(max.x, min.y), color);
+            }
+            if edges.top {
+                ctx.draw_line(Vec2::new(min.x, max.y), max, color);
+            }
+        }
+
+        // Outlines where the dragged plates would land, red if they'd overlap another plate
+        if let Some(start) = self.move_start {
+            let offset = self.hovered_cell - start;
+            let color = if self.can_move_selection(offset) { [255, 255, 255, 160] } else { [255, 60, 60, 160] };
+
+            for &i in &self.selected_plate_indicies {
+                let min = self.plates[i].min + offset;
+                let max = self.plates[i].max + offset;
+                ctx.draw_line(min, Vec2::new(min.x, max.y), color);
+                ctx.draw_line(min, Vec2::new(max.x, min.y), color);
+                ctx.draw_line(max, Vec2::new(min.x, max.y), color);
+                ctx.draw_line(max, Vec2::new(max.x, min.y), color);
+            }
+        }
+
+        // Draw hovered cell
+        if self.selection_active {
+            if show_selection || self.mouse_down {
+                let min = Vec2::new(
+                    self.cell_start.x.min(self.cell_end.x),
+                    self.cell_start.y.min(self.cell_end.y),
+                );
+                let max = Vec2::new(
+                    self.cell_start.x.max(self.cell_end.x),
+                    self.cell_start.y.max(self.cell_end.y),
+                );
+    
+                let beg = min;
+                let end = max + Vec2::one() * self.cell_extent();
+    
+                ctx.draw_line(beg, Vec2::new(beg.x, end.y), self.palette.selection);
+                ctx.draw_line(beg, Vec2::new(end.x, beg.y), self.palette.selection);
+                ctx.draw_line(end, Vec2::new(beg.x, end.y), self.palette.selection);
+                ctx.draw_line(end, Vec2::new(end.x, beg.y), self.palette.selection);
+            }
+        } else {
+            let beg = self.hovered_cell;
+            let end = self.hovered_cell + Vec2::one() * self.grid_size;
+        
+            ctx.draw_line(beg, Vec2::new(beg.x, end.y), self.palette.selection);
+            ctx.draw_line(beg, Vec2::new(end.x, beg.y), self.palette.selection);
+            ctx.draw_line(end, Vec2::new(beg.x, end.y), self.palette.selection);
+            ctx.draw_line(end, Vec2::new(end.x, beg.y), self.palette.selection);
+        }
+
+        if let Some(resolution) = self.pending_screenshot.take() {
+            let (width, height) = self.window_size;
+            self.screenshot_status = Some(match canvas.rasterize(width as u32, height as u32, resolution) {
+                Some(image) if image.width > 0 && image.height > 0 => match image.save() {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(err) => format!("Couldn't save the screenshot: {}", err),
+                },
+                _ => "Couldn't take a screenshot of an empty window".to_string(),
+            });
+        }
+    }
+
+    fn gui(&mut self, ctx: &quarkstrom::egui::Context) {
+        // Crash message, since the scene just stops moving otherwise
+        if let Some(crash) = CRASH.lock().as_ref() {
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("crash"))).text(
+                ctx.screen_rect().center_top() + egui::vec2(0.0, 40.0),
+                egui::Align2::CENTER_TOP,
+                format!("Simulation crashed: {}", crash),
+                egui::FontId::proportional(20.0),
+                egui::Color32::RED,
+            );
+        }
+
+        // Ctrl+C and Ctrl+V arrive as clipboard events, unless a text field has the keyboard
+        if !ctx.wants_keyboard_input() {
+            let events = ctx.input(|i| i.events.clone());
+            for event in events {
+                match event {
+                    egui::Event::Copy if self.selection_active && !self.selected_plate_indicies.is_empty() => {
+                        let text = self.copy_selection();
+                        ctx.output_mut(|o| o.copied_text = text);
+                    },
+                    egui::Event::Paste(text) => {
+                        self.pending_paste = Some(text);
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        // Frame and step rates, and how far the shown snapshot lags the simulation
+        if self.show_overlay {
+            let fps = if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 };
+            // Steps the simulation has likely taken since the shown snapshot
+            let age = (self.snapshot_taken.elapsed().as_secs_f32() * self.performance.steps_per_second) as usize;
+            let behind = age > SNAPSHOT_AGE_WARNING * self.parameters.steps_per_frame;
+
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("overlay")));
+            let font = egui::FontId::monospace(12.0);
+            let mut pos = ctx.screen_rect().right_bottom() + egui::vec2(-8.0, -8.0);
+            let lines = [
+                (format!("Snapshot Age: {} steps", age), if behind { egui::Color32::RED } else { egui::Color32::LIGHT_GRAY }),
+                (format!("Bodies Rendered: {}", self.bodies_rendered), egui::Color32::LIGHT_GRAY),
+                (format!("Steps/sec: {:.0}", self.performance.steps_per_second), egui::Color32::LIGHT_GRAY),
+                (format!("FPS: {:.0} ({:.1} ms)", fps, self.frame_time * 1000.0), egui::Color32::LIGHT_GRAY),
+            ];
+            for (text, color) in lines {
+                let rect = painter.text(pos, egui::Align2::RIGHT_BOTTOM, text, font.clone(), color);
+                pos.y -= rect.height();
+            }
+        }
+
+        // Cursor position and cell, over a scale bar
+        if self.show_coordinates {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("coordinates")));
+            let font = egui::FontId::monospace(12.0);
+            let corner = ctx.screen_rect().right_top() + egui::vec2(-10.0, 10.0);
+
+            if let Some(cursor) = self.cursor {
+                let cell = (cursor / self.grid_size).map(|t| t.floor());
+                let text = format!("({:.2}, {:.2})  Cell ({}, {})", cursor.x, cursor.y, cell.x as i64, cell.y as i64);
+                painter.text(corner, egui::Align2::RIGHT_TOP, text, font.clone(), egui::Color32::LIGHT_GRAY);
+            }
+
+            let length = nice_length(self.scale * SCALE_BAR_FRACTION);
+            let pixels = length / self.scale * ctx.screen_rect().height() * 0.5;
+            let (right, y) = (corner.x, corner.y + 36.0);
+            let stroke = egui::Stroke::new(2.0, egui::Color32::WHITE);
+            painter.line_segment([egui::pos2(right - pixels, y), egui::pos2(right, y)], stroke);
+            for x in [right - pixels, right] {
+                painter.line_segment([egui::pos2(x, y - 4.0), egui::pos2(x, y + 4.0)], stroke);
+            }
+            painter.text(egui::pos2(right, y - 6.0), egui::Align2::RIGHT_BOTTOM, length_label(length), font, egui::Color32::WHITE);
+        }
+
+        // Distance and potential difference beside the ruler
+        if let [a, b] = self.ruler_points[..] {
+            let mut text = format!("{:.2} units", (b - a).mag());
+            if let Some(voltage) = self.readings.ruler_voltage {
+                text += &format!("\nΔV = {:.4}", voltage);
+            }
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("ruler"))).text(
+                self.world_to_screen(ctx, (a + b) * 0.5) + egui::vec2(8.0, -8.0),
+                egui::Align2::LEFT_BOTTOM,
+                text,
+                egui::FontId::proportional(14.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        // Rewind indicator
+        if self.parameters.rewind {
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("rewind"))).text(
+                ctx.screen_rect().center_top() + egui::vec2(0.0, 10.0),
+                egui::Align2::CENTER_TOP,
+                "◀◀ REWINDING",
+                egui::FontId::proportional(20.0),
+                egui::Color32::from_rgb(255, 160, 60),
+            );
+        }
+
+        // Ammeter label above the selected plate
+        let selected_current = self.selected_current();
+        if let Some((idx, current)) = selected_current {
+            let plate = &self.plates[idx];
+            let anchor = Vec2::new((plate.min.x + plate.max.x) * 0.5, plate.max.y);
+
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("ammeter"))).text(
+                self.world_to_screen(ctx, anchor),
+                egui::Align2::CENTER_BOTTOM,
+                format!("I = {:.3}", current),
+                egui::FontId::proportional(14.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        // Annotations, under the windows, growing with zoom but never too small to read
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("annotations")));
+        let size = (ANNOTATION_SIZE / self.scale * ctx.screen_rect().height() * 0.5).max(MIN_ANNOTATION_SIZE);
+        let mut bounds = Vec::with_capacity(self.annotations.len());
+        for (i, annotation) in self.annotations.iter().enumerate() {
+            let selected = self.annotation_mode && self.selected_annotation == Some(i);
+            let color = if selected { egui::Color32::YELLOW } else { egui::Color32::WHITE };
+            let text = if annotation.text.is_empty() { "…" } else { &annotation.text };
+            let rect = painter.text(
+                self.world_to_screen(ctx, annotation.pos),
+                egui::Align2::LEFT_BOTTOM,
+                text,
+                egui::FontId::proportional(size),
+                color,
+            );
+            if selected {
+                painter.rect_stroke(rect.expand(2.0), 2.0, egui::Stroke::new(1.0, color));
+            }
+
+            let (a, b) = (self.screen_to_world(ctx, rect.left_bottom()), self.screen_to_world(ctx, rect.right_top()));
+            bounds.push((a, b));
+        }
+        self.annotation_bounds = bounds;
+
+        // The text of the annotation being edited, in a popup beside it
+        let mut finish_annotation = false;
+        let mut delete_annotation = false;
+        if let Some(idx) = self.editing_annotation {
+            let anchor = self.world_to_screen(ctx, self.annotations[idx].pos) + egui::vec2(0.0, 8.0);
+            let focus = std::mem::take(&mut self.focus_annotation);
+            let text = &mut self.annotations[idx].text;
+            egui::Window::new("Annotation")
+                .fixed_pos(anchor)
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    let field = ui.text_edit_singleline(text);
+                    if focus {
+                        field.request_focus();
+                    }
+                    finish_annotation = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                    ui.horizontal(|ui| {
+                        finish_annotation |= ui.button("Done").clicked();
+                        delete_annotation = ui.button("Delete").clicked();
+                    });
+                });
+
+            if delete_annotation {
+                self.remove_annotation(idx);
+            } else if finish_annotation {
+                self.finish_annotation();
+            }
+        }
+
+        // Name, type and key setting on each plate, under the windows
+        if self.show_labels {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("plate labels")));
+            for plate in &self.plates {
+                let mut text = format!("{:?}", plate.plate_type);
+                if let Some((name, value)) = plate.key_parameter() {
+                    text += &format!(" {} {:.2}", name, value);
+                }
+                if !plate.name.is_empty() {
+                    text = format!("{}\n{}", plate.name, text);
+                }
+
+                painter.text(
+                    self.world_to_screen(ctx, (plate.min + plate.max) * 0.5),
+                    egui::Align2::CENTER_CENTER,
+                    text,
+                    egui::FontId::proportional(12.0),
+                    egui::Color32::WHITE,
+                );
+            }
+        }
+
+        // Readout next to the cursor
+        if self.show_readout {
+            if let Some(reading) = self.readout {
+                let mut text = format!(
+                    "E = ({:.3e}, {:.3e})\n|E| = {:.3e}\nV = {:.3e}",
+                    reading.efield.x, reading.efield.y, reading.efield.mag(), reading.potential,
+                );
+                if let Some(plate) = self.plates.iter().find(|plate| plate.contains_point(reading.pos)) {
+                    text += &format!("\n{}", plate_summary(plate));
+                }
+
+                ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("readout"))).text(
+                    self.world_to_screen(ctx, reading.pos) + egui::vec2(16.0, 16.0),
+                    egui::Align2::LEFT_TOP,
+                    text,
+                    egui::FontId::proportional(14.0),
+                    egui::Color32::WHITE,
+                );
+            }
+        }
+
+        // Color scale for the heatmap, in the bottom left corner
+        let mut heatmap_legend = false;
+        if self.show_heatmap {
+            if let Some((low, high)) = self.field.as_ref().and_then(|field| log_magnitude_range(&field.efield)) {
+                draw_heatmap_legend(ctx, low, high);
+                heatmap_legend = true;
+            }
+        }
+
+        // Resistor shades, whenever there are resistors to read them off
+        if self.show_plates && self.plates.iter().any(|plate| plate.plate_type == PlateType::Resistor) {
+            draw_resistor_legend(ctx, heatmap_legend, self.palette.resistor);
+        }
+
+        // Scene buttons act once the window has let go of self
+        let mut save_scene = false;
+        let mut save_keymap = false;
+        let mut save_palette = false;
+        let mut load_scene = false;
+        let mut reseed = false;
+        let mut decimate = false;
+        let mut clear_ruler = false;
+        let mut parameters = self.parameters;
+        let mut grid_size = self.grid_size;
+        let mut snap_to_grid = self.snap_to_grid;
+
+        egui::Window::new("")
+            .open(&mut self.settings_window_open)
+            .show(ctx, |ui| {
+                // Number of bodies
+                ui.label(format!("Bodies: {}", self.bodies.len()));
+
+                // Substeps (red when the simulation is at the cap)
+                let substeps = self.readings.substeps;
+                if substeps >= simulation::MAX_SUBSTEPS {
+                    ui.colored_label(egui::Color32::RED, format!("Substeps: {}", substeps));
+                } else {
+                    ui.label(format!("Substeps: {}", substeps));
+                }
+
+                // Bodies slowed down by the speed limit
+                let clamped = self.readings.clamped;
+                if clamped > 0 {
+                    ui.colored_label(egui::Color32::YELLOW, format!("Clamped: {}", clamped));
+                }
+
+                // Substeps that had to recover from non-finite bodies
+                let nan_events = self.readings.nan_events;
+                if nan_events > 0 {
+                    ui.colored_label(egui::Color32::RED, format!("NaN Events: {}", nan_events));
+                }
+
+                // Flow rate
+                if self.selected_plate_indicies.len() == 1 {
+                    ui.label(format!("Flow Rate: {}", self.last_flow_count));
+
+                    if let Some((_, current)) = selected_current {
+                        ui.label(format!("Current: {:.3} bodies/step", current));
+                    }
+
+                    let idx = self.selected_plate_indicies[0];
+                    if let (Some(count), Some(charge)) = (self.measurements.body_counts.get(idx), self.measurements.net_charges.get(idx)) {
+                        ui.label(format!("Bodies Inside: {}", count));
+                        ui.label(format!("Net Charge: {:.3}", charge));
+                    }
+
+                    if let Some(drift) = self.measurements.drift.get(self.selected_plate_indicies[0]) {
+                        ui.label(format!("Drift: {:.3} ({:.3}, {:.3}) per step", drift.mag(), drift.x, drift.y));
+                    }
+
+                    // Per-species drift, for plates with more than one kind of carrier
+                    if let Some(species_drift) = self.measurements.species_drift.get(self.selected_plate_indicies[0]) {
+                        let names = ["Electron", "Ion", "Hole"];
+                        let moving = species_drift.iter().filter(|drift| **drift != Vec2::zero()).count();
+                        if moving > 1 {
+                            for (name, drift) in names.iter().zip(species_drift) {
+                                ui.label(format!("  {} Drift: ({:.3}, {:.3})", name, drift.x, drift.y));
+                            }
+                        }
+                    }
+
+                    let plate = &self.plates[self.selected_plate_indicies[0]];
+                    if plate.plate_type == PlateType::Capacitor {
+                        ui.label(format!("Stored Charge: {}", plate.stored_charge));
+                    }
+
+                    if plate.plate_type == PlateType::Battery && plate.gain > 0.0 {
+                        ui.label(format!("Effective EMF: {:.3}", plate.regulated_emf));
+                    }
+                }
+
+                // Bodies in the selection
+                if let Some((count, charge)) = self.readings.region_stats {
+                    ui.label(format!("Selection: {} bodies, charge {:.1}", count, charge));
+                }
+
+                if let Some(failure) = &self.body_edit_failure {
+                    ui.colored_label(egui::Color32::YELLOW, failure);
+                }
+
+                // Voltage probe
+                if self.probe_mode {
+                    match self.readings.probe_voltage {
+                        Some(voltage) => {
+                            ui.label(format!("Probe Voltage: {:.4}", voltage));
+                        },
+                        None => {
+                            ui.label("Probe Voltage: click two points");
+                        }
+                    }
+                }
+
+                // Ruler
+                if !self.ruler_points.is_empty() {
+                    ui.horizontal(|ui| {
+                        match (&self.ruler_points[..], self.readings.ruler_voltage) {
+                            ([a, b], Some(voltage)) => ui.label(format!("Ruler: {:.2} units, ΔV {:.4}", (*b - *a).mag(), voltage)),
+                            _ => ui.label("Ruler: click a second point"),
+                        };
+                        clear_ruler = ui.button("Clear").clicked();
+                    });
+                }
+
+                // Flux loops (flux / 2π should match the enclosed charge)
+                for (i, &(flux, enclosed)) in self.readings.flux.iter().enumerate() {
+                    ui.label(format!("Loop {}: Flux/2π {:.3}  Enclosed {:.3}", i + 1, flux / std::f32::consts::TAU, enclosed));
+                }
+
+                // Diagnostics
+                ui.collapsing("Diagnostics", |ui| {
+                    let stats = self.stats;
+                    ui.label(format!("Frame: {}", self.frame));
+                    ui.label(format!("Body Count: {}", stats.body_count));
+                    ui.label(format!("Total Charge: {:.3}", stats.total_charge));
+                    ui.label(format!("Kinetic Energy: {:.4}", stats.kinetic_energy));
+                    ui.label(format!("Potential Energy: {:.4}", stats.potential_energy));
+                    ui.label(format!("Total Energy: {:.4}", stats.kinetic_energy + stats.potential_energy));
+
+                    ui.add(egui::Slider::new(&mut parameters.stats_interval, 1..=120).text("Update Every N Frames"));
+                });
+
+                // Keys for each action; click one, then press the new key
+                ui.collapsing("Controls", |ui| {
+                    egui::Grid::new("controls").striped(true).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.label());
+
+                            let text = if self.rebinding == Some(action) {
+                                egui::RichText::new("Press a key...").color(egui::Color32::YELLOW)
+                            } else if self.keymap.conflicts(action) {
+                                egui::RichText::new(keymap::key_name(self.keymap.key(action))).color(egui::Color32::RED)
+                            } else {
+                                egui::RichText::new(keymap::key_name(self.keymap.key(action)))
+                            };
+                            if ui.button(text).clicked() {
+                                self.rebinding = Some(action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        save_keymap = ui.button("Save Keymap").clicked();
+                        if ui.button("Reset To Defaults").clicked() {
+                            self.keymap = Keymap::default();
+                        }
+                    });
+                    if let Some(status) = &self.keymap_status {
+                        ui.label(status);
+                    }
+                });
+
+                // Colors
+                ui.collapsing("Colors", |ui| {
+                    egui::Grid::new("colors").show(ui, |ui| {
+                        for (label, color) in self.palette.entries() {
+                            ui.label(label);
+                            ui.color_edit_button_srgba_unmultiplied(color);
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Default").clicked() {
+                            self.palette = Palette::default();
+                        }
+                        if ui.button("High Contrast").clicked() {
+                            self.palette = Palette::high_contrast();
+                        }
+                        save_palette = ui.button("Save Colors").clicked();
+                    });
+                    if let Some(status) = &self.palette_status {
+                        ui.label(status);
+                    }
+                });
+
+                // Performance
+                ui.collapsing("Performance", |ui| {
+                    let performance = self.performance;
+                    ui.label(format!("Field Backend: {}", self.field_backend));
+                    ui.label(format!("Field Calculations: {}", performance.calcs));
+                    ui.label(format!("Tree Nodes: {}", performance.nodes));
+                    ui.label(format!("Tree Depth: {}", performance.max_depth));
+                    ui.label(format!("Step Time: {:.2} ms", performance.step_time * 1000.0));
+
+                    // Averaged phase times, with the rest of the step as "Other"
+                    let measured = performance.build_time + performance.force_time + performance.plate_time + performance.integrate_time;
+                    let phases = [
+                        ("Tree Build", performance.build_time, egui::Color32::from_rgb(230, 160, 60)),
+                        ("Force Evaluation", performance.force_time, egui::Color32::from_rgb(220, 80, 80)),
+                        ("Plate Fields", performance.plate_time, egui::Color32::from_rgb(90, 150, 230)),
+                        ("Integration", performance.integrate_time, egui::Color32::from_rgb(100, 200, 120)),
+                        ("Other", (performance.step_time - measured).max(0.0), egui::Color32::GRAY),
+                        ("Publish", performance.publish_time, egui::Color32::from_rgb(180, 120, 220)),
+                    ];
+
+                    let total: f32 = phases.iter().map(|phase| phase.1).sum();
+                    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 10.0), egui::Sense::hover());
+                    let mut x = rect.left();
+                    for (_, time, color) in phases {
+                        let width = rect.width() * time / total.max(f32::EPSILON);
+                        let bar = egui::Rect::from_min_size(egui::pos2(x, rect.top()), egui::vec2(width, rect.height()));
+                        ui.painter().rect_filled(bar, 0.0, color);
+                        x += width;
+                    }
+
+                    for (name, time, color) in phases {
+                        ui.colored_label(color, format!("{}: {:.2} ms", name, time * 1000.0));
+                    }
+                });
+
+                // Scene file
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.scene_path);
+                    save_scene = ui.button("Save Scene").clicked();
+                    load_scene = ui.button("Load Scene").clicked();
+                });
+                if let Some(status) = &self.scene_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Screenshot").clicked() {
+                        self.pending_screenshot = Some(self.screenshot_resolution);
+                    }
+                    for resolution in SCREENSHOT_RESOLUTIONS {
+                        ui.radio_value(&mut self.screenshot_resolution, resolution, format!("{}x", resolution));
+                    }
+                });
+                if let Some(status) = &self.screenshot_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Seed:");
+                    ui.add(egui::DragValue::new(&mut self.seed));
+                    reseed = ui.button("Reseed").clicked();
+                });
+
+                ui.checkbox(&mut self.show_overlay, "Show Performance Overlay");
+                ui.checkbox(&mut self.show_coordinates, "Show Coordinates And Scale");
+                ui.checkbox(&mut self.show_bodies, "Show Bodies");
+                ui.checkbox(&mut self.show_quadtree, "Show Quadtree");
+                ui.checkbox(&mut self.show_plates, "Show Plates");
+                ui.checkbox(&mut self.show_drift, "Show Drift");
+                ui.checkbox(&mut self.show_labels, "Show Plate Labels");
+                ui.checkbox(&mut self.show_plate_list, "Show Plate List");
+                ui.checkbox(&mut self.show_plot, "Show Plot");
+                ui.checkbox(&mut self.show_scope, "Show Scope");
+                ui.checkbox(&mut self.show_field, "Show Field");
+                ui.checkbox(&mut self.show_heatmap, "Show Field Heatmap");
+                ui.checkbox(&mut self.show_readout, "Probe Field At Cursor");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_body_vectors, "Show Body Field Vectors");
+                    ui.checkbox(&mut self.body_vector_log, "Log");
+                });
+                if self.show_body_vectors {
+                    ui.add(egui::Slider::new(&mut self.body_vector_scale, 0.1..=100.0).logarithmic(true).text("Vector Scale"));
+                    ui.add(egui::Slider::new(&mut self.body_vector_min, 1e-5..=1.0).logarithmic(true).text("Min |E|"));
+                }
+                ui.checkbox(&mut self.show_trails, "Show Trails");
+                if self.show_trails {
+                    ui.add(egui::Slider::new(&mut self.trail_length, 2..=100).text("Trail Length"));
+                    ui.add(egui::Slider::new(&mut self.trail_stride, 1..=100).logarithmic(true).text("Trail Every Nth Body"));
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_streamlines, "Show Streamlines");
+                    ui.checkbox(&mut self.streamline_batteries, "From Batteries");
+                    if ui.button(format!("Clear {} Seeds", self.streamline_seeds.len())).clicked() {
+                        self.streamline_seeds.clear();
+                    }
+                });
+                if self.show_streamlines {
+                    ui.add(egui::Slider::new(&mut self.streamline_density, 0.1..=4.0).text("Seeds Per Cell"));
+                    ui.add(egui::Slider::new(&mut self.streamline_steps, 10..=2000).logarithmic(true).text("Max Steps"));
+                }
+                
+                ui.add(egui::Slider::new(&mut parameters.dt, 0.1..=1.0).text("Time Step"));
+                {
+                    ui.add(egui::Slider::new(&mut parameters.steps_per_frame, 1..=32).text("Steps Per Frame"));
+                    ui.label(format!("Steps/sec: {:.0}", self.performance.steps_per_second));
+
+                    // Ticks are one batch of steps plus a publication; red when the sim can't keep up
+                    ui.add(egui::Slider::new(&mut parameters.target_ticks_per_second, 1.0..=240.0).text("Target Ticks/sec"));
+                    let ticks = self.performance.ticks_per_second;
+                    let label = format!("Ticks/sec: {:.0}", ticks);
+                    if ticks < parameters.target_ticks_per_second * 0.95 && !PAUSED.load(Ordering::Relaxed) {
+                        ui.colored_label(egui::Color32::RED, label);
+                    } else {
+                        ui.label(label);
+                    }
+                }
+                ui.add(egui::Slider::new(&mut parameters.max_speed, 0.0..=50.0).text("Max Speed (0 = off)"));
+                ui.horizontal(|ui| {
+                    ui.label("Integration:");
+                    ui.radio_value(&mut parameters.integration_mode, IntegrationMode::Drift, "Drift");
+                    ui.radio_value(&mut parameters.integration_mode, IntegrationMode::Inertial, "Inertial");
+                    ui.radio_value(&mut parameters.integration_mode, IntegrationMode::Verlet, "Verlet");
+                    ui.radio_value(&mut parameters.integration_mode, IntegrationMode::Rk4, "RK4");
+                });
+                ui.add(egui::Slider::new(&mut parameters.qe, 1e-2..=1.0).text("Electron Charge"));
+                ui.add(egui::Slider::new(&mut parameters.qp, 1e-3..=1.0e-1).text("Plate Charge"));
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("Force Method:");
+                        ui.radio_value(&mut parameters.force_method, ForceMethod::BarnesHut, "Barnes-Hut");
+                        ui.radio_value(&mut parameters.force_method, ForceMethod::Direct, "Direct");
+                        ui.radio_value(&mut parameters.force_method, ForceMethod::Compare, "Compare");
+                    });
+
+                    if parameters.force_method == ForceMethod::Compare {
+                        match self.readings.force_error {
+                            Some((max, rms)) => {
+                                ui.label(format!("Max Error: {:.3}%  RMS Error: {:.3}%", max * 100.0, rms * 100.0));
+                            },
+                            None => {
+                                ui.label(format!("Compare needs at most {} bodies", simulation::COMPARE_LIMIT));
+                            }
+                        }
+                    }
+                }
+                ui.checkbox(&mut parameters.fixed_root, "Fixed Root Quad (around plates)");
+                if parameters.fixed_root {
+                    ui.add(egui::Slider::new(&mut parameters.fixed_root_margin, 0.0..=500.0).text("Root Margin"));
+                }
+                ui.add(egui::Slider::new(&mut parameters.theta, 0.0..=2.0).text("Theta"));
+                ui.add(egui::Slider::new(&mut parameters.epsilon, quadtree::MIN_EPSILON..=10.0).text("Body Softening"));
+                ui.add(egui::Slider::new(&mut parameters.leaf_capacity, 1..=32).text("Bodies Per Leaf"));
+                ui.checkbox(&mut parameters.morton_sort, "Morton Order Tree Build");
+                ui.checkbox(&mut parameters.dipole, "Dipole Terms (mixed charges)");
+                ui.horizontal(|ui| {
+                    ui.label("Resistor Model:");
+                    ui.radio_value(&mut parameters.resist_model, ResistModel::Scale, "Scale");
+                    ui.radio_value(&mut parameters.resist_model, ResistModel::Scatter, "Scatter");
+                });
+                ui.checkbox(&mut parameters.precise_plates, "Double Precision Plate Fields");
+                ui.add(egui::Slider::new(&mut parameters.plate_epsilon, 0.0..=10.0).text("Plate Corner Softening"));
+                {
+                    ui.checkbox(&mut parameters.interpolate_plates, "Interpolate Plate Fields");
+
+                    if parameters.interpolate_plates {
+                        ui.add(egui::Slider::new(&mut parameters.plate_grid_spacing, 0.5..=10.0).text("Plate Grid Spacing"));
+
+                        if let Some((max, rms)) = self.readings.plate_grid_error {
+                            ui.label(format!("Grid Max Error: {:.3}%  RMS Error: {:.3}%", max * 100.0, rms * 100.0));
+                        }
+                    }
+                }
+                ui.add(egui::Slider::new(&mut parameters.interior_epsilon, quadtree::MIN_EPSILON..=10.0).text("Softening Inside Plates"));
+                ui.checkbox(&mut parameters.induced_charge, "Induced Surface Charge");
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("World Bounds:");
+                        ui.radio_value(&mut parameters.boundary, Boundary::None, "None");
+                        ui.radio_value(&mut parameters.boundary, Boundary::Reflect, "Reflect");
+                        ui.radio_value(&mut parameters.boundary, Boundary::Delete, "Delete");
+                        ui.radio_value(&mut parameters.boundary, Boundary::Periodic, "Periodic");
+                    });
+
+                    if parameters.boundary != Boundary::None {
+                        let size = &mut parameters.world_size;
+                        ui.horizontal(|ui| {
+                            ui.label("World Size:");
+                            ui.add(egui::DragValue::new(&mut size.x).speed(10.0).clamp_range(10.0..=10000.0));
+                            ui.label("x");
+                            ui.add(egui::DragValue::new(&mut size.y).speed(10.0).clamp_range(10.0..=10000.0));
+                        });
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut parameters.separation, "Hard-Core Repulsion");
+                    ui.add(egui::Slider::new(&mut parameters.separation_strength, 0.0..=1.0).text("Strength"));
+                });
+                ui.add(egui::Slider::new(&mut parameters.temperature, 0.0..=1.0).text("Temperature"));
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::Slider::new(&mut grid_size, MIN_GRID_SIZE..=MAX_GRID_SIZE).logarithmic(true).text("Grid Size"));
+                    ui.checkbox(&mut snap_to_grid, "Snap To Grid");
+                });
+                ui.add(egui::Slider::new(&mut self.body_density, 1..=6).text("Electron Density"));
+                ui.horizontal(|ui| {
+                    ui.label("Max Bodies:");
+                    ui.add(egui::DragValue::new(&mut self.max_bodies).speed(1000.0));
+                    ui.label(format!("({} now)", self.bodies.len()));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Undo Depth:");
+                    ui.add(egui::DragValue::new(&mut self.history.depth).clamp_range(1..=10_000));
+                    ui.label(format!("({} to undo, {} to redo)", self.history.len(), self.history.redo_len()));
+                });
+                ui.checkbox(&mut self.duplicate_bodies, "Duplicate Bodies With Plates (Ctrl+D)");
+                if let Some(warning) = &self.body_warning {
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                }
+                ui.horizontal(|ui| {
+                    decimate = ui.button("Decimate To").clicked();
+                    ui.add(egui::DragValue::new(&mut self.decimate_target).speed(1000.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Electron Sign:");
+                    ui.radio_value(&mut self.body_charge, ELECTRON_CHARGE, "Negative");
+                    ui.radio_value(&mut self.body_charge, -ELECTRON_CHARGE, "Positive");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Fill With:");
+                    ui.radio_value(&mut self.body_fill, BodyFill::Electrons, "Electrons");
+                    ui.radio_value(&mut self.body_fill, BodyFill::Ions, "Ions");
+                    ui.radio_value(&mut self.body_fill, BodyFill::Neutral, "Both (Neutral)");
+                    ui.radio_value(&mut self.body_fill, BodyFill::ElectronsAndHoles, "Electrons + Holes");
+                });
+                ui.add(egui::Slider::new(&mut self.ion_mass, 1.0..=5000.0).logarithmic(true).text("Ion Mass"));
+                ui.add(egui::Slider::new(&mut self.battery_strength, -5.0..=5.0).text("Battery Strength"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::Slider::new(&mut self.battery_direction, 0.0..=360.0).text("Direction"));
+                    ui.selectable_value(&mut self.battery_direction, 0.0, "→");
+                    ui.selectable_value(&mut self.battery_direction, 90.0, "↑");
+                    ui.selectable_value(&mut self.battery_direction, 180.0, "←");
+                    ui.selectable_value(&mut self.battery_direction, 270.0, "↓");
+                });
+                ui.add(egui::Slider::new(&mut self.battery_frequency, 0.0..=0.1).text("Battery Frequency (0 = DC)"));
+                ui.add(egui::Slider::new(&mut self.battery_phase, 0.0..=std::f32::consts::TAU).text("Battery Phase"));
+                ui.add(egui::Slider::new(&mut self.battery_gain, 0.0..=0.1).text("Battery Regulator Gain (0 = off)"));
+                ui.add(egui::Slider::new(&mut self.battery_target, 0.0..=5.0).text("Battery Target Current"));
+                ui.horizontal(|ui| {
+                    ui.label("Battery Profile:");
+                    ui.radio_value(&mut self.battery_profile, DriveProfile::Uniform, "Uniform");
+                    ui.radio_value(&mut self.battery_profile, DriveProfile::Triangular, "Triangular");
+                    ui.radio_value(&mut self.battery_profile, DriveProfile::Smoothstep, "Smoothstep");
+                });
+                ui.add(egui::Slider::new(&mut self.resistor_strength, 0.0..=1.0).text("Resistor Strength"));
+                ui.add(egui::Slider::new(&mut self.resistor_alpha, 0.0..=1.0).text("Resistor Temperature Coefficient"));
+                ui.add(egui::Slider::new(&mut self.point_charge, -50.0..=50.0).text("Fixed Charge (q to place)"));
+                ui.add(egui::Slider::new(&mut self.emitter_rate, 0.0..=10.0).text("Emitter Rate"));
+                ui.add(egui::Slider::new(&mut self.epsilon_r, 1.0..=10.0).text("Dielectric Permittivity (εr)"));
+                if self.selection_active && self.mixed_settings {
+                    ui.label("Selected plates differ; moving a slider sets them all");
+                }
+
+                if self.selection_active && !self.selected_plate_indicies.is_empty() {
+                    let slider = egui::Slider::new(&mut self.plate_charge_scale, -5.0..=5.0).text("Plate Charge Scale");
+                    self.plate_charge_scale_changed |= ui.add(slider).changed();
+                }
+                if self.selection_active && self.selected_plate_indicies.len() == 1 {
+                    ui.horizontal(|ui| {
+                        ui.label("Plate Name:");
+                        self.plate_name_changed |= ui.text_edit_singleline(&mut self.plate_name).changed();
+                    });
+                }
+    
+                if self.show_quadtree {
+                    let range = &mut self.depth_range;
+                    ui.horizontal(|ui| {
+                        ui.label("Depth Range:");
+                        ui.add(egui::DragValue::new(&mut range.0).speed(0.05));
+                        ui.label("to");
+                        ui.add(egui::DragValue::new(&mut range.1).speed(0.05));
+                    });
+                }
+            });
+
+        // Inspected body, updated with every snapshot; closing the window stops inspecting
+        let inspected = self.readings.inspected;
+        let mut inspecting = inspected.is_some();
+        let mut body_edit = None;
+        if let Some(body) = inspected {
+            let plate = self.plates.iter().find(|plate| plate.contains_point(body.pos));
+            egui::Window::new("Body")
+                .open(&mut inspecting)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Id: {}", body.id));
+                    ui.label(format!("Position: ({:.2}, {:.2})", body.pos.x, body.pos.y));
+                    ui.label(format!("Field: {:.4} ({:.4}, {:.4})", body.efield.mag(), body.efield.x, body.efield.y));
+                    ui.label(format!("Resist: {:.3}", body.resist));
+                    ui.label(format!("Charge: {:.2}{}", body.charge, if body.fixed { " (fixed)" } else { "" }));
+                    ui.label(match plate {
+                        Some(plate) if plate.name.is_empty() => format!("Plate: #{} {:?}", plate.id, plate.plate_type),
+                        Some(plate) => format!("Plate: #{} {} ({:?})", plate.id, plate.name, plate.plate_type),
+                        None => "Plate: none".to_string(),
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Delete").clicked() {
+                            body_edit = Some(EditCommand::RemoveBody { id: body.id });
+                        }
+                        if ui.add_enabled(!body.fixed, egui::Button::new("Pin")).clicked() {
+                            body_edit = Some(EditCommand::PinBody { id: body.id });
+                        }
+                    });
+                });
+        }
+
+        let deleted = matches!(body_edit, Some(EditCommand::RemoveBody { .. }));
+        if (inspected.is_some() && !inspecting) || deleted {
+            self.stop_inspecting();
+        }
+        if body_edit.is_some() {
+            self.pending_body_edit = body_edit;
+        }
+
+        // Plot of the chosen measurement over the last plot_length snapshots
+        let mut export_plot = false;
+        let mut clear_plot = false;
+        let plot_plate = self.plates.iter().find(|plate| Some(plate.id) == self.plot_plate);
+        egui::Window::new("Plot")
+            .open(&mut self.show_plot)
+            .show(ctx, |ui| {
+                let quantity = self.plot_quantity;
+                ui.horizontal(|ui| {
+                    for option in PlotQuantity::ALL {
+                        ui.radio_value(&mut self.plot_quantity, option, option.label());
+                    }
+                });
+                clear_plot |= self.plot_quantity != quantity;
+
+                if matches!(self.plot_quantity, PlotQuantity::Current | PlotQuantity::BatteryStrength) {
+                    ui.label(match plot_plate {
+                        Some(plate) if plate.name.is_empty() => format!("Plate #{} {:?}", plate.id, plate.plate_type),
+                        Some(plate) => format!("Plate #{} {} ({:?})", plate.id, plate.name, plate.plate_type),
+                        None => "Select a plate to follow".to_string(),
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.plot_paused, "Pause");
+                    clear_plot |= ui.button("Clear").clicked();
+                    ui.add(egui::DragValue::new(&mut self.plot_length).clamp_range(10..=100_000).prefix("Samples: "));
+                });
+
+                let line = egui::plot::Line::new(egui::plot::PlotPoints::from_iter(self.plot_samples.iter().copied()))
+                    .name(self.plot_quantity.label());
+                egui::plot::Plot::new("plot")
+                    .height(200.0)
+                    .x_axis_formatter(|frame, _| format!("{:.0}", frame))
+                    .show(ui, |plot_ui| plot_ui.line(line));
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.plot_path);
+                    export_plot = ui.button("Export CSV").clicked();
+                });
+                if let Some(status) = &self.plot_status {
+                    ui.label(status);
+                }
+            });
+
+        if clear_plot {
+            self.plot_samples.clear();
+        }
+        if export_plot {
+            self.export_plot();
+        }
+
+        // Scope of the probe channels over the last scope_length steps
+        let mut add_probe = None;
+        let mut remove_channel = None;
+        let mut clear_scope = false;
+        let probe = match self.probe_points[..] {
+            [a, b] => Some(ProbeKind::Voltage { a, b }),
+            _ => None,
+        };
+        let ammeter = match self.selected_plate_indicies[..] {
+            [i] => Some(ProbeKind::Ammeter { plate: self.plates[i].id }),
+            _ => None,
+        };
+        let flux_loops = self.instruments.flux_loops.clone();
+        egui::Window::new("Scope")
+            .open(&mut self.show_scope)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(probe.is_some(), egui::Button::new("Add Voltage Probe"))
+                        .on_disabled_hover_text("Place the voltage probe's two points first").clicked() {
+                        add_probe = probe;
+                    }
+                    if ui.add_enabled(ammeter.is_some(), egui::Button::new("Add Ammeter"))
+                        .on_disabled_hover_text("Select a single plate first").clicked() {
+                        add_probe = ammeter;
+                    }
+                    ui.add_enabled_ui(!flux_loops.is_empty(), |ui| {
+                        ui.menu_button("Add Flux Loop", |ui| {
+                            for (i, &(min, max)) in flux_loops.iter().enumerate() {
+                                if ui.button(format!("Loop {}", i + 1)).clicked() {
+                                    add_probe = Some(ProbeKind::FluxLoop { min, max });
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                });
+
+                for channel in &mut self.scope_channels {
+                    ui.horizontal(|ui| {
+                        let [r, g, b, _] = channel.color;
+                        ui.colored_label(egui::Color32::from_rgb(r, g, b), "■");
+                        ui.checkbox(&mut channel.enabled, &channel.name);
+                        ui.add(egui::DragValue::new(&mut channel.scale).speed(0.01).clamp_range(1e-4..=1e4).prefix("×"));
+                        if ui.small_button("Remove").clicked() {
+                            remove_channel = Some(channel.probe.id);
+                        }
+                    });
+                }
+
+                // Trigger
+                ui.horizontal(|ui| {
+                    let name = |id: Option<u32>| {
+                        self.scope_channels.iter().find(|channel| Some(channel.probe.id) == id).map_or("None", |channel| channel.name.as_str()).to_string()
+                    };
+                    egui::ComboBox::from_label("Trigger")
+                        .selected_text(name(self.trigger_channel))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.trigger_channel, None, "None");
+                            for channel in &self.scope_channels {
+                                ui.selectable_value(&mut self.trigger_channel, Some(channel.probe.id), &channel.name);
+                            }
+                        });
+                    ui.radio_value(&mut self.trigger_edge, TriggerEdge::Rising, "Rising");
+                    ui.radio_value(&mut self.trigger_edge, TriggerEdge::Falling, "Falling");
+                    ui.add(egui::DragValue::new(&mut self.trigger_level).speed(0.01).prefix("Level: "));
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.trigger_channel.is_some(), egui::Button::new("Arm")).clicked() {
+                        self.trigger_state = TriggerState::Armed;
+                        self.trigger_last = None;
+                    }
+                    if ui.button("Free Run").clicked() {
+                        self.trigger_state = TriggerState::FreeRunning;
+                    }
+                    ui.label(match self.trigger_state {
+                        TriggerState::FreeRunning => "Free running".to_string(),
+                        TriggerState::Armed => "Waiting for the trigger".to_string(),
+                        TriggerState::Capturing(start) => format!("Capturing since step {}", start),
+                        TriggerState::Captured(start) => format!("Captured from step {}", start),
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.scope_paused, "Pause");
+                    clear_scope = ui.button("Clear").clicked();
+                    ui.add(egui::DragValue::new(&mut self.scope_length).clamp_range(10..=100_000).prefix("Samples: "));
+                });
+
+                let trigger = self.scope_channels.iter().find(|channel| channel.enabled && Some(channel.probe.id) == self.trigger_channel);
+                egui::plot::Plot::new("scope")
+                    .height(240.0)
+                    .legend(egui::plot::Legend::default())
+                    .x_axis_formatter(|step, _| format!("{:.0}", step))
+                    .show(ui, |plot_ui| {
+                        for channel in self.scope_channels.iter().filter(|channel| channel.enabled) {
+                            let scale = channel.scale as f64;
+                            let points = egui::plot::PlotPoints::from_iter(channel.samples.iter().map(|&[step, value]| [step, value * scale]));
+                            let [r, g, b, _] = channel.color;
+                            plot_ui.line(egui::plot::Line::new(points).color(egui::Color32::from_rgb(r, g, b)).name(&channel.name));
+                        }
+
+                        // Trigger level on the trigger channel's scale, and where the capture started
+                        if let Some(channel) = trigger {
+                            let [r, g, b, _] = channel.color;
+                            plot_ui.hline(egui::plot::HLine::new(self.trigger_level as f64 * channel.scale as f64)
+                                .color(egui::Color32::from_rgb(r, g, b))
+                                .style(egui::plot::LineStyle::dashed_loose()));
+                        }
+                        if let TriggerState::Capturing(start) | TriggerState::Captured(start) = self.trigger_state {
+                            plot_ui.vline(egui::plot::VLine::new(start as f64).color(egui::Color32::GRAY));
+                        }
+                    });
+            });
+
+        if let Some(kind) = add_probe {
+            self.add_scope_channel(kind);
+        }
+        if let Some(id) = remove_channel {
+            self.scope_channels.retain(|channel| channel.probe.id != id);
+            if self.trigger_channel == Some(id) {
+                self.trigger_channel = None;
+                self.trigger_state = TriggerState::FreeRunning;
+            }
+        }
+        if clear_scope {
+            for channel in &mut self.scope_channels {
+                channel.samples.clear();
+            }
+        }
+
+        // Only the enabled channels are read, and none while the scope is closed
+        self.instruments.scope_probes.clear();
+        if self.show_scope {
+            self.instruments.scope_probes.extend(self.scope_channels.iter().filter(|channel| channel.enabled).map(|channel| channel.probe));
+        }
+
+        // Plate list, by id so rows stay put as snapshots come in
+        let mut clicked_plate = None;
+        let mut zoom_plate = None;
+        let mut edited_plate = None;
+        egui::Window::new("Plates")
+            .open(&mut self.show_plate_list)
+            .show(ctx, |ui| {
+                let mut order: Vec<usize> = (0..self.plates.len()).collect();
+                order.sort_by_key(|&i| self.plates[i].id);
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("plate list").striped(true).show(ui, |ui| {
+                        for i in order {
+                            let plate = &self.plates[i];
+                            let selected = self.selection_active && self.selected_plate_indicies.contains(&i);
+                            let title = if plate.name.is_empty() {
+                                format!("#{} {:?}", plate.id, plate.plate_type)
+                            } else {
+                                format!("#{} {} ({:?})", plate.id, plate.name, plate.plate_type)
+                            };
+
+                            let row = ui.selectable_label(selected, title);
+                            if row.double_clicked() {
+                                zoom_plate = Some(i);
+                            }
+                            if row.clicked() {
+                                clicked_plate = Some(i);
+                            }
+
+                            ui.label(format!("({:.0}, {:.0}) to ({:.0}, {:.0})", plate.min.x, plate.min.y, plate.max.x, plate.max.y));
+
+                            match plate.key_parameter() {
+                                // Stored charge is built up by the simulation, which keeps its own over an edit
+                                Some((name, value)) if plate.plate_type == PlateType::Capacitor => {
+                                    ui.label(format!("{} {:.2}", name, value));
+                                },
+                                Some((name, value)) => {
+                                    let mut value = value;
+                                    if ui.add(egui::DragValue::new(&mut value).speed(0.01).prefix(format!("{} ", name))).changed() {
+                                        edited_plate = Some((i, value));
+                                    }
+                                },
+                                None => {
+                                    ui.label("");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+
+        if let Some(idx) = clicked_plate {
+            self.select_plate(idx);
+        }
+        if let Some(idx) = zoom_plate {
+            self.zoom_to_plate(idx);
+        }
+        if let Some((idx, value)) = edited_plate {
+            // Editing a row selects it, so the sliders follow instead of undoing the edit
+            let before = self.plates[idx].clone();
+            self.plates[idx].set_key_parameter(value);
+            self.send_plate_change(idx, before);
+            self.select_plate(idx);
+        }
+
+        if save_scene {
+            self.save_scene();
+        }
+        if save_keymap {
+            let path = Keymap::path();
+            self.keymap_status = Some(match self.keymap.save(&path) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(err) => format!("Couldn't save {}: {}", path.display(), err),
+            });
+        }
+        if save_palette {
+            let path = Palette::path();
+            self.palette_status = Some(match self.palette.save(&path) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(err) => format!("Couldn't save {}: {}", path.display(), err),
+            });
+        }
+        if load_scene {
+            self.load_scene();
+        }
+        if clear_ruler {
+            self.ruler_points.clear();
+            self.instruments.ruler = None;
+        }
+        if reseed {
+            self.reseed();
+        }
+        if decimate {
+            self.decimate();
+        }
+        if grid_size != self.grid_size || snap_to_grid != self.snap_to_grid {
+            self.set_grid(grid_size, snap_to_grid);
+        }
+        if parameters != self.parameters {
+            self.parameters = parameters;
+            self.send_edit(EditCommand::SetParameters(parameters));
+        }
+        self.instruments.quadtree = self.show_quadtree;
+        self.send_instruments();
+
+        // Everything changed this frame undoes in one step
+        self.history.finish_action();
+
+        self.typing = ctx.wants_keyboard_input();
+        self.pointer_on_gui = ctx.wants_pointer_input();
+    }
+    
+}
+
+// Draw rows of chevrons inside a battery plate pointing along its drive, closer together the stronger it is
+fn draw_battery_chevrons(ctx: &mut Canvas, plate: &Plate) {
+    let drive = if plate.gain > 0.0 { plate.direction_vec() * plate.regulated_emf } else { plate.efield };
+    let strength = drive.mag();
+    if strength == 0.0 {
+        return;
+    }
+
+    let center = (plate.min + plate.max) * 0.5;
+    let half = (plate.max - plate.min) * 0.5;
+    let dir = drive / strength;
+    let normal = Vec2::new(-dir.y, dir.x);
+
+    // Extent of the plate along and across the drive
+    let along = half.x * dir.x.abs() + half.y * dir.y.abs();
+    let across = half.x * normal.x.abs() + half.y * normal.y.abs();
+
+    // Spacing shrinks with strength, but stays coarse enough to keep the count down on big plates
+    let mut spacing = BATTERY_CHEVRON_SPACING / strength.clamp(0.25, 4.0);
+    let mut lane = BATTERY_CHEVRON_SPACING;
+    let count = (along * 2.0 / spacing) * (across * 2.0 / lane);
+    if count > BATTERY_CHEVRON_MAX as f32 {
+        let grow = (count / BATTERY_CHEVRON_MAX as f32).sqrt();
+        spacing *= grow;
+        lane *= grow;
+    }
+    let arm = (lane * 0.3).min(spacing * 0.4);
+
+    // Whole chevrons only, centered on the plate in both directions
+    let columns = ((along - arm) / spacing).floor().max(0.0) as i32;
+    let rows = ((across - arm) / lane).floor().max(0.0) as i32;
+    let mut drawn = false;
+    for row in -rows..=rows {
+        for column in -columns..=columns {
+            let pos = center + dir * (column as f32 * spacing) + normal * (row as f32 * lane);
+            if (pos - center).x.abs() + arm > half.x || (pos - center).y.abs() + arm > half.y {
+                continue;
+            }
+            draw_chevron(ctx, pos, dir, arm);
+            drawn = true;
+        }
+    }
+
+    // Plates too small for the pattern still get one, scaled to fit
+    if !drawn {
+        draw_chevron(ctx, center, dir, half.x.min(half.y) * 0.6);
+    }
+}
+
+fn draw_chevron(ctx: &mut Canvas, pos: Vec2, dir: Vec2, arm: f32) {
+    let normal = Vec2::new(-dir.y, dir.x);
+    let tip = pos + dir * arm * 0.5;
+    let back = pos - dir * arm * 0.5;
+
+    ctx.draw_line(tip, back + normal * arm, [200, 255, 200, 255]);
+    ctx.draw_line(tip, back - normal * arm, [200, 255, 200, 255]);
+}
+
+// Resistor fill, light for plates that conduct freely and dark for ones that block
+fn resistor_color(resist: f32, light: [u8; 4]) -> [u8; 4] {
+    let t = resist.clamp(0.0, 1.0);
+    let channel = |i: usize| (light[i] as f32 * (0.15 + 0.85 * t)) as u8;
+    return [channel(0), channel(1), channel(2), light[3]];
+}
+
+// Ramp of resistor shades along the bottom of the screen, above the heatmap legend if that's shown
+fn draw_resistor_legend(ctx: &egui::Context, raised: bool, light: [u8; 4]) {
+    let segments = 16;
+    let size = egui::vec2(200.0, 12.0);
+    let min = ctx.screen_rect().left_bottom() + egui::vec2(10.0, if raised { -60.0 } else { -30.0 });
+    let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("resistor legend")));
+
+    for i in 0..segments {
+        let color = resistor_color(i as f32 / (segments - 1) as f32, light);
+        let x0 = min.x + size.x * i as f32 / segments as f32;
+        let x1 = min.x + size.x * (i + 1) as f32 / segments as f32;
+        painter.rect_filled(
+            egui::Rect::from_min_max(egui::pos2(x0, min.y), egui::pos2(x1, min.y + size.y)),
+            0.0,
+            egui::Color32::from_rgb(color[0], color[1], color[2]),
+        );
+    }
+
+    let font = egui::FontId::proportional(12.0);
+    painter.text(min, egui::Align2::LEFT_BOTTOM, "Resist 0 (blocking)", font.clone(), egui::Color32::WHITE);
+    painter.text(min + egui::vec2(size.x, 0.0), egui::Align2::RIGHT_BOTTOM, "1 (conducting)", font, egui::Color32::WHITE);
+}
+
+// Longest 1, 2 or 5 times a power of ten that fits in max
+fn nice_length(max: f32) -> f32 {
+    let power = 10f32.powf(max.log10().floor());
+    let mantissa = max / power;
+    let nice = if mantissa >= 5.0 { 5.0 } else if mantissa >= 2.0 { 2.0 } else { 1.0 };
+    return nice * power;
+}
+
+// A scale bar length, with just the decimals a nice_length needs
+fn length_label(length: f32) -> String {
+    let decimals = (-length.log10().floor()).clamp(0.0, 6.0) as usize;
+    let unit = if (length - 1.0).abs() < 1e-6 { "unit" } else { "units" };
+    return format!("{:.*} {}", decimals, length, unit);
+}
+
+// Draw the ruler from a to b, with a tick across it every spacing
+fn draw_ruler(ctx: &mut Canvas, a: Vec2, b: Vec2, spacing: f32, tick: f32) {
+    let color = [255, 255, 255, 255];
+    ctx.draw_line(a, b, color);
+
+    let length = (b - a).mag();
+    if length == 0.0 {
+        return;
+    }
+    let dir = (b - a) / length;
+    let normal = Vec2::new(-dir.y, dir.x);
+
+    // Longer ticks at the ends
+    ctx.draw_line(a - normal * tick * 2.0, a + normal * tick * 2.0, color);
+    ctx.draw_line(b - normal * tick * 2.0, b + normal * tick * 2.0, color);
+
+    let ticks = ((length / spacing) as usize).min(RULER_MAX_TICKS);
+    for i in 1..=ticks {
+        let pos = a + dir * spacing * i as f32;
+        ctx.draw_line(pos - normal * tick, pos + normal * tick, color);
+    }
+}
+
+// Whether two plates of the same type would load the same slider values
+fn same_settings(a: &Plate, b: &Plate) -> bool {
+    let same = match a.plate_type {
+        PlateType::Battery => {
+            (a.battery_strength(), a.direction, a.frequency, a.phase, a.profile, a.gain, a.target_current)
+                == (b.battery_strength(), b.direction, b.frequency, b.phase, b.profile, b.gain, b.target_current)
+        },
+        PlateType::Resistor => (a.resist, a.alpha) == (b.resist, b.alpha),
+        PlateType::Diode => a.direction == b.direction,
+        PlateType::Emitter => a.emit_rate == b.emit_rate,
+        PlateType::Dielectric => a.epsilon_r == b.epsilon_r,
+        _ => true,
+    };
+
+    return same && a.charge_scale == b.charge_scale;
+}
+
+// Draw a triangle inside a diode plate pointing along the allowed direction
+fn draw_diode_marker(ctx: &mut Canvas, plate: &Plate) {
+    let center = (plate.min + plate.max) * 0.5;
+    let size = (plate.max.x - plate.min.x).min(plate.max.y - plate.min.y);
+    let dir = plate.direction_vec();
+    let normal = Vec2::new(-dir.y, dir.x);
+
+    let tip = center + dir * size * 0.3;
+    let back = center - dir * size * 0.3;
+    let left = back + normal * size * 0.3;
+    let right = back - normal * size * 0.3;
+
+    ctx.draw_line(tip, left, [255, 200, 255, 255]);
+    ctx.draw_line(left, right, [255, 200, 255, 255]);
+    ctx.draw_line(right, tip, [255, 200, 255, 255]);
+    ctx.draw_line(tip + normal * size * 0.3, tip - normal * size * 0.3, [255, 200, 255, 255]);
+}
+
+// Draw an arrow from the plate center along the mean drift, scaled by its speed
+fn draw_drift_arrow(ctx: &mut Canvas, plate: &Plate, drift: Vec2) {
+    let speed = drift.mag();
+    if speed <= 0.0 {
+        return;
+    }
+
+    let center = (plate.min + plate.max) * 0.5;
+    let size = (plate.max.x - plate.min.x).min(plate.max.y - plate.min.y);
+    let dir = drift / speed;
+    let normal = Vec2::new(-dir.y, dir.x);
+
+    // One cell of arrow per unit of drift per step, capped to the plate
+    let length = (speed * 10.0).min(size * 0.5);
+    let head = center + dir * length;
+    let barb = length * 0.3;
+
+    ctx.draw_line(center, head, [255, 220, 80, 255]);
+    ctx.draw_line(head, head - dir * barb + normal * barb, [255, 220, 80, 255]);
+    ctx.draw_line(head, head - dir * barb - normal * barb, [255, 220, 80, 255]);
+}
+
+// A plate's type and the settings that matter for it
+fn plate_summary(plate: &Plate) -> String {
+    return match plate.plate_type {
+        PlateType::Battery => format!(
+            "Battery: strength {:.2}, direction {:.0}°, frequency {:.2}",
+            plate.battery_strength(), plate.direction.to_degrees(), plate.frequency,
+        ),
+        PlateType::Resistor => format!("Resistor: resist {:.2}, alpha {:.2}", plate.resist, plate.alpha),
+        PlateType::Capacitor => format!("Capacitor: stored charge {:.2}", plate.stored_charge),
+        PlateType::Diode => format!("Diode: direction {:.0}°", plate.direction.to_degrees()),
+        PlateType::Emitter => format!("Emitter: rate {:.2}", plate.emit_rate),
+        PlateType::Dielectric => format!("Dielectric: epsilon_r {:.2}", plate.epsilon_r),
+        plate_type => format!("{:?}", plate_type),
+    };
+}
+
+// Sides of the plate's outline within tolerance of pos, or None when pos is nowhere near it
+fn edges_near(plate: &Plate, pos: Vec2, tolerance: f32) -> Option<ResizeEdges> {
+    let near_x = pos.x > plate.min.x - tolerance && pos.x < plate.max.x + tolerance;
+    let near_y = pos.y > plate.min.y - tolerance && pos.y < plate.max.y + tolerance;
+    if !(near_x && near_y) {
+        return None;
+    }
+
+    // On a plate thinner than the handles, take whichever side is closer
+    let (left, right) = ((pos.x - plate.min.x).abs(), (pos.x - plate.max.x).abs());
+    let (bottom, top) = ((pos.y - plate.min.y).abs(), (pos.y - plate.max.y).abs());
+    let edges = ResizeEdges {
+        left: left < tolerance && left <= right,
+        right: right < tolerance && right < left,
+        bottom: bottom < tolerance && bottom <= top,
+        top: top < tolerance && top < bottom,
+    };
+
+    if edges.left || edges.right || edges.bottom || edges.top {
+        Some(edges)
+    } else {
+        None
+    }
+}
+
+// Plate bounds with the dragged edges moved to the snapped cursor, keeping at least one cell
+fn resized_bounds(plate: &Plate, edges: ResizeEdges, snapped: Vec2, grid_size: f32) -> (Vec2, Vec2) {
+    let (mut min, mut max) = (plate.min, plate.max);
+    if edges.left {
+        min.x = snapped.x.min(max.x - grid_size);
+    }
+    if edges.right {
+        max.x = snapped.x.max(min.x + grid_size);
+    }
+    if edges.bottom {
+        min.y = snapped.y.min(max.y - grid_size);
+    }
+    if edges.top {
+        max.y = snapped.y.max(min.y + grid_size);
+    }
+
+    (min, max)
+}
+
+// Draw a trail that fades out towards its oldest end
+fn draw_trail(ctx: &mut Canvas, trail: &VecDeque<Vec2>) {
+    let count = trail.len();
+    for (i, (&from, &to)) in trail.iter().zip(trail.iter().skip(1)).enumerate() {
+        let alpha = (255 * (i + 1) / count) as u8;
+        ctx.draw_line(from, to, [200, 220, 255, alpha]);
+    }
+}
+
+// Draw a field line with an arrowhead halfway along, pointing along the field
+fn draw_streamline(ctx: &mut Canvas, line: &[Vec2], head_size: f32) {
+    let color = [160, 255, 160, 200];
+    for pair in line.windows(2) {
+        ctx.draw_line(pair[0], pair[1], color);
+    }
+
+    if line.len() < 2 {
+        return;
+    }
+    let mid = line.len() / 2;
+    let (from, to) = (line[mid - 1], line[mid]);
+    if to == from {
+        return;
+    }
+    let dir = (to - from).normalized();
+    let normal = Vec2::new(-dir.y, dir.x);
+
+    ctx.draw_line(to, to - dir * head_size + normal * head_size * 0.6, color);
+    ctx.draw_line(to, to - dir * head_size - normal * head_size * 0.6, color);
+}
+
+// Range of log |E| over the samples, ignoring zero and non-finite ones
+fn log_magnitude_range(efield: &[Vec2]) -> Option<(f32, f32)> {
+    let mut range: Option<(f32, f32)> = None;
+    for e in efield {
+        let log = e.mag().ln();
+        if log.is_finite() {
+            range = Some(match range {
+                Some((low, high)) => (low.min(log), high.max(log)),
+                None => (log, log),
+            });
+        }
+    }
+
+    range
+}
+
+// Color for a magnitude at t between the weakest (0) and strongest (1), on the quadtree's hue ramp
+fn magnitude_color(t: f32) -> [u8; 4] {
+    let start_h = -100.0;
+    let end_h = 80.0;
+    let c = Hsluv::new(start_h + (end_h - start_h) * t, 100.0, 40.0 + 40.0 * t);
+    let rgba: Rgba = c.into_color();
+    let color: [u8; 4] = rgba.into_format().into();
+    [color[0], color[1], color[2], 255]
+}
+
+// Draw an arrow on every stride-th sample, sized and colored by log magnitude and kept inside its stride x stride block
+fn draw_field_arrows(ctx: &mut Canvas, field: &FieldSamples, stride: usize) {
+    let Some((low, high)) = log_magnitude_range(&field.efield) else { return };
+    let request = &field.request;
+    let cell = (request.max - request.min) / Vec2::new(request.columns as f32, request.rows as f32);
+    let max_length = cell.x.min(cell.y) * stride as f32 * 0.8;
+
+    for y in (stride / 2..request.rows).step_by(stride) {
+        for x in (stride / 2..request.columns).step_by(stride) {
+            let efield = field.efield[y * request.columns + x];
+            let mag = efield.mag();
+            if !(mag > 0.0 && mag.is_finite()) {
+                continue;
+            }
+
+            // The weakest arrows keep a quarter of the length so their direction still shows
+            let t = ((mag.ln() - low) / (high - low).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let length = max_length * (0.25 + 0.75 * t);
+            let dir = efield / mag;
+            let normal = Vec2::new(-dir.y, dir.x);
+
+            let center = request.point(x, y);
+            let tail = center - dir * length * 0.5;
+            let head = center + dir * length * 0.5;
+            let barb = length * 0.3;
+            let color = magnitude_color(t);
+
+            ctx.draw_line(tail, head, color);
+            ctx.draw_line(head, head - dir * barb + normal * barb, color);
+            ctx.draw_line(head, head - dir * barb - normal * barb, color);
+        }
+    }
+}
+
+// Fill each sample's cell with a translucent color on a log scale of |E|
+fn draw_field_heatmap(ctx: &mut Canvas, field: &FieldSamples) {
+    let Some((low, high)) = log_magnitude_range(&field.efield) else { return };
+    let request = &field.request;
+    let cell = (request.max - request.min) / Vec2::new(request.columns as f32, request.rows as f32);
+
+    for y in 0..request.rows {
+        for x in 0..request.columns {
+            let log = field.efield[y * request.columns + x].mag().ln();
+            if !log.is_finite() {
+                continue;
+            }
+
+            let t = ((log - low) / (high - low).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let color = magnitude_color(t);
+            let min = request.min + Vec2::new(x as f32, y as f32) * cell;
+            ctx.draw_rect(min, min + cell, [color[0], color[1], color[2], 0x60]);
+        }
+    }
+}
+
+// Gradient bar with the smallest and largest |E| in view at its ends
+fn draw_heatmap_legend(ctx: &egui::Context, low: f32, high: f32) {
+    let segments = 32;
+    let size = egui::vec2(200.0, 12.0);
+    let min = ctx.screen_rect().left_bottom() + egui::vec2(10.0, -30.0);
+    let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("heatmap legend")));
+
+    for i in 0..segments {
+        let t = i as f32 / (segments - 1) as f32;
+        let color = magnitude_color(t);
+        let x0 = min.x + size.x * i as f32 / segments as f32;
+        let x1 = min.x + size.x * (i + 1) as f32 / segments as f32;
+        painter.rect_filled(
+            egui::Rect::from_min_max(egui::pos2(x0, min.y), egui::pos2(x1, min.y + size.y)),
+            0.0,
+            egui::Color32::from_rgb(color[0], color[1], color[2]),
+        );
+    }
+
+    let font = egui::FontId::proportional(12.0);
+    painter.text(min, egui::Align2::LEFT_BOTTOM, format!("|E| {:.2e}", low.exp()), font.clone(), egui::Color32::WHITE);
+    painter.text(min + egui::vec2(size.x, 0.0), egui::Align2::RIGHT_BOTTOM, format!("{:.2e}", high.exp()), font, egui::Color32::WHITE);
+}
+
+// Draw a plus or minus over a fixed charge
+fn draw_charge_glyph(ctx: &mut Canvas, body: &Body) {
+    let size = body.radius * 0.6;
+
+    ctx.draw_line(body.pos - Vec2::new(size, 0.0), body.pos + Vec2::new(size, 0.0), [255, 255, 255, 255]);
+    if body.charge > 0.0 {
+        ctx.draw_line(body.pos - Vec2::new(0.0, size), body.pos + Vec2::new(0.0, size), [255, 255, 255, 255]);
     }
-    
 }
@@ -0,0 +1,137 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use ultraviolet::Vec2;
+
+use crate::{body::Body, plate::Plate, simulation::{Parameters, Simulation}};
+
+// A saved layout, plus the parameters to run it with
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    pub plates: Vec<Plate>,
+    #[serde(default)]
+    pub bodies: Vec<Body>,
+    #[serde(default = "default_dt")]
+    pub dt: f32,
+    #[serde(default = "default_qe")]
+    pub qe: f32,
+    #[serde(default = "default_qp")]
+    pub qp: f32,
+    #[serde(default)]
+    pub temperature: f32,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+// A text label placed in the world, left of and below pos
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub pos: Vec2,
+    pub text: String,
+}
+
+// Plates copied to the clipboard, with bounds relative to the copied selection's min corner
+#[derive(Serialize, Deserialize)]
+pub struct PlateClipboard {
+    pub plates: Vec<Plate>,
+}
+
+impl PlateClipboard {
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap_or_default();
+    }
+
+    // None for anything that isn't copied plates, since the clipboard can hold any text
+    pub fn from_json(text: &str) -> Option<Self> {
+        return serde_json::from_str(text).ok();
+    }
+}
+
+// Same as a new simulation's
+fn default_dt() -> f32 {
+    Parameters::default().dt
+}
+
+fn default_qe() -> f32 {
+    Parameters::default().qe
+}
+
+fn default_qp() -> f32 {
+    Parameters::default().qp
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        return Ok(serde_json::from_str(&text)?);
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        return fs::write(path, text);
+    }
+
+    // Simulation that runs this scene on its own, without the renderer
+    pub fn into_simulation(self) -> Simulation {
+        let mut simulation = Simulation::with_parameters(self.bodies, self.plates, self.dt, self.qe, self.qp, self.temperature);
+        simulation.reseed(self.seed);
+        return simulation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let (mut bodies, plates) = utils::ohms_law_circuit(2, 1.5, 0.25);
+        bodies.push(Body::new_fixed(Vec2::new(0.0, 80.0), 3.0));
+        let scene = Scene {
+            plates,
+            bodies,
+            dt: 0.5,
+            qe: 0.3,
+            qp: 2.0e-2,
+            temperature: 0.75,
+            seed: 99,
+            annotations: vec![Annotation { pos: Vec2::new(-10.0, 60.0), text: "Resistor".to_string() }],
+        };
+
+        let path = std::env::temp_dir().join(format!("efield-sim-scene-{}.json", std::process::id()));
+        scene.save(&path).unwrap();
+        let loaded = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        // Plates compare by their bounds, so check what makes them batteries and resistors too
+        assert!(loaded.plates == scene.plates);
+        for (a, b) in loaded.plates.iter().zip(&scene.plates) {
+            assert!(a.plate_type == b.plate_type);
+            assert_eq!((a.efield, a.direction, a.resist), (b.efield, b.direction, b.resist));
+        }
+        assert_eq!(loaded.bodies.len(), scene.bodies.len());
+        for (a, b) in loaded.bodies.iter().zip(&scene.bodies) {
+            assert_eq!((a.pos, a.charge, a.fixed), (b.pos, b.charge, b.fixed));
+        }
+        assert_eq!((loaded.dt, loaded.qe, loaded.qp, loaded.temperature, loaded.seed), (0.5, 0.3, 2.0e-2, 0.75, 99));
+        assert_eq!(loaded.annotations.len(), 1);
+        assert_eq!((loaded.annotations[0].pos, loaded.annotations[0].text.as_str()), (Vec2::new(-10.0, 60.0), "Resistor"));
+
+        let simulation = loaded.into_simulation();
+        let parameters = simulation.parameters();
+        assert_eq!((parameters.dt, parameters.qe, parameters.qp, parameters.temperature), (0.5, 0.3, 2.0e-2, 0.75));
+        assert_eq!(simulation.seed, 99);
+    }
+
+    #[test]
+    fn missing_parameters_take_the_defaults() {
+        let scene: Scene = serde_json::from_str(r#"{ "plates": [] }"#).unwrap();
+        let defaults = Parameters::default();
+        assert_eq!((scene.dt, scene.qe, scene.qp, scene.temperature, scene.seed), (defaults.dt, defaults.qe, defaults.qp, 0.0, 0));
+        assert!(scene.bodies.is_empty() && scene.annotations.is_empty());
+    }
+}
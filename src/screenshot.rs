@@ -0,0 +1,263 @@
+use std::{fs, io, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use ultraviolet::Vec2;
+
+// Screenshots go here, relative to the working directory
+const SCREENSHOT_DIR: &str = "screenshots";
+
+// Resolutions a screenshot can be taken at, as multiples of the window size
+pub const SCREENSHOT_RESOLUTIONS: [u32; 3] = [1, 2, 4];
+
+// One draw call, in world coordinates
+#[derive(Clone, Copy)]
+pub enum DrawCall {
+    Circle { pos: Vec2, radius: f32, color: [u8; 4] },
+    Line { a: Vec2, b: Vec2, color: [u8; 4] },
+    Rect { min: Vec2, max: Vec2, color: [u8; 4] },
+}
+
+// Passes draw calls on to the window, keeping a copy of them and the view while a screenshot is being taken
+pub struct Canvas<'a> {
+    ctx: &'a mut quarkstrom::RenderContext,
+    calls: Option<Vec<DrawCall>>,
+    view_pos: Vec2,
+    view_scale: f32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(ctx: &'a mut quarkstrom::RenderContext, record: bool) -> Self {
+        Self {
+            ctx,
+            calls: if record { Some(Vec::new()) } else { None },
+            view_pos: Vec2::zero(),
+            view_scale: 1.0,
+        }
+    }
+
+    pub fn set_view_pos(&mut self, pos: Vec2) {
+        self.view_pos = pos;
+        self.ctx.set_view_pos(pos);
+    }
+
+    pub fn set_view_scale(&mut self, scale: f32) {
+        self.view_scale = scale;
+        self.ctx.set_view_scale(scale);
+    }
+
+    pub fn clear_circles(&mut self) {
+        self.ctx.clear_circles();
+    }
+
+    pub fn clear_lines(&mut self) {
+        self.ctx.clear_lines();
+    }
+
+    pub fn clear_rects(&mut self) {
+        self.ctx.clear_rects();
+    }
+
+    pub fn draw_circle(&mut self, pos: Vec2, radius: f32, color: [u8; 4]) {
+        if let Some(calls) = &mut self.calls {
+            calls.push(DrawCall::Circle { pos, radius, color });
+        }
+        self.ctx.draw_circle(pos, radius, color);
+    }
+
+    pub fn draw_line(&mut self, a: Vec2, b: Vec2, color: [u8; 4]) {
+        if let Some(calls) = &mut self.calls {
+            calls.push(DrawCall::Line { a, b, color });
+        }
+        self.ctx.draw_line(a, b, color);
+    }
+
+    pub fn draw_rect(&mut self, min: Vec2, max: Vec2, color: [u8; 4]) {
+        if let Some(calls) = &mut self.calls {
+            calls.push(DrawCall::Rect { min, max, color });
+        }
+        self.ctx.draw_rect(min, max, color);
+    }
+
+    // The recorded calls drawn over a window of width by height pixels, scaled up by resolution; None if
+    // nothing was recorded
+    pub fn rasterize(&self, width: u32, height: u32, resolution: u32) -> Option<Image> {
+        let calls = self.calls.as_ref()?;
+        let mut image = Image::new(width * resolution, height * resolution, self.view_pos, self.view_scale);
+
+        // Layered as the window draws them: the background and plates are rects under the bodies,
+        // and outlines go on top
+        for call in calls {
+            if let &DrawCall::Rect { min, max, color } = call {
+                image.fill_rect(min, max, color);
+            }
+        }
+        for call in calls {
+            if let &DrawCall::Circle { pos, radius, color } = call {
+                image.fill_circle(pos, radius, color);
+            }
+        }
+        for call in calls {
+            if let &DrawCall::Line { a, b, color } = call {
+                image.draw_line(a, b, resolution as f32, color);
+            }
+        }
+
+        Some(image)
+    }
+}
+
+// Software framebuffer, with the window's view transform
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    // RGBA rows from the top
+    pub pixels: Vec<u8>,
+    view_pos: Vec2,
+    view_scale: f32,
+}
+
+impl Image {
+    fn new(width: u32, height: u32, view_pos: Vec2, view_scale: f32) -> Self {
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+
+        Self { width, height, pixels, view_pos, view_scale }
+    }
+
+    // Pixels per world unit; the view's scale is half its height
+    fn zoom(&self) -> f32 {
+        return self.height as f32 * 0.5 / self.view_scale;
+    }
+
+    // Pixel coordinates of a world position, with y down
+    fn to_pixel(&self, pos: Vec2) -> Vec2 {
+        let offset = (pos - self.view_pos) * self.zoom();
+        return Vec2::new(self.width as f32 * 0.5 + offset.x, self.height as f32 * 0.5 - offset.y);
+    }
+
+    // Blend color over a pixel, with its alpha scaled by coverage
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+
+        let alpha = color[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        for (channel, &value) in self.pixels[i..i + 3].iter_mut().zip(&color[..3]) {
+            let old = *channel as f32;
+            *channel = (old + (value as f32 - old) * alpha).round() as u8;
+        }
+    }
+
+    // Pixels touching [min, max], clamped to the image
+    fn span(&self, min: f32, max: f32, size: u32) -> std::ops::Range<i64> {
+        let start = (min.floor() as i64).max(0);
+        let end = (max.ceil() as i64).min(size as i64);
+        return start..end;
+    }
+
+    // Pixels whose centers are inside the rect
+    fn fill_rect(&mut self, min: Vec2, max: Vec2, color: [u8; 4]) {
+        let a = self.to_pixel(min);
+        let b = self.to_pixel(max);
+        let (left, right) = (a.x.min(b.x), a.x.max(b.x));
+        let (top, bottom) = (a.y.min(b.y), a.y.max(b.y));
+
+        let rows = ((top - 0.5).ceil() as i64).max(0)..((bottom - 0.5).ceil() as i64).min(self.height as i64);
+        let columns = ((left - 0.5).ceil() as i64).max(0)..((right - 0.5).ceil() as i64).min(self.width as i64);
+        for y in rows {
+            for x in columns.clone() {
+                self.blend(x, y, color, 1.0);
+            }
+        }
+    }
+
+    // Disc with its edge smoothed over a pixel
+    fn fill_circle(&mut self, pos: Vec2, radius: f32, color: [u8; 4]) {
+        let center = self.to_pixel(pos);
+        let radius = radius * self.zoom();
+
+        for y in self.span(center.y - radius - 1.0, center.y + radius + 1.0, self.height) {
+            for x in self.span(center.x - radius - 1.0, center.x + radius + 1.0, self.width) {
+                let distance = (Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - center).mag();
+                let coverage = radius - distance + 0.5;
+                if coverage > 0.0 {
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+
+    // Line width pixels thick, stepped along its longer axis
+    fn draw_line(&mut self, a: Vec2, b: Vec2, width: f32, color: [u8; 4]) {
+        let a = self.to_pixel(a);
+        let b = self.to_pixel(b);
+        let steep = (b.y - a.y).abs() > (b.x - a.x).abs();
+
+        // Swap axes for steep lines, so one pixel per column is stepped either way
+        let (a, b) = if steep { (Vec2::new(a.y, a.x), Vec2::new(b.y, b.x)) } else { (a, b) };
+        let (a, b) = if a.x > b.x { (b, a) } else { (a, b) };
+        let (major_size, minor_size) = if steep { (self.height, self.width) } else { (self.width, self.height) };
+
+        let slope = if b.x > a.x { (b.y - a.y) / (b.x - a.x) } else { 0.0 };
+        // Thickness across the minor axis that gives the width across the line
+        let half = width * 0.5 * (1.0 + slope * slope).sqrt();
+
+        for major in self.span(a.x - 0.5, b.x + 0.5, major_size) {
+            let t = (major as f32 + 0.5).clamp(a.x, b.x);
+            let minor_center = a.y + (t - a.x) * slope;
+            for minor in self.span(minor_center - half - 1.0, minor_center + half + 1.0, minor_size) {
+                let coverage = half + 0.5 - (minor as f32 + 0.5 - minor_center).abs();
+                if coverage > 0.0 {
+                    let (x, y) = if steep { (minor, major) } else { (major, minor) };
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+
+    // Write the image into the screenshot directory under a timestamped name, returning where it went
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let dir = PathBuf::from(SCREENSHOT_DIR);
+        fs::create_dir_all(&dir)?;
+
+        // Screenshots taken within the same second get a number after the time
+        let stamp = timestamp();
+        let mut path = dir.join(format!("screenshot-{}.png", stamp));
+        let mut n = 2;
+        while path.exists() {
+            path = dir.join(format!("screenshot-{}-{}.png", stamp, n));
+            n += 1;
+        }
+
+        let file = io::BufWriter::new(fs::File::create(&path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&self.pixels).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)?;
+
+        return Ok(path);
+    }
+}
+
+// Current UTC time as YYYY-MM-DD_HH-MM-SS
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since 1970-01-01, in 400-year eras starting in March
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    return format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60);
+}
@@ -1,123 +1,2411 @@
 use crate::{
-    body::Body, plate::Plate, quadtree::{Quad, Quadtree}, renderer, utils
+    body::{Body, Species, ELECTRON_CHARGE}, field_grid::PlateFieldGrid, plate_index::{PlateIndex, PLATE_INDEX_CELL_SIZE}, plate::{Plate, PlateTransform, PlateType, DIODE_REVERSE_RESIST, INDUCED_SEGMENTS, MAX_REGULATED_EMF}, quadtree::{direct_efield, morton_code, Node, Quad, Quadtree, LEAF_CAPACITY, MIN_EPSILON}, utils
 };
 
+use std::{collections::{HashMap, VecDeque}, sync::atomic::Ordering, time::Instant};
+
+use crossbeam_channel::Receiver;
+use rayon::prelude::*;
+
 use ultraviolet::Vec2;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum IntegrationMode {
+    // Bodies move with the local field (no momentum)
+    Drift,
+    // Bodies accelerate with the local field (F = ma)
+    Inertial,
+    // Velocity Verlet (half-kick, drift, half-kick)
+    Verlet,
+    // Drift with a fourth-order Runge-Kutta step (4x field evaluations)
+    Rk4,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Boundary {
+    // Bodies can drift off forever
+    None,
+    // Bodies bounce off the world bounds
+    Reflect,
+    // Bodies leaving the world bounds are removed
+    Delete,
+    // Bodies leaving one side of the world bounds come back in the other
+    Periodic,
+}
+
+// Constants and solver settings set from the settings window; the simulation owns them and the renderer only
+// sends changes
+#[derive(Clone, Copy, PartialEq)]
+pub struct Parameters {
+    pub dt: f32,
+    pub qe: f32,
+    pub qp: f32,
+    pub temperature: f32,
+    // Step backwards in time
+    pub rewind: bool,
+    // Steps per publication, and publications per second, for the loop running the simulation
+    pub steps_per_frame: usize,
+    pub target_ticks_per_second: f32,
+    // 0 turns the speed limit off
+    pub max_speed: f32,
+    pub integration_mode: IntegrationMode,
+    pub force_method: ForceMethod,
+    pub fixed_root: bool,
+    pub fixed_root_margin: f32,
+    pub theta: f32,
+    pub epsilon: f32,
+    pub leaf_capacity: usize,
+    pub morton_sort: bool,
+    pub dipole: bool,
+    pub resist_model: ResistModel,
+    pub precise_plates: bool,
+    pub plate_epsilon: f32,
+    pub interior_epsilon: f32,
+    pub interpolate_plates: bool,
+    pub plate_grid_spacing: f32,
+    pub induced_charge: bool,
+    pub boundary: Boundary,
+    // Centered on the origin
+    pub world_size: Vec2,
+    pub separation: bool,
+    pub separation_strength: f32,
+    // Frames between stats updates
+    pub stats_interval: usize,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            dt: 1.0,
+            qe: 0.56,
+            qp: 4.5e-2,
+            temperature: 0.0,
+            rewind: false,
+            steps_per_frame: 1,
+            target_ticks_per_second: 60.0,
+            max_speed: 0.0,
+            integration_mode: IntegrationMode::Drift,
+            force_method: ForceMethod::BarnesHut,
+            fixed_root: false,
+            fixed_root_margin: 50.0,
+            theta: 0.75,
+            epsilon: 1.0,
+            leaf_capacity: LEAF_CAPACITY,
+            morton_sort: true,
+            dipole: false,
+            resist_model: ResistModel::Scale,
+            precise_plates: false,
+            plate_epsilon: 0.0,
+            interior_epsilon: 1.0,
+            interpolate_plates: false,
+            plate_grid_spacing: 2.0,
+            induced_charge: false,
+            boundary: Boundary::None,
+            world_size: Vec2::new(1000.0, 1000.0),
+            separation: false,
+            separation_strength: 0.5,
+            stats_interval: 10,
+        }
+    }
+}
+
+// Parameters needed to evaluate the plate fields
+#[derive(Clone, Copy)]
+pub struct FieldParams {
+    pub qp: f32,
+    pub qe: f32,
+    pub time: f32,
+    pub precise_plates: bool,
+    pub plate_epsilon: f32,
+    pub temperature: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ForceMethod {
+    BarnesHut,
+    // Exact pairwise sum (O(n²))
+    Direct,
+    // Barnes-Hut, with the error against the direct sum measured for small scenes
+    Compare,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResistModel {
+    // Scale every body's motion by the plate's resist
+    Scale,
+    // Randomly stop bodies, with the plate's resist as the chance to move
+    Scatter,
+}
+
+// Largest scene the compare mode will run the direct sum on
+pub const COMPARE_LIMIT: usize = 2000;
+
+// Per-plate readings gathered while stepping, published to the renderer
+#[derive(Clone, Default)]
+pub struct Measurements {
+    // Net bodies that crossed each plate's mid cross-section during the last step,
+    // in electrons (a hole crossing counts as an electron crossing the other way)
+    pub crossings: Vec<f32>,
+    // Exponential moving average of the crossings (bodies per step), by plate id
+    pub currents: HashMap<u32, f32>,
+    // Mean displacement per step of the bodies inside each plate, over the last DRIFT_WINDOW steps
+    pub drift: Vec<Vec2>,
+    // The same drift, for each species on its own
+    pub species_drift: Vec<[Vec2; Species::COUNT]>,
+    // Bodies inside each plate after the last step, and the net charge there, counted as for a flux loop
+    pub body_counts: Vec<usize>,
+    pub net_charges: Vec<f32>,
+}
+
+// Whole-scene diagnostics, refreshed every few frames
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    pub body_count: usize,
+    pub total_charge: f32,
+    pub kinetic_energy: f32,
+    // Body-body interaction energy, approximated with the quadtree
+    pub potential_energy: f32,
+}
+
+// Where the time in the last step went, summed over its substeps
+#[derive(Clone, Copy, Default)]
+pub struct Performance {
+    pub calcs: usize,
+    pub nodes: usize,
+    pub max_depth: usize,
+    // Wall-clock seconds
+    pub build_time: f32,
+    pub force_time: f32,
+    pub plate_time: f32,
+    pub integrate_time: f32,
+    pub step_time: f32,
+    // Copying the scene out to the renderer, measured by whoever publishes it
+    pub publish_time: f32,
+    // Achieved rates of the loop running the simulation, measured by whoever runs it
+    pub steps_per_second: f32,
+    pub ticks_per_second: f32,
+}
+
+impl Performance {
+    // Move the rolling averages towards the times of the latest step
+    pub fn blend(&mut self, step: &Performance) {
+        smooth(&mut self.build_time, step.build_time);
+        smooth(&mut self.force_time, step.force_time);
+        smooth(&mut self.plate_time, step.plate_time);
+        smooth(&mut self.integrate_time, step.integrate_time);
+        smooth(&mut self.step_time, step.step_time);
+        self.calcs = step.calcs;
+        self.nodes = step.nodes;
+        self.max_depth = step.max_depth;
+    }
+}
+
+// Exponential moving average with PERFORMANCE_SMOOTHING as the weight of the new sample
+pub fn smooth(average: &mut f32, sample: f32) {
+    *average += (sample - *average) * PERFORMANCE_SMOOTHING;
+}
+
+// Grid over the renderer's view where it wants the field sampled
+#[derive(Clone, Copy, PartialEq)]
+pub struct FieldRequest {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl FieldRequest {
+    // Center of cell (x, y)
+    pub fn point(&self, x: usize, y: usize) -> Vec2 {
+        let cell = (self.max - self.min) / Vec2::new(self.columns as f32, self.rows as f32);
+        return self.min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * cell;
+    }
+}
+
+// Field at each cell of a request, row by row
+pub struct FieldSamples {
+    pub request: FieldRequest,
+    pub efield: Vec<Vec2>,
+}
+
+// Seeds to trace field lines from, and how to trace them
+#[derive(Clone, PartialEq)]
+pub struct StreamlineRequest {
+    pub seeds: Vec<Vec2>,
+    // Arc length of each step, and the most steps each way from a seed
+    pub step: f32,
+    pub max_steps: usize,
+    // Lines stop where the field gets weaker than this
+    pub min_field: f32,
+}
+
+// Field and potential under the renderer's cursor
+#[derive(Clone, Copy)]
+pub struct CursorReading {
+    pub pos: Vec2,
+    pub efield: Vec2,
+    pub potential: f32,
+}
+
+// What a scope probe reads
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProbeKind {
+    // Potential at b minus the potential at a
+    Voltage { a: Vec2, b: Vec2 },
+    // Smoothed current through the plate with this id, in bodies per step
+    Ammeter { plate: u32 },
+    // Outward flux through the loop's boundary over 2π, which reads as the enclosed charge
+    FluxLoop { min: Vec2, max: Vec2 },
+}
+
+// A probe read after every step; its id stays the same as other probes come and go
+#[derive(Clone, Copy, PartialEq)]
+pub struct Probe {
+    pub id: u32,
+    pub kind: ProbeKind,
+}
+
+// Every probe's reading after one step, as (probe id, value)
+#[derive(Clone)]
+pub struct ProbeSample {
+    pub frame: usize,
+    pub values: Vec<(u32, f32)>,
+}
+
+// What the renderer is measuring and showing, which decides what the simulation reads and puts in its snapshots
+#[derive(Clone, Default, PartialEq)]
+pub struct Instruments {
+    // Two points to measure the potential difference between
+    pub probe: Option<(Vec2, Vec2)>,
+    // Ruler ends, read the same way
+    pub ruler: Option<(Vec2, Vec2)>,
+    // Rectangle to count bodies in
+    pub region: Option<(Vec2, Vec2)>,
+    // Where to sample the field for the next snapshot
+    pub field_request: Option<FieldRequest>,
+    // Where to read the field and potential for the next snapshot
+    pub cursor: Option<Vec2>,
+    // Field lines to trace
+    pub streamline_request: Option<StreamlineRequest>,
+    // Gauss's law loops
+    pub flux_loops: Vec<(Vec2, Vec2)>,
+    // Probes read after every step for the scope
+    pub scope_probes: Vec<Probe>,
+    // Copy the tree into snapshots, while the renderer is drawing it
+    pub quadtree: bool,
+}
+
+// Instrument readings and solver state, as of a snapshot
+#[derive(Clone, Default)]
+pub struct Readings {
+    pub probe_voltage: Option<f32>,
+    pub ruler_voltage: Option<f32>,
+    // (count, total charge) of the bodies in the region
+    pub region_stats: Option<(usize, f32)>,
+    // (flux, enclosed charge) through each flux loop
+    pub flux: Vec<(f32, f32)>,
+    pub inspected: Option<Body>,
+    pub substeps: usize,
+    pub clamped: usize,
+    pub nan_events: usize,
+    // Max and RMS relative error of Barnes-Hut against the direct sum, and of the plate field grid
+    pub force_error: Option<(f32, f32)>,
+    pub plate_grid_error: Option<(f32, f32)>,
+}
+
+// What a snapshot was taken from, to tell whether another one would show anything new
+#[derive(PartialEq)]
+struct SnapshotKey {
+    frame: usize,
+    edits_applied: u64,
+    instruments: Instruments,
+    inspect: Option<(Vec2, f32)>,
+}
+
+// Everything the renderer draws, sent once per publication
+pub struct SimSnapshot {
+    pub bodies: Vec<Body>,
+    // Only sent when the plates changed
+    pub plates: Option<Vec<Plate>>,
+    pub quadtree: Vec<Node>,
+    pub measurements: Measurements,
+    pub stats: Stats,
+    pub frame: usize,
+    // When it was taken, to tell how far the renderer lags
+    pub taken: Instant,
+    pub parameters: Parameters,
+    pub readings: Readings,
+    pub performance: Performance,
+    pub field_backend: String,
+    pub seed: u64,
+    // Only sent when the renderer asked for it
+    pub field: Option<FieldSamples>,
+    // Only sent when they were traced again
+    pub streamlines: Option<Vec<Vec<Vec2>>>,
+    pub cursor: Option<CursorReading>,
+    // Probe readings from every step since the last snapshot
+    pub probe_samples: Vec<ProbeSample>,
+    // Why body edits since the last snapshot did nothing
+    pub failed_edits: Vec<String>,
+    // How many edits from the renderer were applied before this was taken
+    pub edits_applied: u64,
+}
+
+// Changes to the scene, made in the renderer and applied by the simulation
+#[derive(Clone)]
+pub enum EditCommand {
+    AddBodies(Vec<Body>),
+    AddPlate { plate: Plate, bodies: Vec<Body> },
+    // Removes the plates with these ids, the bodies in them, and the fixed charges in [min, max)
+    RemovePlates { ids: Vec<u32>, min: Vec2, max: Vec2 },
+    // Replaces the plate with the same id
+    SetPlate(Plate),
+    // Replaces everything, e.g. with a loaded scene
+    ReplaceScene { bodies: Vec<Body>, plates: Vec<Plate> },
+    // Restarts the simulation's random numbers, in order with the other edits
+    Reseed(u64),
+    // Randomly removes free bodies down to target, keeping the share in each plate
+    Decimate { target: usize, seed: u64 },
+    // Changes the time step, charges and temperature, in order with the other edits
+    SetParameters(Parameters),
+    // Moves the plates with these ids and the bodies in them
+    MovePlates { ids: Vec<u32>, offset: Vec2 },
+    // Gives a plate new bounds, removing the bodies left outside it
+    ResizePlate { id: u32, min: Vec2, max: Vec2 },
+    // Turns or flips the plates with these ids about center, along with the bodies in them
+    TransformPlates { ids: Vec<u32>, center: Vec2, transform: PlateTransform },
+    // Removes the bodies within radius of the segment from `from` to `to`, as swept by the eraser in one frame
+    EraseBodies { from: Vec2, to: Vec2, radius: f32 },
+    // Removes the body with this id, if it hasn't gone since it was picked
+    RemoveBody { id: u64 },
+    // Pins the body with this id as a fixed charge
+    PinBody { id: u64 },
+    // Changes what the renderer is measuring; it leaves the scene alone, so it isn't counted as an edit
+    SetInstruments(Instruments),
+    // Picks the body nearest a point, within a radius, to follow in the inspector; None stops following
+    Inspect(Option<(Vec2, f32)>),
+}
+
+impl EditCommand {
+    // Whether this changes the scene, so the renderer waits for the simulation to apply it before taking its bodies
+    pub fn changes_scene(&self) -> bool {
+        return !matches!(self, EditCommand::SetInstruments(_) | EditCommand::Inspect(_));
+    }
+
+    // Whether applying this edit to the scene would delete the body
+    pub fn removes_body(&self, body: &Body, plates: &[Plate]) -> bool {
+        match self {
+            EditCommand::RemovePlates { ids, min, max } => {
+                let pos = body.pos;
+                let fixed_in_region = body.fixed && pos.x >= min.x && pos.x < max.x && pos.y >= min.y && pos.y < max.y;
+                return fixed_in_region || plates.iter().any(|plate| ids.contains(&plate.id) && plate.contains_point(pos));
+            },
+            EditCommand::ResizePlate { id, min, max } => {
+                let Some(plate) = plates.iter().find(|plate| plate.id == *id) else { return false };
+
+                let pos = body.pos;
+                let inside_new = pos.x >= min.x && pos.x < max.x && pos.y >= min.y && pos.y < max.y;
+                return plate.contains_point(pos) && !inside_new;
+            },
+            EditCommand::EraseBodies { from, to, radius } => {
+                let stroke = *to - *from;
+                let t = if stroke.mag_sq() > 0.0 { ((body.pos - *from).dot(stroke) / stroke.mag_sq()).clamp(0.0, 1.0) } else { 0.0 };
+                return (body.pos - (*from + stroke * t)).mag_sq() <= radius * radius;
+            },
+            _ => return false,
+        }
+    }
+
+    // Shared by the simulation and the renderer's local copy, so both end up with the same scene
+    pub fn apply(&self, bodies: &mut Vec<Body>, plates: &mut Vec<Plate>) {
+        match self {
+            EditCommand::AddBodies(new_bodies) => {
+                bodies.extend_from_slice(new_bodies);
+            },
+            EditCommand::AddPlate { plate, bodies: new_bodies } => {
+                plates.push(plate.clone());
+                bodies.extend_from_slice(new_bodies);
+            },
+            EditCommand::RemovePlates { ids, .. } => {
+                bodies.retain(|body| !self.removes_body(body, plates));
+                plates.retain(|plate| !ids.contains(&plate.id));
+            },
+            EditCommand::SetPlate(plate) => {
+                let Some(old) = plates.iter_mut().find(|old| old.id == plate.id) else { return };
+
+                // Keep the state the simulation has built up, unless the plate became something else
+                let mut new = plate.clone();
+                if old.plate_type == new.plate_type {
+                    new.stored_charge = old.stored_charge;
+                    new.induced = old.induced;
+                    new.emit_accum = old.emit_accum;
+                    new.regulated_emf = old.regulated_emf;
+                }
+                *old = new;
+            },
+            EditCommand::ReplaceScene { bodies: new_bodies, plates: new_plates } => {
+                bodies.clone_from(new_bodies);
+                plates.clone_from(new_plates);
+            },
+            EditCommand::MovePlates { ids, offset } => {
+                // Which bodies come along is decided before anything moves
+                let moving: Vec<Plate> = plates.iter().filter(|plate| ids.contains(&plate.id)).cloned().collect();
+                for body in bodies.iter_mut() {
+                    if moving.iter().any(|plate| plate.contains_point(body.pos)) {
+                        body.pos += *offset;
+                    }
+                }
+
+                for plate in plates.iter_mut().filter(|plate| ids.contains(&plate.id)) {
+                    plate.min += *offset;
+                    plate.max += *offset;
+                }
+            },
+            EditCommand::EraseBodies { .. } => {
+                bodies.retain(|body| !self.removes_body(body, plates));
+            },
+            EditCommand::RemoveBody { id } => {
+                bodies.retain(|body| body.id != *id);
+            },
+            EditCommand::PinBody { id } => {
+                if let Some(body) = bodies.iter_mut().find(|body| body.id == *id) {
+                    body.pin();
+                }
+            },
+            EditCommand::ResizePlate { id, min, max } => {
+                bodies.retain(|body| !self.removes_body(body, plates));
+
+                let Some(plate) = plates.iter_mut().find(|plate| plate.id == *id) else { return };
+                plate.min = *min;
+                plate.max = *max;
+            },
+            EditCommand::TransformPlates { ids, center, transform } => {
+                let moving: Vec<Plate> = plates.iter().filter(|plate| ids.contains(&plate.id)).cloned().collect();
+                for body in bodies.iter_mut() {
+                    if moving.iter().any(|plate| plate.contains_point(body.pos)) {
+                        body.pos = transform.apply_point(body.pos, *center);
+                        body.vel = transform.apply_vector(body.vel);
+                        body.acc = transform.apply_vector(body.acc);
+                        body.prev_acc = transform.apply_vector(body.prev_acc);
+                    }
+                }
+
+                for plate in plates.iter_mut().filter(|plate| ids.contains(&plate.id)) {
+                    *plate = plate.transformed(*transform, *center);
+                }
+            },
+            EditCommand::Reseed(_) | EditCommand::SetParameters(_) | EditCommand::SetInstruments(_) | EditCommand::Inspect(_) => {},
+            EditCommand::Decimate { target, seed } => {
+                let fixed = bodies.iter().filter(|body| body.fixed).count();
+                let free = bodies.len() - fixed;
+                let target_free = target.saturating_sub(fixed);
+                if free <= target_free {
+                    return;
+                }
+                let fraction = target_free as f32 / free as f32;
+
+                // Free bodies grouped by the plate they're in, with the last group outside every plate
+                let mut groups: Vec<Vec<usize>> = vec![Vec::new(); plates.len() + 1];
+                for (i, body) in bodies.iter().enumerate().filter(|(_, body)| !body.fixed) {
+                    let group = plates.iter().position(|plate| plate.contains_point(body.pos)).unwrap_or(plates.len());
+                    groups[group].push(i);
+                }
+
+                let mut rng = fastrand::Rng::with_seed(*seed);
+                let mut keep = vec![true; bodies.len()];
+                for group in &mut groups {
+                    rng.shuffle(group);
+                    let kept = (group.len() as f32 * fraction).round() as usize;
+                    for &i in &group[kept..] {
+                        keep[i] = false;
+                    }
+                }
+
+                let mut i = 0;
+                bodies.retain(|_| {
+                    i += 1;
+                    keep[i - 1]
+                });
+            },
+        }
+    }
+}
+
+// Weight of the newest step in the smoothed current
+pub const CURRENT_SMOOTHING: f32 = 0.05;
+
+// Weight of the latest step in the rolling performance averages
+pub const PERFORMANCE_SMOOTHING: f32 = 0.1;
+
+// Field samples along each side of a flux loop
+pub const FLUX_SAMPLES_PER_SIDE: usize = 64;
+
+// Most probe samples held for the renderer, dropping the oldest while nothing takes them
+pub const PROBE_SAMPLE_LIMIT: usize = 100_000;
+
+// Number of steps the drift velocity is averaged over
+pub const DRIFT_WINDOW: usize = 60;
+
+// Drift is tracked for all bodies together and for each species
+const DRIFT_GROUPS: usize = Species::COUNT + 1;
+
+pub const MAX_SUBSTEPS: usize = 16;
+
+// How fast induced surface charge flows along a conductor's boundary
+pub const INDUCED_RATE: f32 = 0.5;
+
+// Largest charge moved between two segments in a step for a conductor to count as settled, so its plates aren't
+// republished to the renderer every step
+pub const INDUCED_SETTLED: f32 = 1e-3;
+
 pub struct Simulation {
     pub dt: f32,
+    // Step backwards in time
+    pub rewind: bool,
+    // Read by the loop running the simulation, which steps it and publishes snapshots
+    pub steps_per_frame: usize,
+    pub target_ticks_per_second: f32,
+    pub substeps: usize,
+    pub max_step_dist: f32,
+    pub max_speed: f32,
+    pub clamped: usize,
+    // Number of substeps where non-finite bodies were found
+    pub nan_events: usize,
+    pub integration_mode: IntegrationMode,
     pub frame: usize,
+    pub time: f32,
     pub bodies: Vec<Body>,
     pub plates: Vec<Plate>,
     pub quadtree: Quadtree,
     pub qe: f32,
     pub qp: f32,
+    pub temperature: f32,
+    // Edits to pull at the start of each step, when something else owns the scene
+    pub edits: Option<Receiver<EditCommand>>,
+    // Plates changed since they were last sent to the renderer
+    pub plates_dirty: bool,
+    pub edits_applied: u64,
+    last_snapshot: Option<SnapshotKey>,
+    pub measurements: Measurements,
+    // Recent per-step drift of each plate, keyed by plate id
+    drift_history: HashMap<u32, VecDeque<[Vec2; DRIFT_GROUPS]>>,
+    // Drift accumulated over the substeps of the current step
+    step_drift: Vec<[Vec2; DRIFT_GROUPS]>,
+    // Buffers reused by iterate: positions before the move, and RK4's (new position, clamped) per body
+    scratch_positions: Vec<Vec2>,
+    scratch_moves: Vec<(Vec2, bool)>,
+    pub stats: Stats,
+    // Frames between stats updates
+    pub stats_interval: usize,
+    pub performance: Performance,
+    // Rolling averages of performance, which is only the last step
+    pub average_performance: Performance,
+    // Build and walk the tree in Z-order along the bodies, leaving the bodies themselves in place
+    pub morton_sort: bool,
+    pub instruments: Instruments,
+    // Potential differences across the probe and the ruler
+    pub probe_voltage: Option<f32>,
+    pub ruler_voltage: Option<f32>,
+    // (count, total charge) of the bodies in the region
+    pub region_stats: Option<(usize, f32)>,
+    // The request, edit count and parameters the last field lines were traced with
+    streamline_key: Option<(StreamlineRequest, u64, Parameters)>,
+    // Point and pick radius of the body being inspected, and that body
+    pub inspect: Option<(Vec2, f32)>,
+    pub inspected: Option<Body>,
+    // Id for the next body added
+    next_body_id: u64,
+    // Body edits that found their body gone, to tell the renderer
+    failed_edits: Vec<String>,
+    // (flux, enclosed charge) through each flux loop
+    pub flux: Vec<(f32, f32)>,
+    // Scope probe readings not yet sent
+    probe_samples: VecDeque<ProbeSample>,
+    pub force_method: ForceMethod,
+    // Use the padded plate bounds as the quadtree root
+    pub fixed_root: bool,
+    pub fixed_root_margin: f32,
+    pub resist_model: ResistModel,
+    // Max and RMS relative error of Barnes-Hut against the direct sum
+    pub force_error: Option<(f32, f32)>,
+    pub precise_plates: bool,
+    // Softening length for the plate fields near corners and edges
+    pub plate_epsilon: f32,
+    // Read the plate fields from a precomputed grid instead of evaluating them exactly
+    pub interpolate_plates: bool,
+    pub plate_grid_spacing: f32,
+    pub plate_grid: Option<PlateFieldGrid>,
+    // Which plates overlap each grid cell, for point-in-plate queries
+    pub plate_index: PlateIndex,
+    // Evaluates the Barnes-Hut field on the GPU when set
+    #[cfg(feature = "gpu")]
+    pub gpu: Option<crate::gpu::GpuField>,
+    // Softening length for the body-body field felt by bodies inside plates (screening)
+    pub interior_epsilon: f32,
+    pub boundary: Boundary,
+    pub bounds_min: Vec2,
+    pub bounds_max: Vec2,
+    pub induced_charge: bool,
+    pub separation: bool,
+    pub separation_strength: f32,
+    // Seed for scattering, thermal noise and emitters; the same seed and edits give the same run
+    pub seed: u64,
+    pub rng: fastrand::Rng,
 }
 
 impl Simulation {
     pub fn new() -> Self {
-        let theta = 0.75;
-        let epsilon = 1.0;
+        let parameters = Parameters::default();
 
-        let quadtree = Quadtree::new(theta, epsilon);
+        let mut quadtree = Quadtree::new(parameters.theta, parameters.epsilon);
+        quadtree.leaf_capacity = parameters.leaf_capacity;
+        quadtree.dipole = parameters.dipole;
         // let (bodies, plates) = utils::large_plate(60000, Vec2::new(-400.0, -400.0), Vec2::new(400.0, 400.0));
         let bodies = Vec::new();
         let plates = Vec::new();
 
         Self {
-            dt: 1.0,
+            dt: parameters.dt,
+            rewind: parameters.rewind,
+            steps_per_frame: parameters.steps_per_frame,
+            target_ticks_per_second: parameters.target_ticks_per_second,
+            substeps: 1,
+            max_step_dist: 5.0,
+            max_speed: parameters.max_speed,
+            clamped: 0,
+            nan_events: 0,
+            integration_mode: parameters.integration_mode,
             frame: 0,
+            time: 0.0,
             bodies,
             plates,
             quadtree,
-            qe: -1.0,
-            qp: 1.0,
+            qe: parameters.qe,
+            qp: parameters.qp,
+            temperature: parameters.temperature,
+            edits: None,
+            plates_dirty: true,
+            edits_applied: 0,
+            last_snapshot: None,
+            measurements: Measurements::default(),
+            drift_history: HashMap::new(),
+            step_drift: Vec::new(),
+            scratch_positions: Vec::new(),
+            scratch_moves: Vec::new(),
+            stats: Stats::default(),
+            stats_interval: parameters.stats_interval,
+            performance: Performance::default(),
+            average_performance: Performance::default(),
+            morton_sort: parameters.morton_sort,
+            instruments: Instruments::default(),
+            probe_voltage: None,
+            ruler_voltage: None,
+            region_stats: None,
+            streamline_key: None,
+            inspect: None,
+            inspected: None,
+            next_body_id: 1,
+            failed_edits: Vec::new(),
+            flux: Vec::new(),
+            probe_samples: VecDeque::new(),
+            force_method: parameters.force_method,
+            fixed_root: parameters.fixed_root,
+            fixed_root_margin: parameters.fixed_root_margin,
+            resist_model: parameters.resist_model,
+            force_error: None,
+            precise_plates: parameters.precise_plates,
+            plate_epsilon: parameters.plate_epsilon,
+            interpolate_plates: parameters.interpolate_plates,
+            plate_grid_spacing: parameters.plate_grid_spacing,
+            plate_grid: None,
+            plate_index: PlateIndex::new(&[], PLATE_INDEX_CELL_SIZE),
+            #[cfg(feature = "gpu")]
+            gpu: None,
+            interior_epsilon: parameters.interior_epsilon.max(MIN_EPSILON),
+            boundary: parameters.boundary,
+            bounds_min: -parameters.world_size * 0.5,
+            bounds_max: parameters.world_size * 0.5,
+            induced_charge: parameters.induced_charge,
+            separation: parameters.separation,
+            separation_strength: parameters.separation_strength,
+            seed: 0,
+            rng: fastrand::Rng::with_seed(0),
         }
     }
 
+    // Simulation that never talks to the renderer, for scripted runs
+    pub fn headless(mut bodies: Vec<Body>, plates: Vec<Plate>) -> Self {
+        let mut simulation = Self::new();
+        simulation.assign_body_ids(&mut bodies);
+        simulation.bodies = bodies;
+        simulation.plates = plates;
+        return simulation;
+    }
+
+    // Headless simulation with its constants set up front
+    pub fn with_parameters(bodies: Vec<Body>, plates: Vec<Plate>, dt: f32, qe: f32, qp: f32, temperature: f32) -> Self {
+        let mut simulation = Self::headless(bodies, plates);
+        simulation.set_parameters(Parameters { dt, qe, qp, temperature, ..simulation.parameters() });
+        return simulation;
+    }
+
+    pub fn parameters(&self) -> Parameters {
+        return Parameters {
+            dt: self.dt,
+            qe: self.qe,
+            qp: self.qp,
+            temperature: self.temperature,
+            rewind: self.rewind,
+            steps_per_frame: self.steps_per_frame,
+            target_ticks_per_second: self.target_ticks_per_second,
+            max_speed: self.max_speed,
+            integration_mode: self.integration_mode,
+            force_method: self.force_method,
+            fixed_root: self.fixed_root,
+            fixed_root_margin: self.fixed_root_margin,
+            theta: self.quadtree.t_sq.sqrt(),
+            epsilon: self.quadtree.e_sq.sqrt(),
+            leaf_capacity: self.quadtree.leaf_capacity,
+            morton_sort: self.morton_sort,
+            dipole: self.quadtree.dipole,
+            resist_model: self.resist_model,
+            precise_plates: self.precise_plates,
+            plate_epsilon: self.plate_epsilon,
+            interior_epsilon: self.interior_epsilon,
+            interpolate_plates: self.interpolate_plates,
+            plate_grid_spacing: self.plate_grid_spacing,
+            induced_charge: self.induced_charge,
+            boundary: self.boundary,
+            world_size: self.bounds_max - self.bounds_min,
+            separation: self.separation,
+            separation_strength: self.separation_strength,
+            stats_interval: self.stats_interval,
+        };
+    }
+
+    pub fn set_parameters(&mut self, parameters: Parameters) {
+        self.dt = parameters.dt;
+        self.qe = parameters.qe;
+        self.qp = parameters.qp;
+        self.temperature = parameters.temperature;
+        self.rewind = parameters.rewind;
+        self.steps_per_frame = parameters.steps_per_frame;
+        self.target_ticks_per_second = parameters.target_ticks_per_second;
+        self.max_speed = parameters.max_speed;
+        self.integration_mode = parameters.integration_mode;
+        self.force_method = parameters.force_method;
+        self.fixed_root = parameters.fixed_root;
+        self.fixed_root_margin = parameters.fixed_root_margin;
+        self.quadtree.set_theta(parameters.theta);
+        self.quadtree.set_epsilon(parameters.epsilon);
+        self.quadtree.leaf_capacity = parameters.leaf_capacity;
+        self.morton_sort = parameters.morton_sort;
+        self.quadtree.dipole = parameters.dipole;
+        self.resist_model = parameters.resist_model;
+        self.precise_plates = parameters.precise_plates;
+        self.plate_epsilon = parameters.plate_epsilon;
+        self.interior_epsilon = parameters.interior_epsilon.max(MIN_EPSILON);
+        self.interpolate_plates = parameters.interpolate_plates;
+        self.plate_grid_spacing = parameters.plate_grid_spacing;
+        self.induced_charge = parameters.induced_charge;
+        self.boundary = parameters.boundary;
+        self.bounds_min = -parameters.world_size * 0.5;
+        self.bounds_max = parameters.world_size * 0.5;
+        self.separation = parameters.separation;
+        self.separation_strength = parameters.separation_strength;
+        self.stats_interval = parameters.stats_interval;
+    }
+
+    // Restart the random numbers from a seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+
+    // Simulation that pulls its scene edits from a channel
+    pub fn with_edits(edits: Receiver<EditCommand>) -> Self {
+        let mut simulation = Self::new();
+        simulation.edits = Some(edits);
+        return simulation;
+    }
+
     pub fn step(&mut self) {
-        self.refresh_objects();
-        self.iterate();
-        self.attract();
+        let start = Instant::now();
+        self.performance = Performance::default();
+
+        self.apply_edits();
+        self.update_plate_grid();
+        if !self.plate_index.matches(&self.plates) {
+            self.plate_index = PlateIndex::new(&self.plates, PLATE_INDEX_CELL_SIZE);
+        }
+
+        self.substeps = self.substep_count();
+        self.clamped = 0;
+        // Rewinding runs the same integrators with a negated time step
+        let direction = if self.rewind { -1.0 } else { 1.0 };
+        let dt = direction * self.dt / self.substeps as f32;
+
+        // Start a fresh crossing count
+        self.measurements.crossings = vec![0.0; self.plates.len()];
+        self.step_drift = vec![[Vec2::zero(); DRIFT_GROUPS]; self.plates.len()];
+
+        for _ in 0..self.substeps {
+            let integrate_start = Instant::now();
+            self.iterate(dt);
+            self.performance.integrate_time += integrate_start.elapsed().as_secs_f32();
+
+            self.jitter(dt);
+            if self.separation {
+                self.separate();
+            }
+            self.apply_boundary();
+            self.validate();
+            self.attract();
+            if self.integration_mode == IntegrationMode::Verlet {
+                let kick_start = Instant::now();
+                self.kick(dt);
+                self.performance.integrate_time += kick_start.elapsed().as_secs_f32();
+            }
+            self.time += dt;
+        }
+
+        // Before bodies are absorbed or emitted, while the tree still indexes them
+        self.measure_region();
+        // Sinks and emitters only run forwards; rewinding leaves the body count alone
+        if !self.rewind {
+            self.absorb_and_emit(self.dt);
+        }
+        self.measure_plate_contents();
+
+        self.update_currents();
+        self.update_drift();
+        self.regulate_batteries();
+
+        if self.frame % self.stats_interval.max(1) == 0 {
+            self.update_stats();
+        }
+
+        self.performance.step_time = start.elapsed().as_secs_f32();
+        self.average_performance.blend(&self.performance);
         self.frame += 1;
+
+        self.read_scope_probes();
+    }
+
+    pub fn apply_boundary(&mut self) {
+        let (min, max) = (self.bounds_min, self.bounds_max);
+
+        match self.boundary {
+            Boundary::None => {},
+            Boundary::Reflect => {
+                for body in &mut self.bodies {
+                    if body.pos.x < min.x {
+                        body.pos.x = (2.0 * min.x - body.pos.x).min(max.x);
+                        body.vel.x = body.vel.x.abs();
+                    } else if body.pos.x > max.x {
+                        body.pos.x = (2.0 * max.x - body.pos.x).max(min.x);
+                        body.vel.x = -body.vel.x.abs();
+                    }
+
+                    if body.pos.y < min.y {
+                        body.pos.y = (2.0 * min.y - body.pos.y).min(max.y);
+                        body.vel.y = body.vel.y.abs();
+                    } else if body.pos.y > max.y {
+                        body.pos.y = (2.0 * max.y - body.pos.y).max(min.y);
+                        body.vel.y = -body.vel.y.abs();
+                    }
+                }
+            },
+            Boundary::Delete => {
+                self.bodies.retain(|body| {
+                    body.pos.x >= min.x && body.pos.x <= max.x && body.pos.y >= min.y && body.pos.y <= max.y
+                });
+            },
+            Boundary::Periodic => {
+                for body in &mut self.bodies {
+                    body.pos = wrap_pos(body.pos, Some((min, max)));
+                }
+            }
+        }
+    }
+
+    // Remove bodies that went non-finite and reset broken fields before they spread
+    pub fn validate(&mut self) {
+        let count = self.bodies.len();
+        self.bodies.retain(|body| body.pos.x.is_finite() && body.pos.y.is_finite());
+        let removed = count - self.bodies.len();
+
+        let mut reset = 0;
+        for body in &mut self.bodies {
+            let finite = |v: Vec2| v.x.is_finite() && v.y.is_finite();
+            if !finite(body.vel) || !finite(body.efield) || !finite(body.acc) || !finite(body.prev_acc) {
+                body.vel = Vec2::zero();
+                body.efield = Vec2::zero();
+                body.acc = Vec2::zero();
+                body.prev_acc = Vec2::zero();
+                reset += 1;
+            }
+        }
+
+        if removed > 0 || reset > 0 {
+            self.nan_events += 1;
+            eprintln!("Frame {}: removed {} non-finite bodies, reset {} non-finite fields", self.frame, removed, reset);
+        }
+    }
+
+    pub fn periodic_domain(&self) -> Option<(Vec2, Vec2)> {
+        if self.boundary == Boundary::Periodic {
+            return Some((self.bounds_min, self.bounds_max));
+        }
+
+        None
+    }
+
+    // Sinks delete the bodies inside them and emitters spawn new ones
+    pub fn absorb_and_emit(&mut self, dt: f32) {
+        for plate in &mut self.plates {
+            match plate.plate_type {
+                PlateType::Sink => {
+                    self.bodies.retain(|body| body.fixed || !plate.contains_point(body.pos));
+                },
+                PlateType::Emitter => {
+                    plate.emit_accum += plate.emit_rate * dt;
+                    while plate.emit_accum >= 1.0 {
+                        let pos = Vec2::new(
+                            self.rng.f32() * (plate.max.x - plate.min.x) + plate.min.x,
+                            self.rng.f32() * (plate.max.y - plate.min.y) + plate.min.y,
+                        );
+                        let mut body = Body::new(pos, 1.0);
+                        body.id = self.next_body_id;
+                        self.next_body_id += 1;
+                        self.bodies.push(body);
+                        plate.emit_accum -= 1.0;
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // Number of substeps needed so no body moves farther than max_step_dist per substep
+    pub fn substep_count(&self) -> usize {
+        let mut max_dist = 0.0f32;
+        for body in self.bodies.iter().filter(|body| !body.fixed) {
+            let dist = (body.get_new_pos(self.dt, self.integration_mode) - body.pos).mag();
+            max_dist = max_dist.max(dist);
+        }
+
+        ((max_dist / self.max_step_dist).ceil() as usize).clamp(1, MAX_SUBSTEPS)
+    }
+
+    pub fn field_params(&self) -> FieldParams {
+        FieldParams {
+            qp: self.qp,
+            qe: self.qe,
+            time: self.time,
+            precise_plates: self.precise_plates,
+            plate_epsilon: self.plate_epsilon,
+            temperature: self.temperature,
+        }
+    }
+
+    // Rebuild the plate field grid when the plates or the field settings have changed
+    pub fn update_plate_grid(&mut self) {
+        if !self.interpolate_plates {
+            self.plate_grid = None;
+            return;
+        }
+
+        let stale = match &self.plate_grid {
+            Some(grid) => !grid.matches(&self.plates, self.plate_grid_spacing, self.precise_plates, self.plate_epsilon),
+            None => true,
+        };
+        if stale {
+            self.plate_grid = Some(PlateFieldGrid::new(&self.plates, self.plate_grid_spacing, self.precise_plates, self.plate_epsilon));
+        }
+    }
+
+    // Apply the edits sent since the last step
+    pub fn apply_edits(&mut self) {
+        // A handle of our own, so the edits can change the rest of self
+        let Some(edits) = self.edits.clone() else {
+            return;
+        };
+
+        for mut edit in edits.try_iter() {
+            match &mut edit {
+                EditCommand::Reseed(seed) => {
+                    self.seed = *seed;
+                    self.rng = fastrand::Rng::with_seed(*seed);
+                },
+                EditCommand::SetParameters(parameters) => self.set_parameters(*parameters),
+                // New bodies get ids of their own, even when copied from bodies that have them
+                EditCommand::AddBodies(bodies) | EditCommand::AddPlate { bodies, .. } | EditCommand::ReplaceScene { bodies, .. } => {
+                    self.assign_body_ids(bodies);
+                },
+                EditCommand::RemoveBody { id } | EditCommand::PinBody { id } => {
+                    if !self.bodies.iter().any(|body| body.id == *id) {
+                        self.failed_edits.push("The body was gone before the edit reached it".to_string());
+                    }
+                },
+                EditCommand::SetInstruments(instruments) => self.instruments = std::mem::take(instruments),
+                EditCommand::Inspect(inspect) => {
+                    self.inspect = *inspect;
+                    self.inspected = None;
+                },
+                _ => {}
+            }
+            if !edit.changes_scene() {
+                continue;
+            }
+            edit.apply(&mut self.bodies, &mut self.plates);
+            self.edits_applied += 1;
+            self.plates_dirty = true;
+        }
+    }
+
+    // Whether a snapshot now would differ from the last one, i.e. we stepped or applied edits since
+    pub fn snapshot_stale(&self) -> bool {
+        return self.last_snapshot.as_ref() != Some(&self.snapshot_key()) || self.streamlines_stale();
+    }
+
+    fn snapshot_key(&self) -> SnapshotKey {
+        return SnapshotKey {
+            frame: self.frame,
+            edits_applied: self.edits_applied,
+            instruments: self.instruments.clone(),
+            inspect: self.inspect,
+        };
+    }
+
+    // Whether the streamlines need tracing again, because the request, the scene or the charges changed
+    fn streamlines_stale(&self) -> bool {
+        let Some(request) = &self.instruments.streamline_request else {
+            return false;
+        };
+
+        return match &self.streamline_key {
+            Some((traced, edits_applied, parameters)) => {
+                traced != request || *edits_applied != self.edits_applied || *parameters != self.parameters()
+            },
+            None => true,
+        };
     }
 
-    pub fn refresh_objects(&mut self) {
-        let mut lock = renderer::RENDERER_TO_SIM_UPDATE_LOCK.lock();
-        if *lock {
-            std::mem::swap(&mut self.bodies, &mut renderer::BODIES.lock());
-            std::mem::swap(&mut self.plates, &mut renderer::PLATES.lock());
-            *lock = false;
+    // Copy of the scene for the renderer, taking the plates only when they changed
+    pub fn snapshot(&mut self) -> SimSnapshot {
+        // Read before the key is taken, since following the inspected body moves its point
+        self.measure_instruments();
+        self.last_snapshot = Some(self.snapshot_key());
+
+        let streamlines = if self.streamlines_stale() {
+            self.instruments.streamline_request.clone().map(|request| {
+                let streamlines = self.trace_streamlines(&request);
+                self.streamline_key = Some((request, self.edits_applied, self.parameters()));
+                streamlines
+            })
+        } else {
+            None
+        };
+        if self.instruments.streamline_request.is_none() {
+            self.streamline_key = None;
+        }
+        let plates = if self.plates_dirty { Some(self.plates.clone()) } else { None };
+        self.plates_dirty = false;
+
+        SimSnapshot {
+            bodies: self.bodies.clone(),
+            plates,
+            quadtree: if self.instruments.quadtree { self.quadtree.nodes.clone() } else { Vec::new() },
+            measurements: self.measurements.clone(),
+            stats: self.stats,
+            frame: self.frame,
+            taken: Instant::now(),
+            parameters: self.parameters(),
+            readings: self.readings(),
+            performance: self.average_performance,
+            field_backend: self.field_backend(),
+            seed: self.seed,
+            field: self.instruments.field_request.map(|request| self.sample_field(request)),
+            streamlines,
+            cursor: self.instruments.cursor.map(|pos| CursorReading {
+                pos,
+                efield: self.efield_at(pos),
+                potential: self.potential_at(pos),
+            }),
+            probe_samples: self.probe_samples.drain(..).collect(),
+            failed_edits: std::mem::take(&mut self.failed_edits),
+            edits_applied: self.edits_applied,
         }
     }
 
     pub fn attract(&mut self) {
-        let quad = Quad::new_containing(&self.bodies);
+        // Capacitors absorb the bodies that flow into them and store their charge
+        for plate in &mut self.plates {
+            if plate.plate_type == PlateType::Capacitor {
+                self.bodies.retain(|body| {
+                    if !body.fixed && plate.contains_point(body.pos) {
+                        plate.stored_charge += body.charge;
+                        self.plates_dirty = true;
+                        return false;
+                    }
+                    true
+                });
+            }
+        }
+
+        if self.bodies.is_empty() {
+            return;
+        }
+
+        // Bounded worlds use the fixed bounds as the root instead of refitting every step
+        let fixed_root = self.fixed_root_quad();
+        let mut quad = if self.boundary != Boundary::None {
+            Quad::new_bounds(self.bounds_min, self.bounds_max)
+        } else if let Some(quad) = fixed_root {
+            quad
+        } else {
+            Quad::new_containing(&self.bodies)
+        };
+        if self.induced_charge {
+            for plate in &self.plates {
+                quad.include(plate.min, plate.max);
+            }
+        }
+        let build_start = Instant::now();
+
         self.quadtree.clear(quad);
+        self.quadtree.period = self.periodic_domain().map(|(min, max)| max - min);
 
-        for body in &mut self.bodies {
-            self.quadtree.insert(body.pos, 1.0);
+        let sources = self.point_charges();
+
+        // Neighbors in space are inserted and walked one after another. Only this order is sorted, not the bodies,
+        // since the renderer and its edits refer to bodies by index
+        let mut order: Vec<usize> = (0..sources.len()).collect();
+        if self.morton_sort {
+            // Stable, so bodies sharing a cell keep their order and runs stay reproducible
+            order.par_sort_by_key(|&i| morton_code(&quad, sources[i].0));
+        }
+
+        for &i in &order {
+            let (pos, charge) = sources[i];
+            // Bodies outside a fixed root are left out of the tree but still feel its field
+            if fixed_root.is_some() && !quad.contains(pos) {
+                continue;
+            }
+            self.quadtree.insert(pos, charge, i);
         }
 
         self.quadtree.propagate();
+        self.performance.build_time += build_start.elapsed().as_secs_f32();
 
-        for body in &mut self.bodies {
-            body.efield = self.quadtree.efield(body.pos) * self.qe;
-        }
+        let walk_start = Instant::now();
+        let period = self.quadtree.period;
+        self.force_error = None;
 
-        for body in &mut self.bodies {
-            for plate in &mut self.plates {
-                body.efield += plate.efield_at(body.pos) * self.qp;
+        match self.force_method {
+            ForceMethod::BarnesHut => {
+                if !self.gpu_efield() {
+                    let (bodies, plates, index, quadtree) = (&self.bodies, &self.plates, &self.plate_index, &self.quadtree);
+                    let (qe, interior_epsilon) = (self.qe, self.interior_epsilon);
 
-                if plate.contains_point(body.pos) {
-                    let w = plate.max.x - plate.min.x;
-                    let h = plate.max.y - plate.min.y;
-                    
-                    // Battery
-                    let strength_x = 1.0 - (body.pos.x - (plate.min.x + plate.max.x) / 2.0).abs() / (w / 2.0);
-                    let strength_y = 1.0 - (body.pos.y - (plate.min.y + plate.max.y) / 2.0).abs() / (h / 2.0);
+                    // Walked in the insertion order, so consecutive walks visit the same nodes; induced charges
+                    // come after the bodies and feel no force
+                    let efields: Vec<(usize, Vec2)> = order.par_iter().filter(|&&i| i < bodies.len()).map(|&i| {
+                        let body = &bodies[i];
+                        let e_sq = softening_sq(plates, index, body.pos, quadtree.e_sq, interior_epsilon);
+                        (i, quadtree.efield_with(body.pos, e_sq) * qe * body.charge)
+                    }).collect();
+                    for (i, efield) in efields {
+                        self.bodies[i].efield = efield;
+                    }
+                }
+            },
+            ForceMethod::Direct => {
+                let (plates, index, e_sq) = (&self.plates, &self.plate_index, self.quadtree.e_sq);
+                let (qe, interior_epsilon) = (self.qe, self.interior_epsilon);
+                self.bodies.par_iter_mut().for_each(|body| {
+                    let e_sq = softening_sq(plates, index, body.pos, e_sq, interior_epsilon);
+                    body.efield = direct_efield(&sources, body.pos, e_sq, period) * qe * body.charge;
+                });
+            },
+            ForceMethod::Compare => {
+                let compare = self.bodies.len() <= COMPARE_LIMIT;
+                let mut max_error = 0.0f32;
+                let mut sum_sq_error = 0.0f32;
+                let mut count = 0;
+
+                for body in &mut self.bodies {
+                    let e_sq = softening_sq(&self.plates, &self.plate_index, body.pos, self.quadtree.e_sq, self.interior_epsilon);
+                    let efield = self.quadtree.efield_with(body.pos, e_sq);
+                    body.efield = efield * self.qe * body.charge;
 
-                    body.efield.x += plate.efield.x * strength_x;
-                    body.efield.y += plate.efield.y * strength_y;
+                    if compare {
+                        let exact = direct_efield(&sources, body.pos, e_sq, period);
+                        if exact.mag_sq() > 0.0 {
+                            let error = (efield - exact).mag() / exact.mag();
+                            max_error = max_error.max(error);
+                            sum_sq_error += error * error;
+                            count += 1;
+                        }
+                    }
+                }
 
-                    // Resistor
-                    body.resist = plate.resist;
+                if count > 0 {
+                    self.force_error = Some((max_error, (sum_sq_error / count as f32).sqrt()));
                 }
             }
         }
+        self.performance.force_time += walk_start.elapsed().as_secs_f32();
+        self.performance.calcs += self.quadtree.calcs.load(Ordering::Relaxed);
+        self.performance.nodes = self.quadtree.nodes.len();
+        self.performance.max_depth = self.performance.max_depth.max(self.quadtree.max_depth);
+
+        let plate_start = Instant::now();
+
+        // Scattering rolls are drawn up front so the seeded rng stays deterministic across threads
+        let rolls: Vec<f32> = match self.resist_model {
+            ResistModel::Scale => Vec::new(),
+            ResistModel::Scatter => (0..self.bodies.len()).map(|_| self.rng.f32()).collect(),
+        };
+
+        let params = self.field_params();
+        let (plates, index, grid, resist_model) = (&self.plates, &self.plate_index, self.plate_grid.as_ref(), self.resist_model);
+        self.bodies.par_iter_mut().enumerate().for_each(|(i, body)| {
+            let (efield, resist) = plate_efield(plates, index, grid, body.pos, body.charge, &params);
+            body.efield += efield;
+            body.efield *= dielectric_scale(plates, index, body.pos);
+
+            if let Some(resist) = resist {
+                body.resist = match resist_model {
+                    ResistModel::Scale => resist,
+                    // A scattering event (probability 1 - resist) stops the body, otherwise it moves freely
+                    ResistModel::Scatter => if rolls[i] < resist { 1.0 } else { 0.0 },
+                };
+            }
+
+            if let Some(resist) = diode_resist(plates, index, body.pos, body.efield) {
+                body.resist = resist;
+            }
+        });
+
+        if self.induced_charge {
+            self.relax_induced_charge();
+        }
+        self.performance.plate_time += plate_start.elapsed().as_secs_f32();
     }
 
-    pub fn iterate(&mut self) {
-        let bodies_len = self.bodies.len();
-        for i in 0..bodies_len {
-            let body = &mut self.bodies[i];
-            self.bodies[i].pos = get_new_pos_clip(body, &self.plates, self.dt);
+    // Barnes-Hut field on the GPU, returning false to fall back to the CPU walk
+    #[cfg(feature = "gpu")]
+    fn gpu_efield(&mut self) -> bool {
+        let Some(gpu) = &self.gpu else {
+            return false;
+        };
+
+        let (plates, index) = (&self.plates, &self.plate_index);
+        let (e_sq, interior_epsilon) = (self.quadtree.e_sq, self.interior_epsilon);
+        let queries: Vec<(Vec2, f32)> = self.bodies.par_iter().map(|body| {
+            (body.pos, softening_sq(plates, index, body.pos, e_sq, interior_epsilon))
+        }).collect();
+
+        let Some(efields) = gpu.efield(&self.quadtree, &queries) else {
+            // A device that failed once is dropped for good
+            eprintln!("GPU field evaluation failed, falling back to the CPU");
+            self.gpu = None;
+            return false;
+        };
+
+        for (body, efield) in self.bodies.iter_mut().zip(efields) {
+            body.efield = efield * self.qe * body.charge;
         }
+
+        true
     }
-}
 
-pub fn get_new_pos_clip(body: &Body, plates: &Vec<Plate>, dt: f32) -> Vec2 {
-    let old_pos = body.pos;
-    let new_pos = body.get_new_pos(dt);
+    #[cfg(not(feature = "gpu"))]
+    fn gpu_efield(&mut self) -> bool {
+        false
+    }
 
-    fn on_plate(pos: Vec2, plates: &Vec<Plate>) -> bool {
-        for plate in plates {
-            if plate.is_in_plate(pos) {
-                return true;
+    // Move induced charge along each conductor's boundary to cancel the tangential field
+    pub fn relax_induced_charge(&mut self) {
+        for p in 0..self.plates.len() {
+            if !self.plates[p].is_conductor() {
+                continue;
+            }
+
+            let points = self.plates[p].segment_points();
+            let mut induced = self.plates[p].induced;
+            let mut moved = 0.0f32;
+
+            for i in 0..INDUCED_SEGMENTS {
+                let j = (i + 1) % INDUCED_SEGMENTS;
+                let tangent = points[j] - points[i];
+                let mid = (points[i] + points[j]) * 0.5;
+
+                // Field on a unit positive charge at the midpoint between the segments
+                let efield = self.quadtree.efield(mid) * self.qe
+                    + plate_efield(&self.plates, &self.plate_index, self.plate_grid.as_ref(), mid, 1.0, &self.field_params()).0;
+
+                let dq = efield.dot(tangent.normalized()) * INDUCED_RATE;
+                induced[i] -= dq;
+                induced[j] += dq;
+                moved = moved.max(dq.abs());
+            }
+
+            self.plates[p].induced = induced;
+            if moved > INDUCED_SETTLED {
+                self.plates_dirty = true;
             }
         }
-        return false;
     }
 
-    if on_plate(new_pos, plates) {
-        return new_pos;
-    } else if on_plate(Vec2::new(new_pos.x, old_pos.y), plates) {
-        return Vec2::new(new_pos.x, old_pos.y);
-    } else if on_plate(Vec2::new(old_pos.x, new_pos.y), plates) {
-        return Vec2::new(old_pos.x, new_pos.y);
-    } else if !on_plate(Vec2::new(old_pos.x, old_pos.y), plates) {
-        return new_pos;
-    } else {
-        return old_pos;
+    // Smoothed current through a plate, in bodies per step
+    pub fn current_through(&self, plate: usize) -> f32 {
+        let Some(plate) = self.plates.get(plate) else { return 0.0 };
+        return self.measurements.currents.get(&plate.id).copied().unwrap_or(0.0);
+    }
+
+    // Blend this step's crossings into each plate's average, which starts from zero for a new plate
+    fn update_currents(&mut self) {
+        self.measurements.currents.retain(|id, _| self.plates.iter().any(|plate| plate.id == *id));
+
+        for (plate, &crossings) in self.plates.iter().zip(&self.measurements.crossings) {
+            let current = self.measurements.currents.entry(plate.id).or_insert(0.0);
+            *current += (crossings - *current) * CURRENT_SMOOTHING;
+        }
+    }
+
+    pub fn update_stats(&mut self) {
+        let mut stats = Stats {
+            body_count: self.bodies.len(),
+            ..Default::default()
+        };
+
+        // The tree only holds the current bodies when there are some
+        let use_tree = !self.bodies.is_empty();
+        let self_term = if self.quadtree.e_sq > 0.0 { -0.5 * self.quadtree.e_sq.ln() } else { 0.0 };
+
+        for body in &self.bodies {
+            stats.total_charge += body.charge;
+            stats.kinetic_energy += 0.5 * body.mass * body.vel.mag_sq();
+
+            if use_tree {
+                // Remove the body's own softened contribution before pairing
+                let potential = self.quadtree.potential(body.pos) - body.charge * self_term;
+                stats.potential_energy += 0.5 * self.qe * body.charge * potential;
+            }
+        }
+
+        self.stats = stats;
+    }
+
+    // Electrostatic potential, such that electrons are pushed towards higher values
+    pub fn potential_at(&self, pos: Vec2) -> f32 {
+        let mut potential = 0.0;
+
+        // The tree is only rebuilt while there are bodies
+        if !self.bodies.is_empty() {
+            potential += self.quadtree.potential(pos) * self.qe;
+        }
+
+        for plate in &self.plates {
+            potential -= plate.potential_at_auto(pos, self.precise_plates, self.plate_epsilon) * plate.charge_density(self.qp, self.qe);
+        }
+
+        potential
+    }
+
+    pub fn measure_region(&mut self) {
+        self.region_stats = self.instruments.region.map(|(min, max)| {
+            let mut found = Vec::new();
+            self.quadtree.query_rect(min, max, &mut found);
+
+            // Induced charges are indexed after the bodies
+            let bodies: Vec<&Body> = found.iter().filter_map(|&i| self.bodies.get(i)).collect();
+            (bodies.len(), bodies.iter().map(|body| body.charge).sum())
+        });
+    }
+
+    // Give each body the next id
+    fn assign_body_ids(&mut self, bodies: &mut [Body]) {
+        for body in bodies {
+            body.id = self.next_body_id;
+            self.next_body_id += 1;
+        }
+    }
+
+    // Find the inspected body again by its id, or pick the one nearest a new inspect point
+    pub fn inspect_body(&mut self) {
+        let last = self.inspected.take();
+        let Some((pos, radius)) = self.inspect else { return };
+
+        // The point is left where the body was last seen until the renderer asks about another one
+        let followed = last.filter(|body| body.pos == pos).and_then(|last| self.bodies.iter().find(|body| body.id == last.id));
+
+        // The tree can be a step behind the body list, so the index is checked against the body it names
+        let picked = || self.quadtree.nearest(pos).and_then(|i| self.bodies.get(i)).filter(|body| (body.pos - pos).mag() <= radius);
+
+        if let Some(body) = followed.or_else(picked) {
+            self.inspected = Some(*body);
+            self.inspect = Some((body.pos, radius));
+        }
+    }
+
+    pub fn measure_probe(&mut self) {
+        self.probe_voltage = self.instruments.probe.map(|(a, b)| self.potential_at(b) - self.potential_at(a));
+    }
+
+    pub fn measure_ruler(&mut self) {
+        self.ruler_voltage = self.instruments.ruler.map(|(a, b)| self.potential_at(b) - self.potential_at(a));
+    }
+
+    // Read the instruments that are only needed when a snapshot goes out
+    fn measure_instruments(&mut self) {
+        self.measure_probe();
+        self.measure_ruler();
+        self.measure_flux();
+        self.inspect_body();
+    }
+
+    pub fn readings(&self) -> Readings {
+        return Readings {
+            probe_voltage: self.probe_voltage,
+            ruler_voltage: self.ruler_voltage,
+            region_stats: self.region_stats,
+            flux: self.flux.clone(),
+            inspected: self.inspected,
+            substeps: self.substeps,
+            clamped: self.clamped,
+            nan_events: self.nan_events,
+            force_error: self.force_error,
+            plate_grid_error: self.plate_grid.as_ref().map(|grid| grid.error),
+        };
+    }
+
+    // Where the body field is evaluated, for display
+    pub fn field_backend(&self) -> String {
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &self.gpu {
+            return format!("GPU ({})", gpu.adapter_name);
+        }
+        return "CPU".to_string();
+    }
+
+    // Electrostatic field, the negative gradient of potential_at (battery drives are not included)
+    pub fn efield_at(&self, pos: Vec2) -> Vec2 {
+        let quadtree = if self.bodies.is_empty() { None } else { Some(&self.quadtree) };
+        return static_efield(quadtree, &self.plates, pos, &self.field_params());
+    }
+
+    // Sample efield_at at every cell of a request, in parallel
+    pub fn sample_field(&self, request: FieldRequest) -> FieldSamples {
+        let quadtree = if self.bodies.is_empty() { None } else { Some(&self.quadtree) };
+        let plates = &self.plates;
+        let params = self.field_params();
+
+        let efield = (0..request.columns * request.rows).into_par_iter().map(|i| {
+            static_efield(quadtree, plates, request.point(i % request.columns, i / request.columns), &params)
+        }).collect();
+
+        FieldSamples { request, efield }
+    }
+
+    // Field line through each seed, in parallel
+    pub fn trace_streamlines(&self, request: &StreamlineRequest) -> Vec<Vec<Vec2>> {
+        let quadtree = if self.bodies.is_empty() { None } else { Some(&self.quadtree) };
+        let plates = &self.plates;
+        let params = self.field_params();
+
+        request.seeds.par_iter().map(|&seed| trace_streamline(quadtree, plates, seed, request, &params)).collect()
+    }
+
+    // Outward flux through the boundary of the rectangle from min to max
+    pub fn flux_through(&self, min: Vec2, max: Vec2) -> f32 {
+        let size = max - min;
+        let step = size / FLUX_SAMPLES_PER_SIDE as f32;
+        let mut flux = 0.0;
+
+        // Midpoint rule along each edge
+        for i in 0..FLUX_SAMPLES_PER_SIDE {
+            let t = i as f32 + 0.5;
+            let x = min.x + step.x * t;
+            let y = min.y + step.y * t;
+
+            flux += (self.efield_at(Vec2::new(x, max.y)).y - self.efield_at(Vec2::new(x, min.y)).y) * step.x;
+            flux += (self.efield_at(Vec2::new(max.x, y)).x - self.efield_at(Vec2::new(min.x, y)).x) * step.y;
+        }
+
+        flux
+    }
+
+    // Outward flux through each loop's boundary and the charge it encloses
+    pub fn measure_flux(&mut self) {
+        let sources = self.point_charges();
+
+        self.flux = self.instruments.flux_loops.iter().map(|&(min, max)| {
+            let flux = self.flux_through(min, max);
+
+            let mut enclosed = 0.0;
+            for &(pos, charge) in &sources {
+                if pos.x >= min.x && pos.x < max.x && pos.y >= min.y && pos.y < max.y {
+                    enclosed += charge * self.qe;
+                }
+            }
+
+            // Plates are normalized to half the field of an equal point charge
+            for plate in &self.plates {
+                let overlap = (max.x.min(plate.max.x) - min.x.max(plate.min.x)).max(0.0)
+                    * (max.y.min(plate.max.y) - min.y.max(plate.min.y)).max(0.0);
+                enclosed += 0.5 * overlap * plate.charge_density(self.qp, self.qe);
+            }
+
+            (flux, enclosed)
+        }).collect();
+    }
+
+    // Read every scope probe for the step just taken, keeping the readings until the next snapshot
+    fn read_scope_probes(&mut self) {
+        if self.instruments.scope_probes.is_empty() {
+            return;
+        }
+
+        // An ammeter whose plate was removed has nothing to read
+        let values = self.instruments.scope_probes.iter().filter_map(|probe| {
+            let value = match probe.kind {
+                ProbeKind::Voltage { a, b } => self.potential_at(b) - self.potential_at(a),
+                ProbeKind::Ammeter { plate } => self.current_through(self.plates.iter().position(|other| other.id == plate)?),
+                ProbeKind::FluxLoop { min, max } => self.flux_through(min, max) / std::f32::consts::TAU,
+            };
+            Some((probe.id, value))
+        }).collect();
+
+        self.probe_samples.push_back(ProbeSample { frame: self.frame, values });
+        while self.probe_samples.len() > PROBE_SAMPLE_LIMIT {
+            self.probe_samples.pop_front();
+        }
+    }
+
+    // Root quad around the plates, so runaway bodies can't stretch the tree
+    fn fixed_root_quad(&self) -> Option<Quad> {
+        if !self.fixed_root || self.boundary != Boundary::None || self.plates.is_empty() {
+            return None;
+        }
+
+        let mut min = Vec2::broadcast(f32::MAX);
+        let mut max = Vec2::broadcast(f32::MIN);
+        for plate in &self.plates {
+            min = min.min_by_component(plate.min);
+            max = max.max_by_component(plate.max);
+        }
+
+        let margin = Vec2::broadcast(self.fixed_root_margin);
+        Some(Quad::new_bounds(min - margin, max + margin))
+    }
+
+    // Every charge that contributes to the body-body field: bodies plus induced surface charges
+    pub fn point_charges(&self) -> Vec<(Vec2, f32)> {
+        let mut sources: Vec<(Vec2, f32)> = self.bodies.iter().map(|body| (body.pos, body.charge)).collect();
+
+        if self.induced_charge {
+            for plate in &self.plates {
+                if !plate.is_conductor() {
+                    continue;
+                }
+
+                let points = plate.segment_points();
+                for i in 0..INDUCED_SEGMENTS {
+                    if plate.induced[i] != 0.0 {
+                        sources.push((points[i], plate.induced[i]));
+                    }
+                }
+            }
+        }
+
+        sources
+    }
+
+    // Drift velocity of a body if it were moved to pos, with the rest of the scene held fixed
+    fn drift_at(&self, body: &Body, pos: Vec2) -> Vec2 {
+        // Remove the body's own contribution to the tree
+        let e_sq = softening_sq(&self.plates, &self.plate_index, pos, self.quadtree.e_sq, self.interior_epsilon);
+        let d = pos - body.pos;
+        let mut efield = self.quadtree.efield_with(pos, e_sq) - d * (body.charge / (d.mag_sq() + e_sq));
+        efield *= self.qe * body.charge;
+
+        let (plate_efield, resist) = plate_efield(&self.plates, &self.plate_index, self.plate_grid.as_ref(), pos, body.charge, &self.field_params());
+        efield += plate_efield;
+        efield *= dielectric_scale(&self.plates, &self.plate_index, pos);
+
+        let resist = diode_resist(&self.plates, &self.plate_index, pos, efield).or(resist).unwrap_or(body.resist);
+        efield / body.mass * resist
+    }
+
+    pub fn iterate(&mut self, dt: f32) {
+        // Largest displacement allowed this substep (max_speed is per full step)
+        let limit = if self.max_speed > 0.0 { self.max_speed * dt.abs() / self.dt } else { f32::INFINITY };
+        let domain = self.periodic_domain();
+
+        // Positions before the move, in a buffer reused across substeps
+        let mut old_positions = std::mem::take(&mut self.scratch_positions);
+        old_positions.clear();
+        old_positions.extend(self.bodies.iter().map(|body| body.pos));
+
+        let clamped = if self.integration_mode == IntegrationMode::Rk4 {
+            self.iterate_rk4(dt, limit, domain)
+        } else {
+            // Each body only reads the plates, so the bodies are moved in parallel
+            let (plates, index, mode) = (&self.plates, &self.plate_index, self.integration_mode);
+            self.bodies.par_iter_mut().filter(|body| !body.fixed).map(|body| {
+                let mut clamped = false;
+
+                match mode {
+                    IntegrationMode::Drift | IntegrationMode::Rk4 => {
+                        let mut new_pos = body.get_new_pos(dt, mode);
+                        if let Some(pos) = clamp_step(body.pos, new_pos, limit) {
+                            new_pos = pos;
+                            clamped = true;
+                        }
+
+                        body.pos = clip_pos(body.pos, wrap_pos(new_pos, domain), plates, index);
+                    },
+                    IntegrationMode::Inertial => {
+                        body.acc = body.efield / body.mass;
+                        body.vel = body.get_new_vel(dt);
+
+                        let mut new_pos = body.pos + body.vel * dt;
+                        if let Some(pos) = clamp_step(body.pos, new_pos, limit) {
+                            new_pos = pos;
+                            body.vel = (pos - body.pos) / dt;
+                            clamped = true;
+                        }
+
+                        move_body_clip(body, wrap_pos(new_pos, domain), plates, index);
+                    },
+                    IntegrationMode::Verlet => {
+                        body.acc = body.efield / body.mass;
+                        body.prev_acc = body.acc;
+
+                        let mut new_pos = body.get_new_pos(dt, mode);
+                        if let Some(pos) = clamp_step(body.pos, new_pos, limit) {
+                            new_pos = pos;
+                            body.vel = (pos - body.pos) / dt;
+                            body.prev_acc = Vec2::zero();
+                            clamped = true;
+                        }
+
+                        move_body_clip(body, wrap_pos(new_pos, domain), plates, index);
+                    }
+                }
+
+                clamped as usize
+            }).sum()
+        };
+
+        self.clamped = self.clamped.max(clamped);
+        self.count_crossings(&old_positions);
+        self.scratch_positions = old_positions;
+    }
+
+    // Count bodies crossing the middle of each plate, across its long axis, and accumulate their drift
+    fn count_crossings(&mut self, old_positions: &[Vec2]) {
+        let mut displacement = vec![[Vec2::zero(); DRIFT_GROUPS]; self.plates.len()];
+        let mut count = vec![[0; DRIFT_GROUPS]; self.plates.len()];
+
+        for (body, &old_pos) in self.bodies.iter().zip(old_positions) {
+            // Both ends inside the same plate, which also ignores periodic wraps
+            let Some(i) = self.plate_index.index_at(&self.plates, old_pos) else { continue };
+            let plate = &self.plates[i];
+            if !plate.contains_point(body.pos) {
+                continue;
+            }
+            let Some(crossings) = self.measurements.crossings.get_mut(i) else { continue };
+
+            // Group 0 holds every body, the rest one species each
+            for group in [0, body.species.index() + 1] {
+                displacement[i][group] += body.pos - old_pos;
+                count[i][group] += 1;
+            }
+
+            let weight = body.charge / ELECTRON_CHARGE;
+            let center = (plate.min + plate.max) * 0.5;
+            let horizontal = plate.max.x - plate.min.x >= plate.max.y - plate.min.y;
+
+            let (old, new, mid) = if horizontal {
+                (old_pos.x, body.pos.x, center.x)
+            } else {
+                (old_pos.y, body.pos.y, center.y)
+            };
+
+            if old < mid && new >= mid {
+                *crossings += weight;
+            } else if old >= mid && new < mid {
+                *crossings -= weight;
+            }
+        }
+
+        for (i, (displacement, count)) in displacement.iter().zip(&count).enumerate() {
+            for group in 0..DRIFT_GROUPS {
+                if count[group] > 0 {
+                    self.step_drift[i][group] += displacement[group] / count[group] as f32;
+                }
+            }
+        }
+    }
+
+    // Nudge each regulated battery's EMF towards its target current (integral control)
+    fn regulate_batteries(&mut self) {
+        for plate in &mut self.plates {
+            if plate.plate_type != PlateType::Battery || plate.gain <= 0.0 {
+                continue;
+            }
+
+            let current = self.measurements.currents.get(&plate.id).copied().unwrap_or(0.0);
+            let error = plate.target_current - current.abs();
+            let emf = (plate.regulated_emf + plate.gain * error).clamp(0.0, MAX_REGULATED_EMF);
+
+            // The plate field only needs rebuilding when the EMF moved, not while it sits at a limit or on target
+            if emf != plate.regulated_emf {
+                plate.regulated_emf = emf;
+                self.plates_dirty = true;
+            }
+        }
+    }
+
+    // Count the bodies in each plate, and add their charge to the plate's own and its induced charge
+    fn measure_plate_contents(&mut self) {
+        let mut counts = vec![0; self.plates.len()];
+        let mut charges: Vec<f32> = self.plates.iter().map(|plate| {
+            let induced: f32 = if self.induced_charge && plate.is_conductor() { plate.induced.iter().sum() } else { 0.0 };
+            return 0.5 * plate.area() * plate.charge_density(self.qp, self.qe) + induced * self.qe;
+        }).collect();
+
+        for body in &self.bodies {
+            if let Some(i) = self.plate_index.index_at(&self.plates, body.pos) {
+                counts[i] += 1;
+                charges[i] += body.charge * self.qe;
+            }
+        }
+
+        self.measurements.body_counts = counts;
+        self.measurements.net_charges = charges;
+    }
+
+    // Push this step's drift into each plate's history and publish the window averages
+    fn update_drift(&mut self) {
+        self.drift_history.retain(|id, _| self.plates.iter().any(|plate| plate.id == *id));
+
+        self.measurements.drift.clear();
+        self.measurements.species_drift.clear();
+        for (plate, &drift) in self.plates.iter().zip(&self.step_drift) {
+            let history = self.drift_history.entry(plate.id).or_default();
+            history.push_back(drift);
+            if history.len() > DRIFT_WINDOW {
+                history.pop_front();
+            }
+
+            let mut mean = [Vec2::zero(); DRIFT_GROUPS];
+            for entry in history.iter() {
+                for group in 0..DRIFT_GROUPS {
+                    mean[group] += entry[group] / history.len() as f32;
+                }
+            }
+
+            self.measurements.drift.push(mean[0]);
+            self.measurements.species_drift.push([mean[1], mean[2], mean[3]]);
+        }
+    }
+
+    fn iterate_rk4(&mut self, dt: f32, limit: f32, domain: Option<(Vec2, Vec2)>) -> usize {
+        // The stages only read the tree and the plates, so every new position is found in parallel and written after
+        let mut moves = std::mem::take(&mut self.scratch_moves);
+        let this = &*self;
+        this.bodies.par_iter().map(|body| {
+            if body.fixed {
+                return (body.pos, false);
+            }
+
+            // The field at the current position is already known from the last step
+            let k1 = body.efield / body.mass * body.resist;
+            let k2 = this.drift_at(body, body.pos + k1 * (0.5 * dt));
+            let k3 = this.drift_at(body, body.pos + k2 * (0.5 * dt));
+            let k4 = this.drift_at(body, body.pos + k3 * dt);
+
+            let mut new_pos = body.pos + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * (dt / 6.0);
+            let clamped = match clamp_step(body.pos, new_pos, limit) {
+                Some(pos) => {
+                    new_pos = pos;
+                    true
+                },
+                None => false,
+            };
+
+            (clip_pos(body.pos, wrap_pos(new_pos, domain), &this.plates, &this.plate_index), clamped)
+        }).collect_into_vec(&mut moves);
+
+        let mut clamped = 0;
+        for (body, &(pos, was_clamped)) in self.bodies.iter_mut().zip(&moves) {
+            body.pos = pos;
+            clamped += was_clamped as usize;
+        }
+
+        self.scratch_moves = moves;
+        clamped
+    }
+
+    // Thermal noise: random displacement kick scaled by sqrt(temperature * dt)
+    pub fn jitter(&mut self, dt: f32) {
+        if self.temperature <= 0.0 {
+            return;
+        }
+
+        let scale = (self.temperature * dt.abs()).sqrt();
+        let domain = self.periodic_domain();
+        for body in &mut self.bodies {
+            if body.fixed {
+                continue;
+            }
+
+            let kick = Vec2::new(
+                utils::random_gaussian(&mut self.rng),
+                utils::random_gaussian(&mut self.rng),
+            ) * scale;
+            body.pos = clip_pos(body.pos, wrap_pos(body.pos + kick, domain), &self.plates, &self.plate_index);
+        }
+    }
+
+    // Push overlapping bodies apart using a uniform grid of neighbor cells
+    pub fn separate(&mut self) {
+        let max_radius = self.bodies.iter().fold(0.0f32, |r, body| r.max(body.radius));
+        if max_radius <= 0.0 {
+            return;
+        }
+
+        let cell_size = max_radius * 2.0;
+        let cell_of = |pos: Vec2| ((pos.x / cell_size).floor() as i32, (pos.y / cell_size).floor() as i32);
+
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, body) in self.bodies.iter().enumerate() {
+            grid.entry(cell_of(body.pos)).or_default().push(i);
+        }
+
+        // Each push only reads positions, so this pass runs in parallel
+        let pushes: Vec<Vec2> = (0..self.bodies.len()).into_par_iter().map(|i| {
+            let body = &self.bodies[i];
+            let (cx, cy) = cell_of(body.pos);
+            let mut push = Vec2::zero();
+
+            for x in cx - 1..=cx + 1 {
+                for y in cy - 1..=cy + 1 {
+                    let Some(cell) = grid.get(&(x, y)) else { continue };
+
+                    for &j in cell {
+                        if i == j {
+                            continue;
+                        }
+
+                        let other = &self.bodies[j];
+                        let min_dist = body.radius + other.radius;
+                        let d = body.pos - other.pos;
+                        let dist = d.mag();
+
+                        if dist >= min_dist {
+                            continue;
+                        }
+
+                        // Coincident bodies are split along a direction picked from their indices
+                        let dir = if dist > 0.0 {
+                            d / dist
+                        } else {
+                            let a = (i as f32 - j as f32) * 2.399963;
+                            Vec2::new(a.cos(), a.sin())
+                        };
+
+                        push += dir * (min_dist - dist) * 0.5 * self.separation_strength;
+                    }
+                }
+            }
+
+            // Many overlaps at once would add up to a throw far past the neighbours, so a pass moves a body at most
+            // its radius and piles come apart over several steps
+            if push.mag_sq() > body.radius * body.radius {
+                push = push.normalized() * body.radius;
+            }
+            push
+        }).collect();
+
+        let domain = self.periodic_domain();
+        for (body, push) in self.bodies.iter_mut().zip(pushes) {
+            if body.fixed {
+                continue;
+            }
+            body.pos = clip_pos(body.pos, wrap_pos(body.pos + push, domain), &self.plates, &self.plate_index);
+        }
+    }
+
+    // Second half of the velocity Verlet step, once the field at the new positions is known
+    pub fn kick(&mut self, dt: f32) {
+        for body in &mut self.bodies {
+            if body.fixed {
+                continue;
+            }
+
+            body.acc = body.efield / body.mass;
+            body.vel += (body.prev_acc + body.acc) * 0.5 * dt;
+            body.vel *= body.resist;
+        }
+    }
+}
+
+// Field of the tree's charges and the plates at pos
+fn static_efield(quadtree: Option<&Quadtree>, plates: &[Plate], pos: Vec2, params: &FieldParams) -> Vec2 {
+    let mut efield = Vec2::zero();
+
+    if let Some(quadtree) = quadtree {
+        efield += quadtree.efield(pos) * params.qe;
+    }
+
+    for plate in plates {
+        efield -= plate.efield_at_auto(pos, params.precise_plates, params.plate_epsilon) * plate.charge_density(params.qp, params.qe);
+    }
+
+    efield
+}
+
+// Follow the field direction both ways from a seed with fixed-length midpoint steps, stopping at plates,
+// after max_steps, or where the field is too weak. The line runs along the field
+fn trace_streamline(quadtree: Option<&Quadtree>, plates: &[Plate], seed: Vec2, request: &StreamlineRequest, params: &FieldParams) -> Vec<Vec2> {
+    let follow = |sign: f32| -> Vec<Vec2> {
+        let direction = |pos: Vec2| -> Option<Vec2> {
+            let efield = static_efield(quadtree, plates, pos, params) * sign;
+            let mag = efield.mag();
+            if mag.is_finite() && mag >= request.min_field { Some(efield / mag) } else { None }
+        };
+
+        let mut points = Vec::new();
+        let mut pos = seed;
+        for _ in 0..request.max_steps {
+            let Some(k1) = direction(pos) else { break };
+            let Some(k2) = direction(pos + k1 * request.step * 0.5) else { break };
+            pos += k2 * request.step;
+
+            if plates.iter().any(|plate| plate.contains_point(pos)) {
+                break;
+            }
+            points.push(pos);
+        }
+
+        points
+    };
+
+    let mut line = follow(-1.0);
+    line.reverse();
+    line.push(seed);
+    line.extend(follow(1.0));
+
+    line
+}
+
+// Wrap a position into the periodic domain, if there is one
+pub fn wrap_pos(pos: Vec2, domain: Option<(Vec2, Vec2)>) -> Vec2 {
+    let Some((min, max)) = domain else { return pos };
+    let size = max - min;
+
+    Vec2::new(
+        min.x + (pos.x - min.x).rem_euclid(size.x),
+        min.y + (pos.y - min.y).rem_euclid(size.y),
+    )
+}
+
+// Scale a displacement down to the limit, keeping its direction
+fn clamp_step(old_pos: Vec2, new_pos: Vec2, limit: f32) -> Option<Vec2> {
+    let d = new_pos - old_pos;
+    let dist = d.mag();
+
+    if dist > limit {
+        return Some(old_pos + d * (limit / dist));
+    }
+
+    None
+}
+
+// Move a body and stop its velocity along any axis blocked by a plate edge
+fn move_body_clip(body: &mut Body, new_pos: Vec2, plates: &Vec<Plate>, index: &PlateIndex) {
+    let clipped_pos = clip_pos(body.pos, new_pos, plates, index);
+
+    if clipped_pos.x != new_pos.x {
+        body.vel.x = 0.0;
+        body.prev_acc.x = 0.0;
+    }
+    if clipped_pos.y != new_pos.y {
+        body.vel.y = 0.0;
+        body.prev_acc.y = 0.0;
+    }
+
+    body.pos = clipped_pos;
+}
+
+// Field from the plates acting on a body with the given charge, and the resist of the plate it's in
+// The grid, when given, replaces the exact plate fields wherever it covers pos
+pub fn plate_efield(plates: &Vec<Plate>, index: &PlateIndex, grid: Option<&PlateFieldGrid>, pos: Vec2, charge: f32, params: &FieldParams) -> (Vec2, Option<f32>) {
+    // Plate fields and battery drives are defined relative to an electron
+    let q = charge / ELECTRON_CHARGE;
+
+    let mut efield = Vec2::zero();
+    let mut resist = None;
+
+    match grid.and_then(|grid| Some((grid, grid.locate(pos)?))) {
+        Some((grid, sample)) => efield += grid.field(&sample, plates, params.qp, params.qe) * q,
+        None => {
+            for plate in plates {
+                efield += plate.efield_at_auto(pos, params.precise_plates, params.plate_epsilon) * plate.charge_density(params.qp, params.qe) * q;
+            }
+        },
+    }
+
+    if let Some(plate) = index.plate_at(plates, pos).filter(|plate| plate.is_conductor()) {
+        // Battery
+        let strength = plate.drive_profile(pos);
+        let drive = plate.drive(params.time);
+        efield.x += drive.x * strength.x * q;
+        efield.y += drive.y * strength.y * q;
+
+        // Resistor
+        resist = Some(plate.effective_resist(params.temperature));
+    }
+
+    (efield, resist)
+}
+
+// Squared softening for the body-body field at pos: bodies inside plates use the interior length
+pub fn softening_sq(plates: &Vec<Plate>, index: &PlateIndex, pos: Vec2, body_e_sq: f32, interior_epsilon: f32) -> f32 {
+    if index.plate_at(plates, pos).is_some() {
+        return interior_epsilon * interior_epsilon;
+    }
+
+    body_e_sq
+}
+
+// Bodies inside a dielectric feel the field divided by its relative permittivity
+pub fn dielectric_scale(plates: &Vec<Plate>, index: &PlateIndex, pos: Vec2) -> f32 {
+    for plate in index.candidates(plates, pos) {
+        if plate.plate_type == PlateType::Dielectric && plate.contains_point(pos) {
+            return 1.0 / plate.epsilon_r;
+        }
+    }
+
+    1.0
+}
+
+// Diodes heavily damp bodies whose field pushes them against the diode's direction
+pub fn diode_resist(plates: &Vec<Plate>, index: &PlateIndex, pos: Vec2, efield: Vec2) -> Option<f32> {
+    for plate in index.candidates(plates, pos) {
+        if plate.plate_type == PlateType::Diode && plate.contains_point(pos) {
+            if efield.dot(plate.direction_vec()) < 0.0 {
+                return Some(DIODE_REVERSE_RESIST);
+            }
+            return Some(plate.resist);
+        }
+    }
+
+    None
+}
+
+pub fn get_new_pos_clip(body: &Body, plates: &Vec<Plate>, index: &PlateIndex, dt: f32, mode: IntegrationMode) -> Vec2 {
+    return clip_pos(body.pos, body.get_new_pos(dt, mode), plates, index);
+}
+
+pub fn clip_pos(old_pos: Vec2, new_pos: Vec2, plates: &Vec<Plate>, index: &PlateIndex) -> Vec2 {
+    // Inside a conductor and outside every insulator
+    let on_plate = |pos: Vec2| -> bool {
+        let mut on = false;
+        for plate in index.candidates(plates, pos) {
+            if plate.contains_point(pos) {
+                if !plate.is_conductor() {
+                    return false;
+                }
+                on = true;
+            }
+        }
+        return on;
+    };
+
+    let in_insulator = |pos: Vec2| -> bool {
+        for plate in index.candidates(plates, pos) {
+            if plate.plate_type == PlateType::Insulator && plate.contains_point(pos) {
+                return true;
+            }
+        }
+        return false;
+    };
+
+    if on_plate(new_pos) {
+        return new_pos;
+    } else if on_plate(Vec2::new(new_pos.x, old_pos.y)) {
+        return Vec2::new(new_pos.x, old_pos.y);
+    } else if on_plate(Vec2::new(old_pos.x, new_pos.y)) {
+        return Vec2::new(old_pos.x, new_pos.y);
+    } else if !on_plate(Vec2::new(old_pos.x, old_pos.y)) {
+        // Free bodies move anywhere except into insulators
+        if !in_insulator(new_pos) || in_insulator(old_pos) {
+            return new_pos;
+        } else if !in_insulator(Vec2::new(new_pos.x, old_pos.y)) {
+            return Vec2::new(new_pos.x, old_pos.y);
+        } else if !in_insulator(Vec2::new(old_pos.x, new_pos.y)) {
+            return Vec2::new(old_pos.x, new_pos.y);
+        }
+        return old_pos;
+    } else {
+        return old_pos;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A conductor beside a pinned charge, with induced charge on
+    fn induced_scene() -> Simulation {
+        let plate = Plate::new(Vec2::new(-50.0, -20.0), Vec2::new(50.0, 20.0));
+        let charge = Body::new_fixed(Vec2::new(0.0, 60.0), 5.0);
+        let mut simulation = Simulation::headless(vec![charge], vec![plate]);
+        simulation.induced_charge = true;
+        return simulation;
+    }
+
+    #[test]
+    fn induced_charge_sums_to_zero_and_settles() {
+        let mut simulation = induced_scene();
+        for _ in 0..300 {
+            simulation.step();
+            let total: f32 = simulation.plates[0].induced.iter().sum();
+            assert!(total.abs() < 1e-3, "induced charge sums to {}", total);
+        }
+
+        // The pinned charge pulls charge towards its side, then it stops moving and the plates stop being republished
+        let last = simulation.plates[0].induced;
+        assert!(last.iter().any(|q| q.abs() > 1.0));
+        simulation.plates_dirty = false;
+        simulation.step();
+        let moved = simulation.plates[0].induced.iter().zip(&last).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+        assert!(moved < INDUCED_SETTLED, "induced charge still moving by {}", moved);
+        assert!(!simulation.plates_dirty);
+    }
+
+    // Largest change in total energy of the two_body pair over 10k steps
+    fn two_body_energy_drift(mode: IntegrationMode) -> f32 {
+        let mut simulation = Simulation::headless(utils::two_body(), Vec::new());
+        simulation.integration_mode = mode;
+        simulation.step();
+        let energy = |stats: &Stats| stats.kinetic_energy + stats.potential_energy;
+        let start = energy(&simulation.stats);
+
+        let mut drift = 0.0f32;
+        for _ in 0..10_000 {
+            simulation.step();
+            drift = drift.max((energy(&simulation.stats) - start).abs());
+        }
+        return drift;
+    }
+
+    #[test]
+    fn verlet_conserves_energy_better_than_euler() {
+        let euler = two_body_energy_drift(IntegrationMode::Inertial);
+        let verlet = two_body_energy_drift(IntegrationMode::Verlet);
+        assert!(verlet * 10.0 < euler, "energy drift: Verlet {} vs Euler {}", verlet, euler);
+    }
+
+    #[test]
+    fn verlet_leaves_fixed_bodies_still() {
+        let bodies = vec![Body::new_fixed(Vec2::zero(), 1.0), Body::new(Vec2::new(10.0, 0.0), 1.0)];
+        let mut simulation = Simulation::headless(bodies, Vec::new());
+        simulation.integration_mode = IntegrationMode::Verlet;
+        for _ in 0..10 {
+            simulation.step();
+        }
+
+        assert_eq!(simulation.bodies[0].vel, Vec2::zero());
+        assert_eq!(simulation.bodies[0].pos, Vec2::zero());
+        assert!(simulation.bodies[1].vel.mag() > 0.0);
+    }
+
+    #[test]
+    fn rewinding_neither_emits_nor_absorbs() {
+        let mut emitter = Plate::new(Vec2::new(-50.0, -10.0), Vec2::new(-30.0, 10.0));
+        emitter.plate_type = PlateType::Emitter;
+        emitter.emit_rate = 5.0;
+        let mut sink = Plate::new(Vec2::new(30.0, -10.0), Vec2::new(50.0, 10.0));
+        sink.plate_type = PlateType::Sink;
+        let bodies = vec![Body::new(Vec2::new(40.0, 0.0), -1.0)];
+        let mut simulation = Simulation::headless(bodies, vec![emitter, sink]);
+        simulation.rewind = true;
+
+        for _ in 0..10 {
+            simulation.step();
+        }
+        assert_eq!(simulation.bodies.len(), 1);
+        assert_eq!(simulation.plates[0].emit_accum, 0.0);
+
+        // Going forwards again, the sink takes its body and the emitter starts up
+        simulation.rewind = false;
+        simulation.step();
+        assert!(!simulation.bodies.iter().any(|body| simulation.plates[1].contains_point(body.pos)));
+        assert!(simulation.bodies.iter().all(|body| simulation.plates[0].contains_point(body.pos)));
+        assert!(!simulation.bodies.is_empty());
+    }
+
+    #[test]
+    fn pile_relaxes_into_a_uniform_blob() {
+        let n = 1000;
+        let mut simulation = Simulation::headless(vec![Body::new(Vec2::zero(), 1.0); n], Vec::new());
+        simulation.separation = true;
+        for _ in 0..400 {
+            simulation.separate();
+        }
+
+        // Packed discs of radius 1 fill a disc of radius about sqrt(n)
+        let center = simulation.bodies.iter().fold(Vec2::zero(), |sum, body| sum + body.pos) / n as f32;
+        let radii: Vec<f32> = simulation.bodies.iter().map(|body| (body.pos - center).mag()).collect();
+        let radius = radii.iter().fold(0.0f32, |max, &r| max.max(r));
+        assert!(radius < 2.0 * (n as f32).sqrt(), "blob radius {}", radius);
+
+        // A uniform disc has a quarter of its bodies within half its radius; a ring would have none
+        let inner = radii.iter().filter(|&&r| r < radius * 0.5).count() as f32 / n as f32;
+        assert!((0.15..0.4).contains(&inner), "{} of the bodies within half the radius", inner);
+
+        for (i, a) in simulation.bodies.iter().enumerate() {
+            for b in &simulation.bodies[..i] {
+                assert!((a.pos - b.pos).mag() > 1.0, "bodies still stacked at {:?}", a.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_simulation_takes_a_body() {
+        let mut simulation = Simulation::headless(Vec::new(), Vec::new());
+        for _ in 0..10 {
+            simulation.step();
+        }
+
+        simulation.bodies.push(Body::new(Vec2::new(10.0, 5.0), 1.0));
+        simulation.bodies.push(Body::new(Vec2::new(-10.0, 5.0), 1.0));
+        for _ in 0..10 {
+            simulation.step();
+        }
+        assert_eq!(simulation.nan_events, 0);
+        assert!(simulation.bodies.iter().all(|body| body.pos.x.is_finite() && body.pos.y.is_finite()));
+    }
+
+    #[test]
+    fn zero_softening_is_clamped() {
+        let (bodies, plates) = utils::ohms_law_circuit(4, 1.0, 0.5);
+        let mut simulation = Simulation::headless(bodies, plates);
+        let mut parameters = simulation.parameters();
+        parameters.epsilon = 0.0;
+        parameters.interior_epsilon = 0.0;
+        simulation.set_parameters(parameters);
+        assert_eq!(simulation.interior_epsilon, MIN_EPSILON);
+        assert_eq!(simulation.parameters().epsilon, MIN_EPSILON);
+
+        for _ in 0..20 {
+            simulation.step();
+        }
+        assert_eq!(simulation.nan_events, 0);
+    }
+
+    // Mean drift along the resistor of ohms_law_circuit with the scattering model, once the circuit has settled
+    fn scattering_drift(battery_strength: f32) -> f32 {
+        let (bodies, plates) = utils::ohms_law_circuit(4, battery_strength, 0.5);
+        let mut simulation = Simulation::headless(bodies, plates);
+        simulation.resist_model = ResistModel::Scatter;
+        for _ in 0..200 {
+            simulation.step();
+        }
+
+        let mut drift = 0.0;
+        for _ in 0..400 {
+            simulation.step();
+            drift += simulation.measurements.drift[2].x / 400.0;
+        }
+        return drift.abs();
+    }
+
+    #[test]
+    fn scattering_drift_follows_ohms_law() {
+        // Past a battery strength of about 1 the wires start to limit the current
+        let strengths = [0.25, 0.5, 1.0];
+        let ratios: Vec<f32> = strengths.iter().map(|&strength| scattering_drift(strength) / strength).collect();
+        let mean = ratios.iter().sum::<f32>() / ratios.len() as f32;
+
+        assert!(mean > 0.0, "no drift through the resistor");
+        for (strength, ratio) in strengths.iter().zip(&ratios) {
+            assert!((ratio - mean).abs() <= 0.2 * mean, "battery {}: drift / battery = {:.3}, mean {:.3}", strength, ratio, mean);
+        }
+    }
+
+    // Two batteries pushing along +x that meet at x = 40
+    fn seam_plates() -> Vec<Plate> {
+        let mut plates = vec![
+            Plate::new(Vec2::new(0.0, 0.0), Vec2::new(40.0, 10.0)),
+            Plate::new(Vec2::new(40.0, 0.0), Vec2::new(80.0, 10.0)),
+        ];
+        for plate in &mut plates {
+            plate.make_battery(1.0, 0.0);
+        }
+        return plates;
+    }
+
+    #[test]
+    fn seam_belongs_to_exactly_one_plate() {
+        let plates = seam_plates();
+        let index = PlateIndex::new(&plates, PLATE_INDEX_CELL_SIZE);
+
+        let mut old_pos = Vec2::new(35.0, 5.0);
+        for i in 1..=20 {
+            let pos = Vec2::new(35.0 + i as f32 * 0.5, 5.0);
+            assert_eq!(plates.iter().filter(|plate| plate.contains_point(pos)).count(), 1, "at {:?}", pos);
+            assert_eq!(index.index_at(&plates, pos), Some(if pos.x < 40.0 { 0 } else { 1 }));
+
+            // Nothing stops a step across the seam
+            assert_eq!(clip_pos(old_pos, pos, &plates, &index), pos);
+            old_pos = pos;
+        }
+    }
+
+    #[test]
+    fn body_walks_across_a_seam() {
+        let mut simulation = Simulation::headless(vec![Body::new(Vec2::new(38.0, 5.0), 1.0)], seam_plates());
+        for _ in 0..200 {
+            simulation.step();
+            if simulation.bodies[0].pos.x > 45.0 {
+                break;
+            }
+        }
+
+        let pos = simulation.bodies[0].pos;
+        assert!(pos.x > 45.0, "body stuck at {:?}", pos);
+        assert!(simulation.plates[1].contains_point(pos));
+    }
+
+    // 500 steps of a circuit built through edits, with thermal noise and scattering drawing on the seed
+    fn seeded_run(seed: u64) -> Vec<(Vec2, Vec2)> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut simulation = Simulation::with_edits(receiver);
+        let (bodies, plates) = utils::ohms_law_circuit(4, 1.0, 0.5);
+        sender.send(EditCommand::Reseed(seed)).unwrap();
+        sender.send(EditCommand::SetParameters(Parameters { temperature: 1.0, resist_model: ResistModel::Scatter, ..simulation.parameters() })).unwrap();
+        sender.send(EditCommand::ReplaceScene { bodies, plates }).unwrap();
+
+        for _ in 0..500 {
+            simulation.step();
+        }
+        return simulation.bodies.iter().map(|body| (body.pos, body.vel)).collect();
+    }
+
+    #[test]
+    fn same_seed_and_edits_give_identical_runs() {
+        let run = seeded_run(42);
+        assert!(run == seeded_run(42));
+        assert!(run != seeded_run(43), "the seed made no difference");
     }
 }
@@ -0,0 +1,275 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use crate::simulation::EditCommand;
+
+// Plate changes this close together undo as one, so dragging a slider is a single step
+const MERGE_TIME: Duration = Duration::from_millis(500);
+
+// One user action: the edits that reverse it, and the edits that make it again
+struct UndoEntry {
+    undo: Vec<EditCommand>,
+    redo: Vec<EditCommand>,
+    // When the first and the latest edits were recorded
+    start: Instant,
+    end: Instant,
+}
+
+impl UndoEntry {
+    fn new(now: Instant) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            start: now,
+            end: now,
+        }
+    }
+
+    // Ids of the plates, if every edit just changes plate settings
+    fn plate_changes(&self) -> Option<Vec<u32>> {
+        let mut ids = Vec::new();
+        for edit in &self.redo {
+            match edit {
+                EditCommand::SetPlate(plate) => ids.push(plate.id),
+                _ => return None,
+            }
+        }
+
+        ids.sort_unstable();
+        ids.dedup();
+        return Some(ids);
+    }
+}
+
+// Undo and redo history of scene edits, as edits that reverse them
+pub struct UndoStack {
+    undo: VecDeque<UndoEntry>,
+    redo: Vec<UndoEntry>,
+    // Edits recorded since the last finish_action, which undo together
+    action: UndoEntry,
+    // Most actions kept; the oldest are dropped past this
+    pub depth: usize,
+    // Where edit times come from
+    clock: fn() -> Instant,
+}
+
+impl UndoStack {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            action: UndoEntry::new(Instant::now()),
+            depth,
+            clock: Instant::now,
+        }
+    }
+
+    // Add an edit to the current action, along with the edits that reverse it
+    pub fn record(&mut self, edit: EditCommand, undo: Vec<EditCommand>) {
+        let now = (self.clock)();
+        if self.action.redo.is_empty() {
+            self.action.start = now;
+        }
+        self.action.end = now;
+
+        // Later edits are reversed first
+        self.action.undo.splice(0..0, undo);
+        self.action.redo.push(edit);
+    }
+
+    // Close the current action, merging it into the last one if both only change the same plates
+    pub fn finish_action(&mut self) {
+        if self.action.redo.is_empty() {
+            return;
+        }
+
+        let mut action = std::mem::replace(&mut self.action, UndoEntry::new((self.clock)()));
+        self.redo.clear();
+
+        if let Some(last) = self.undo.back_mut() {
+            let same_plates = action.plate_changes().is_some() && action.plate_changes() == last.plate_changes();
+            if same_plates && action.start.duration_since(last.end) < MERGE_TIME {
+                // Keep undoing to how the plates were before the first change
+                last.redo = std::mem::take(&mut action.redo);
+                last.end = action.end;
+                return;
+            }
+        }
+
+        self.undo.push_back(action);
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    // Edits that reverse the last action, which then becomes redoable
+    pub fn undo(&mut self) -> Option<Vec<EditCommand>> {
+        let entry = self.undo.pop_back()?;
+        let edits = entry.undo.clone();
+        self.redo.push(entry);
+        return Some(edits);
+    }
+
+    // Edits that make the last undone action again
+    pub fn redo(&mut self) -> Option<Vec<EditCommand>> {
+        let entry = self.redo.pop()?;
+        let edits = entry.redo.clone();
+        self.undo.push_back(entry);
+        return Some(edits);
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.action = UndoEntry::new((self.clock)());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.undo.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.undo.is_empty();
+    }
+
+    pub fn redo_len(&self) -> usize {
+        return self.redo.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plate::Plate;
+    use std::cell::Cell;
+    use ultraviolet::Vec2;
+
+    thread_local! {
+        static NOW: Cell<Instant> = Cell::new(Instant::now());
+    }
+
+    fn clock() -> Instant {
+        return NOW.with(|now| now.get());
+    }
+
+    fn wait(millis: u64) {
+        NOW.with(|now| now.set(now.get() + Duration::from_millis(millis)));
+    }
+
+    // A stack whose time only moves with wait
+    fn stack(depth: usize) -> UndoStack {
+        let mut stack = UndoStack::new(depth);
+        stack.clock = clock;
+        return stack;
+    }
+
+    fn seeds(edits: &[EditCommand]) -> Vec<u64> {
+        return edits.iter().map(|edit| match edit {
+            EditCommand::Reseed(seed) => *seed,
+            _ => panic!("expected a reseed"),
+        }).collect();
+    }
+
+    // A settings change to the plate, undone by setting it back
+    fn change_plate(stack: &mut UndoStack, plate: &mut Plate, resist: f32) {
+        let old = plate.clone();
+        plate.resist = resist;
+        stack.record(EditCommand::SetPlate(plate.clone()), vec![EditCommand::SetPlate(old)]);
+        stack.finish_action();
+    }
+
+    fn resist(edits: &[EditCommand]) -> f32 {
+        return match edits {
+            [EditCommand::SetPlate(plate)] => plate.resist,
+            _ => panic!("expected a single plate change"),
+        };
+    }
+
+    #[test]
+    fn undo_and_redo_an_action() {
+        let mut stack = stack(10);
+        assert!(stack.undo().is_none());
+
+        // Both edits undo together, the later one first
+        stack.record(EditCommand::Reseed(1), vec![EditCommand::Reseed(10)]);
+        stack.record(EditCommand::Reseed(2), vec![EditCommand::Reseed(20)]);
+        stack.finish_action();
+        assert_eq!(stack.len(), 1);
+
+        assert_eq!(seeds(&stack.undo().unwrap()), vec![20, 10]);
+        assert!(stack.is_empty());
+        assert_eq!(stack.redo_len(), 1);
+
+        assert_eq!(seeds(&stack.redo().unwrap()), vec![1, 2]);
+        assert_eq!(stack.len(), 1);
+        assert!(stack.redo().is_none());
+    }
+
+    #[test]
+    fn a_new_action_clears_redo() {
+        let mut stack = stack(10);
+        stack.record(EditCommand::Reseed(1), vec![EditCommand::Reseed(0)]);
+        stack.finish_action();
+        stack.undo();
+
+        stack.record(EditCommand::Reseed(2), vec![EditCommand::Reseed(0)]);
+        stack.finish_action();
+        assert_eq!(stack.redo_len(), 0);
+
+        // Finishing with nothing recorded adds nothing
+        stack.finish_action();
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn oldest_actions_are_dropped_past_the_depth() {
+        let mut stack = stack(3);
+        for seed in 0..5 {
+            stack.record(EditCommand::Reseed(seed), vec![EditCommand::Reseed(100 + seed)]);
+            stack.finish_action();
+        }
+
+        assert_eq!(stack.len(), 3);
+        let undone: Vec<u64> = std::iter::from_fn(|| stack.undo()).flat_map(|edits| seeds(&edits)).collect();
+        assert_eq!(undone, vec![104, 103, 102]);
+    }
+
+    #[test]
+    fn quick_plate_changes_merge() {
+        let mut stack = stack(10);
+        let mut plate = Plate::new(Vec2::zero(), Vec2::one());
+        let original = plate.resist;
+
+        // A slider drag: each change comes soon after the one before, however long the drag takes
+        for i in 1..=5 {
+            change_plate(&mut stack, &mut plate, original + i as f32);
+            wait(MERGE_TIME.as_millis() as u64 / 2);
+        }
+
+        assert_eq!(stack.len(), 1);
+        assert_eq!(resist(&stack.undo().unwrap()), original);
+        assert_eq!(resist(&stack.redo().unwrap()), original + 5.0);
+    }
+
+    #[test]
+    fn slow_or_different_plate_changes_stay_apart() {
+        let mut stack = stack(10);
+        let mut plate = Plate::new(Vec2::zero(), Vec2::one());
+        let mut other = Plate::new(Vec2::new(2.0, 0.0), Vec2::new(3.0, 1.0));
+
+        change_plate(&mut stack, &mut plate, 1.0);
+        wait(MERGE_TIME.as_millis() as u64 * 2);
+        change_plate(&mut stack, &mut plate, 2.0);
+        assert_eq!(stack.len(), 2);
+
+        // Changes to another plate don't join in, however soon they come
+        change_plate(&mut stack, &mut other, 3.0);
+        assert_eq!(stack.len(), 3);
+
+        // A change after a pause doesn't merge, even when the action before it was finished just now
+        change_plate(&mut stack, &mut other, 4.0);
+        assert_eq!(stack.len(), 3);
+        wait(MERGE_TIME.as_millis() as u64 * 2);
+        change_plate(&mut stack, &mut other, 5.0);
+        assert_eq!(stack.len(), 4);
+    }
+}
@@ -6,7 +6,7 @@ use crate::{
 use ultraviolet::Vec2;
 
 pub fn uniform_disc(n: usize) -> Vec<Body> {
-    fastrand::seed(0);
+    let mut rng = fastrand::Rng::with_seed(0);
     let inner_radius = 25.0;
     let outer_radius = (n as f32).sqrt() * 5.0;
 
@@ -17,10 +17,10 @@ pub fn uniform_disc(n: usize) -> Vec<Body> {
     // bodies.push(center);
 
     while bodies.len() < n {
-        let a = fastrand::f32() * std::f32::consts::TAU;
+        let a = rng.f32() * std::f32::consts::TAU;
         let (sin, cos) = a.sin_cos();
         let t = inner_radius / outer_radius;
-        let r = fastrand::f32() * (1.0 - t * t) + t * t;
+        let r = rng.f32() * (1.0 - t * t) + t * t;
         let pos = Vec2::new(cos, sin) * outer_radius * r.sqrt();
         let mass = 1.0f32;
         let radius = mass.cbrt();
@@ -41,18 +41,48 @@ pub fn uniform_disc(n: usize) -> Vec<Body> {
 }
 
 pub fn uniform_rect(n: usize, min: Vec2, max: Vec2, qe: f32) -> Vec<Body> {
-    fastrand::seed(0);
+    let mut rng = fastrand::Rng::with_seed(0);
     let mut bodies: Vec<Body> = Vec::with_capacity(n);
 
     for i in 0..n {
-        let x = min.x + (max.x - min.x) * fastrand::f32();
-        let y = min.y + (max.y - min.y) * fastrand::f32();
+        let x = min.x + (max.x - min.x) * rng.f32();
+        let y = min.y + (max.y - min.y) * rng.f32();
         bodies.push(Body::new(Vec2::new(x, y), 1.0));
     }
 
     bodies
 }
 
+pub fn uniform_rect_species(rng: &mut fastrand::Rng, n: usize, min: Vec2, max: Vec2, fill: BodyFill, ion_mass: f32) -> Vec<Body> {
+    let mut bodies: Vec<Body> = Vec::with_capacity(n * 2);
+
+    for _ in 0..n {
+        let pos = Vec2::new(random_in_range(rng, min.x, max.x), random_in_range(rng, min.y, max.y));
+
+        if fill != BodyFill::Ions {
+            bodies.push(Body::new(pos, 1.0));
+        }
+
+        // Holes are interleaved with the electrons
+        if fill == BodyFill::ElectronsAndHoles {
+            let pos = Vec2::new(random_in_range(rng, min.x, max.x), random_in_range(rng, min.y, max.y));
+            let mut hole = Body::new(pos, 1.0);
+            hole.make_hole();
+            bodies.push(hole);
+            continue;
+        }
+
+        if fill != BodyFill::Electrons {
+            let pos = Vec2::new(random_in_range(rng, min.x, max.x), random_in_range(rng, min.y, max.y));
+            let mut ion = Body::new(pos, 1.0);
+            ion.make_ion(ion_mass);
+            bodies.push(ion);
+        }
+    }
+
+    bodies
+}
+
 pub fn two_body() -> Vec<Body> {
     let n = 2;
     let mut bodies: Vec<Body> = Vec::with_capacity(n);
@@ -90,6 +120,147 @@ pub fn large_plate(n: usize, min: Vec2, max: Vec2) -> (Vec<Body>, Vec<Plate>) {
     return (bodies, plates);
 }
 
-pub fn random_in_range(min: f32, max: f32) -> f32 {
-    fastrand::f32() * (max - min) + min
+// Closed wire loop driven by an AC battery with a diode in the top wire
+pub fn half_wave_rectifier(density: usize) -> (Vec<Body>, Vec<Plate>) {
+    let mut plates: Vec<Plate> = Vec::with_capacity(6);
+
+    let mut battery = Plate::new(Vec2::new(-100.0, -50.0), Vec2::new(100.0, -30.0));
+    battery.make_ac_battery(2.0, 0.0, 0.005, 0.0);
+    plates.push(battery);
+
+    plates.push(Plate::new(Vec2::new(80.0, -30.0), Vec2::new(100.0, 30.0)));
+    plates.push(Plate::new(Vec2::new(20.0, 30.0), Vec2::new(100.0, 50.0)));
+
+    let mut diode = Plate::new(Vec2::new(-20.0, 30.0), Vec2::new(20.0, 50.0));
+    diode.make_diode(std::f32::consts::PI);
+    plates.push(diode);
+
+    plates.push(Plate::new(Vec2::new(-100.0, 30.0), Vec2::new(-20.0, 50.0)));
+    plates.push(Plate::new(Vec2::new(-100.0, -30.0), Vec2::new(-80.0, 30.0)));
+
+    let mut bodies: Vec<Body> = Vec::new();
+    for plate in &plates {
+        let n = plate.area() / 100.0 * density as f32;
+        bodies.extend(uniform_rect(n as usize, plate.min + Vec2::one(), plate.max - Vec2::one(), -1.0));
+    }
+
+    return (bodies, plates);
+}
+
+// Battery, two wires and a resistor in a loop; the resistor is plates[2]
+pub fn ohms_law_circuit(density: usize, battery_strength: f32, resist: f32) -> (Vec<Body>, Vec<Plate>) {
+    let mut plates: Vec<Plate> = Vec::with_capacity(4);
+
+    let mut battery = Plate::new(Vec2::new(-100.0, -50.0), Vec2::new(100.0, -30.0));
+    battery.make_battery(battery_strength, 0.0);
+    plates.push(battery);
+
+    plates.push(Plate::new(Vec2::new(80.0, -30.0), Vec2::new(100.0, 30.0)));
+
+    let mut resistor = Plate::new(Vec2::new(-100.0, 30.0), Vec2::new(100.0, 50.0));
+    resistor.make_resistor(resist, 0.0);
+    plates.push(resistor);
+
+    plates.push(Plate::new(Vec2::new(-100.0, -30.0), Vec2::new(-80.0, 30.0)));
+
+    let mut bodies: Vec<Body> = Vec::new();
+    for plate in &plates {
+        let n = plate.area() / 100.0 * density as f32;
+        bodies.extend(uniform_rect(n as usize, plate.min + Vec2::one(), plate.max - Vec2::one(), -1.0));
+    }
+
+    return (bodies, plates);
+}
+
+// Two identical battery loops whose resistors only differ in temperature coefficient
+pub fn resistor_alpha_demo(density: usize) -> (Vec<Body>, Vec<Plate>) {
+    let mut plates: Vec<Plate> = Vec::with_capacity(10);
+
+    for (offset, alpha) in [(-120.0, 0.0), (120.0, 0.5)] {
+        let shift = Vec2::new(offset, 0.0);
+
+        let mut battery = Plate::new(Vec2::new(-100.0, -50.0) + shift, Vec2::new(100.0, -30.0) + shift);
+        battery.make_battery(1.0, 0.0);
+        plates.push(battery);
+
+        plates.push(Plate::new(Vec2::new(80.0, -30.0) + shift, Vec2::new(100.0, 30.0) + shift));
+
+        let mut resistor = Plate::new(Vec2::new(-100.0, 30.0) + shift, Vec2::new(100.0, 50.0) + shift);
+        resistor.make_resistor(0.5, alpha);
+        plates.push(resistor);
+
+        plates.push(Plate::new(Vec2::new(-100.0, -30.0) + shift, Vec2::new(-80.0, 30.0) + shift));
+    }
+
+    let mut bodies: Vec<Body> = Vec::new();
+    for plate in &plates {
+        let n = plate.area() / 100.0 * density as f32;
+        bodies.extend(uniform_rect(n as usize, plate.min + Vec2::one(), plate.max - Vec2::one(), -1.0));
+    }
+
+    return (bodies, plates);
+}
+
+// Two oppositely charged plate pairs, the right one with a dielectric slab in the gap
+pub fn dielectric_capacitor(density: usize) -> (Vec<Body>, Vec<Plate>) {
+    let mut plates: Vec<Plate> = Vec::with_capacity(5);
+    let mut bodies: Vec<Body> = Vec::new();
+
+    for (offset, epsilon_r) in [(-100.0, None), (100.0, Some(4.0))] {
+        let shift = Vec2::new(offset, 0.0);
+
+        let mut positive = Plate::new(Vec2::new(-60.0, 30.0) + shift, Vec2::new(60.0, 40.0) + shift);
+        positive.charge_scale = 5.0;
+        plates.push(positive);
+
+        let mut negative = Plate::new(Vec2::new(-60.0, -40.0) + shift, Vec2::new(60.0, -30.0) + shift);
+        negative.charge_scale = -5.0;
+        plates.push(negative);
+
+        let gap_min = Vec2::new(-60.0, -30.0) + shift;
+        let gap_max = Vec2::new(60.0, 30.0) + shift;
+        if let Some(epsilon_r) = epsilon_r {
+            let mut dielectric = Plate::new(gap_min, gap_max);
+            dielectric.make_dielectric(epsilon_r);
+            plates.push(dielectric);
+        }
+
+        // Free test charges drift across the gap, slower inside the dielectric
+        bodies.extend(uniform_rect(density, gap_min + Vec2::one(), gap_max - Vec2::one(), -1.0));
+    }
+
+    return (bodies, plates);
+}
+
+// Battery loop filled with electrons and holes, which drift in opposite directions
+pub fn ambipolar_drift(density: usize) -> (Vec<Body>, Vec<Plate>) {
+    let (_, plates) = ohms_law_circuit(0, 1.0, 1.0);
+
+    let mut rng = fastrand::Rng::with_seed(0);
+    let mut bodies: Vec<Body> = Vec::new();
+    for plate in &plates {
+        let n = plate.area() / 100.0 * density as f32;
+        bodies.extend(uniform_rect_species(&mut rng, n as usize, plate.min + Vec2::one(), plate.max - Vec2::one(), BodyFill::ElectronsAndHoles, 1.0));
+    }
+
+    return (bodies, plates);
+}
+
+pub fn random_in_range(rng: &mut fastrand::Rng, min: f32, max: f32) -> f32 {
+    rng.f32() * (max - min) + min
+}
+
+// Standard normal sample (Box-Muller)
+pub fn random_gaussian(rng: &mut fastrand::Rng) -> f32 {
+    let u1 = 1.0 - rng.f32();
+    let u2 = rng.f32();
+    (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BodyFill {
+    Electrons,
+    Ions,
+    Neutral,
+    ElectronsAndHoles,
 }
@@ -0,0 +1,44 @@
+#![cfg(feature = "gpu")]
+
+use efield_sim::{gpu::GpuField, quadtree::{Quad, Quadtree}, utils};
+
+// Largest error of a body's field on the GPU, relative to the CPU's field there or the scene's mean field, whichever is
+// bigger, so bodies where the field nearly cancels don't count against it
+const TOLERANCE: f32 = 1e-3;
+
+#[test]
+fn gpu_field_matches_cpu() {
+    // Skip on machines without a usable adapter, like most CI runners
+    let Some(gpu) = GpuField::new() else {
+        eprintln!("No GPU adapter with compute shaders, skipping");
+        return;
+    };
+
+    let mut bodies = utils::uniform_disc(5000);
+    let mut rng = fastrand::Rng::with_seed(0);
+    for body in &mut bodies {
+        body.charge = if rng.bool() { 1.0 } else { -1.0 };
+    }
+
+    let mut quadtree = Quadtree::new(0.75, 1.0);
+    quadtree.dipole = true;
+    quadtree.clear(Quad::new_containing(&bodies));
+    for (i, body) in bodies.iter().enumerate() {
+        quadtree.insert(body.pos, body.charge, i);
+    }
+    quadtree.propagate();
+
+    let queries: Vec<_> = bodies.iter().map(|body| (body.pos, quadtree.e_sq)).collect();
+    let gpu_efields = gpu.efield(&quadtree, &queries).expect("GPU field evaluation failed");
+    let cpu_efields: Vec<_> = queries.iter().map(|&(pos, e_sq)| quadtree.efield_with(pos, e_sq)).collect();
+    assert_eq!(gpu_efields.len(), cpu_efields.len());
+
+    let mean_efield = cpu_efields.iter().map(|efield| efield.mag()).sum::<f32>() / cpu_efields.len() as f32;
+    let mut max_error = 0.0f32;
+    for (gpu_efield, cpu_efield) in gpu_efields.iter().zip(&cpu_efields) {
+        let error = (*gpu_efield - *cpu_efield).mag() / cpu_efield.mag().max(mean_efield);
+        max_error = max_error.max(error);
+    }
+
+    assert!(max_error <= TOLERANCE, "{}: max error {:.2e}", gpu.adapter_name, max_error);
+}
@@ -0,0 +1,73 @@
+use efield_sim::{body::Body, plate::Plate, simulation::{EditCommand, Simulation}, utils};
+use ultraviolet::Vec2;
+
+const STEPS: usize = 100;
+
+fn positions(simulation: &Simulation) -> Vec<Vec2> {
+    return simulation.bodies.iter().map(|body| body.pos).collect();
+}
+
+// A closed circuit has nowhere for bodies to go, so stepping it keeps every body, finite and inside a plate
+#[test]
+fn circuit_keeps_its_bodies() {
+    let (bodies, plates) = utils::ohms_law_circuit(4, 1.0, 0.5);
+    let count = bodies.len();
+    let charge: f32 = bodies.iter().map(|body| body.charge).sum();
+    let mut simulation = Simulation::headless(bodies, plates);
+
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+
+    assert_eq!(simulation.bodies.len(), count);
+    assert_eq!(simulation.nan_events, 0);
+    let new_charge: f32 = simulation.bodies.iter().map(|body| body.charge).sum();
+    assert!((new_charge - charge).abs() < 1e-3, "charge went from {} to {}", charge, new_charge);
+
+    for body in &simulation.bodies {
+        assert!(body.pos.x.is_finite() && body.pos.y.is_finite());
+        assert!(simulation.plates.iter().any(|plate| plate.contains_point(body.pos)), "body escaped to {:?}", body.pos);
+    }
+}
+
+// Edits sent the way the renderer sends them are applied on the next step and show up in the snapshot
+#[test]
+fn edits_build_a_scene() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut simulation = Simulation::with_edits(receiver);
+
+    let plate = Plate::new(Vec2::new(-50.0, -10.0), Vec2::new(50.0, 10.0));
+    let bodies = utils::uniform_rect(200, plate.min + Vec2::one(), plate.max - Vec2::one(), -1.0);
+    sender.send(EditCommand::AddPlate { plate, bodies }).unwrap();
+    sender.send(EditCommand::AddBodies(vec![Body::new(Vec2::new(0.0, 40.0), 1.0)])).unwrap();
+    simulation.step();
+
+    let snapshot = simulation.snapshot();
+    assert_eq!(snapshot.edits_applied, 2);
+    assert_eq!(snapshot.bodies.len(), 201);
+    assert_eq!(snapshot.plates.map(|plates| plates.len()), Some(1));
+
+    // Every body got an id of its own
+    let mut ids: Vec<u64> = snapshot.bodies.iter().map(|body| body.id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 201);
+    assert!(!ids.contains(&0));
+}
+
+// The same scene, seed and steps give the same run, thermal noise included
+#[test]
+fn same_seed_gives_the_same_run() {
+    let run = |seed| {
+        let (bodies, plates) = utils::ohms_law_circuit(4, 1.0, 0.5);
+        let mut simulation = Simulation::with_parameters(bodies, plates, 1.0, 0.56, 4.5e-2, 1.0);
+        simulation.reseed(seed);
+        for _ in 0..STEPS {
+            simulation.step();
+        }
+        return positions(&simulation);
+    };
+
+    assert!(run(7) == run(7));
+    assert!(run(7) != run(8), "the seed made no difference");
+}
@@ -0,0 +1,46 @@
+use efield_sim::{simulation::Simulation, utils};
+
+// Steps to let the circuit settle, then steps to average the resistor's current over
+const SETTLE_STEPS: usize = 200;
+const AVERAGE_STEPS: usize = 400;
+
+// Largest spread of current / (battery strength * resist) around its mean over the sweep
+const TOLERANCE: f32 = 0.25;
+
+// Bodies per step crossing the resistor once the circuit has settled, averaged since the smoothed current is noisy
+fn resistor_current(battery_strength: f32, resist: f32) -> f32 {
+    let (bodies, plates) = utils::ohms_law_circuit(4, battery_strength, resist);
+    let mut simulation = Simulation::headless(bodies, plates);
+
+    for _ in 0..SETTLE_STEPS {
+        simulation.step();
+    }
+
+    let mut crossings = 0.0;
+    for _ in 0..AVERAGE_STEPS {
+        simulation.step();
+        crossings += simulation.measurements.crossings[2];
+    }
+
+    return (crossings / AVERAGE_STEPS as f32).abs();
+}
+
+// A resistor's resist is the chance for a body to move, so it acts as a conductance and the current should go as
+// battery strength * resist. Past these values the wires start to limit the current
+#[test]
+fn current_scales_with_battery_strength_and_resist() {
+    let sweep = [(0.5, 0.25), (1.0, 0.25), (0.5, 0.5), (1.0, 0.5)];
+    let ratios: Vec<f32> = sweep.iter().map(|&(battery_strength, resist)| {
+        resistor_current(battery_strength, resist) / (battery_strength * resist)
+    }).collect();
+    let mean = ratios.iter().sum::<f32>() / ratios.len() as f32;
+
+    assert!(mean > 0.0, "no current through the resistor");
+    for (&(battery_strength, resist), &ratio) in sweep.iter().zip(&ratios) {
+        assert!(
+            (ratio - mean).abs() <= TOLERANCE * mean,
+            "battery {} resist {}: current / (battery * resist) = {:.3}, mean {:.3}",
+            battery_strength, resist, ratio, mean,
+        );
+    }
+}